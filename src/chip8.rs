@@ -1,27 +1,77 @@
-use self::output::Chip8Output;
-
+pub mod asm;
+pub mod cfg;
+mod audio;
+pub mod coverage;
 pub mod cpu;
+pub mod dap;
+pub mod debugger;
+pub mod disasm;
+pub mod error;
+pub mod frontend;
+pub mod gdb;
 mod input;
 mod memory;
+pub mod movie;
 mod output;
+pub mod profile;
+pub mod quirks;
+pub mod random;
 mod registers;
+pub mod render;
+pub mod rewind;
+pub mod rpl;
+pub mod savestate;
+pub mod scheduler;
 mod timers;
+pub mod timing;
+pub mod trace;
 mod sprites;
+mod stack;
+pub mod synth;
 
-use cpu::*;
+use audio::*;
+use error::*;
+use frontend::*;
 use input::*;
 use memory::*;
 use output::*;
+use quirks::*;
+use random::*;
 use registers::*;
+use rpl::*;
+use scheduler::*;
+use stack::*;
 use timers::*;
+use timing::*;
+use trace::*;
 
 #[derive(Debug)]
 pub struct Chip8 {
     pub memory: Chip8Memory,
     pub registers: Chip8Registers,
+    pub stack: Chip8Stack,
     pub timers: Chip8Timers,
     pub output: Chip8Output,
     pub input: Chip8Input,
+    pub audio: Chip8Audio,
+    pub fault_policies: Chip8FaultPolicies,
+    pub quirks: Quirks,
+    pub random: Chip8Random,
+    pub rpl_storage: Box<dyn RplStorage>,
+    pub video_sink: Option<Box<dyn VideoSink>>, // see run_frame
+    pub audio_sink: Option<Box<dyn AudioSink>>,
+    pub input_source: Option<Box<dyn InputSource>>,
+    pub timing: TimingModel,
+    pub instructions_per_frame: usize, // see run_frame
+    tracer: Option<Box<Chip8Tracer>>, // see set_tracer
+    vblank: bool, // whether a vertical blank happened since the last draw, used by the display wait quirk
+    instruction_count: u64, // the number of instructions executed since power on (including ones waiting for a key or vblank)
+    frame_count: u64, // the number of vertical blanks since power on
+    frame_start_instruction: u64, // the instruction count at the last vertical blank, see get_frame_position
+    cycle_count: u64, // the number of COSMAC VIP machine cycles since power on, see get_cycle_count
+    frame_cycles: u64, // the number of cycles the interpreter has used since the last vertical blank interrupt
+    frame_time: std::time::Duration, // the time run_for has left over, less than a frame
+    buzzer: bool, // whether the audio sink was last told to turn the buzzer on
 }
 
 impl Chip8 {
@@ -30,14 +80,34 @@ impl Chip8 {
         Chip8 {
             memory: Chip8Memory::new(),
             registers: Chip8Registers::new(),
+            stack: Chip8Stack::new(),
             timers: Chip8Timers::new(),
             output: Chip8Output::new(),
             input: Chip8Input::new(),
+            audio: Chip8Audio::new(),
+            fault_policies: Chip8FaultPolicies::new(),
+            quirks: Quirks::default(),
+            random: Chip8Random::default(),
+            rpl_storage: Box::new(MemoryRplStorage::new()),
+            video_sink: None,
+            audio_sink: None,
+            input_source: None,
+            timing: TimingModel::Instructions,
+            instructions_per_frame: DEFAULT_INSTRUCTIONS_PER_FRAME,
+            tracer: None,
+            vblank: false,
+            instruction_count: 0,
+            frame_count: 0,
+            frame_start_instruction: 0,
+            cycle_count: 0,
+            frame_cycles: 0,
+            frame_time: std::time::Duration::ZERO,
+            buzzer: false,
         }
     }
 
     // new chip8 instance with pre-loaded program
-    pub fn new_with_program(program: &Vec<u16>) -> Chip8 {
+    pub fn new_with_program(program: &[u16]) -> Chip8 {
         let mut inst = Chip8::new();
 
         inst.memory.load_program_into_mem(program);
@@ -45,4 +115,40 @@ impl Chip8 {
 
         inst
     }
+
+    // gets the number of addressable bytes of memory, which depends on the extended memory quirk
+    pub fn get_address_space_size(&self) -> usize {
+        if self.quirks.extended_memory { self.memory.get_size() } else { 0x1000 }
+    }
+
+    // gets the return addresses on the call stack, oldest first
+    pub fn get_call_stack(&self) -> Vec<u16> {
+        self.stack.get_frames(&self.memory, self.quirks.memory_mapped_stack)
+    }
+
+    // gets the number of instructions executed since power on
+    pub fn get_instruction_count(&self) -> u64 {
+        self.instruction_count
+    }
+
+    // gets the number of frames (vertical blanks) since power on
+    pub fn get_frame_count(&self) -> u64 {
+        self.frame_count
+    }
+
+    // ticks the timers, which happens at the vertical blank (60 times a second)
+    pub fn timer_tick(&mut self) {
+        self.end_audio_frame();
+        self.timers.timer_tick();
+        self.vblank = true;
+        self.frame_count += 1;
+        self.frame_start_instruction = self.instruction_count;
+        self.update_buzzer();
+    }
+}
+
+impl Default for Chip8 {
+    fn default() -> Self {
+        Chip8::new()
+    }
 }
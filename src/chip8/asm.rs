@@ -0,0 +1,972 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fmt;
+use super::Chip8;
+use super::cpu::{ALUOperations, CPUInstrTarget, CPUInstruction};
+use super::disasm::PROGRAM_START;
+
+// the most macro expansions a program can do, so a macro that invokes itself fails instead of running forever
+const MAX_MACRO_EXPANSIONS: usize = 10_000;
+
+// an error in a program, at a 1-based line and column of the source
+#[derive(Debug, Clone, PartialEq)]
+pub struct AsmError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl std::error::Error for AsmError {}
+
+// an assembled program
+#[derive(Debug, Clone, PartialEq)]
+pub struct Assembled {
+    pub rom: Vec<u8>, // to be loaded at PROGRAM_START
+    pub labels: BTreeMap<String, usize>,
+    pub line_addresses: Vec<(usize, usize)>, // the source line and address of every instruction, in the order they were assembled
+}
+
+impl Assembled {
+    // gets the address of the first instruction assembled from a source line
+    pub fn get_line_address(&self, line: usize) -> Option<usize> {
+        self.line_addresses
+            .iter()
+            .find(|&&(source_line, _)| source_line == line)
+            .map(|&(_, addr)| addr)
+    }
+
+    // gets the source line an instruction was assembled from
+    pub fn get_address_line(&self, addr: usize) -> Option<usize> {
+        self.line_addresses
+            .iter()
+            .find(|&&(_, instruction_addr)| instruction_addr == addr)
+            .map(|&(line, _)| line)
+    }
+
+    // writes the symbol map: a `label NAME 0xADDR` line for every label, then a `line N 0xADDR` line for every instruction
+    pub fn symbol_map(&self) -> String {
+        let mut out = String::new();
+
+        for (name, addr) in &self.labels {
+            out.push_str(&format!("label {name} 0x{addr:03X}\n"));
+        }
+
+        for (line, addr) in &self.line_addresses {
+            out.push_str(&format!("line {line} 0x{addr:03X}\n"));
+        }
+
+        out
+    }
+
+    // reads a symbol map written by symbol_map back, for a program that was assembled earlier
+    pub fn from_symbol_map(rom: Vec<u8>, map: &str) -> Result<Assembled, AsmError> {
+        let mut labels = BTreeMap::new();
+        let mut line_addresses = Vec::new();
+
+        for (i, text) in map.lines().enumerate() {
+            let error = |message: &str| AsmError { line: i + 1, column: 1, message: message.to_string() };
+            let fields = text.split_whitespace().collect::<Vec<_>>();
+
+            let addr = |text: &str| usize::from_str_radix(text.trim_start_matches("0x"), 16).map_err(|_| error("invalid address"));
+
+            match fields[..] {
+                [] => {},
+                ["label", name, addr_text] => {
+                    labels.insert(name.to_string(), addr(addr_text)?);
+                },
+                ["line", line, addr_text] => {
+                    line_addresses.push((line.parse().map_err(|_| error("invalid line number"))?, addr(addr_text)?));
+                },
+                _ => return Err(error("expected `label NAME ADDR` or `line N ADDR`")),
+            }
+        }
+
+        Ok(Assembled { rom, labels, line_addresses })
+    }
+}
+
+// assembles a program written in Octo syntax
+pub fn assemble(source: &str) -> Result<Assembled, AsmError> {
+    let assembled = Assembler::new(source, false).run()?;
+
+    // execution starts at PROGRAM_START, so if main is somewhere else, assemble again with a jump to it in front
+    match assembled.labels.get("main") {
+        Some(&main) if main != PROGRAM_START => Assembler::new(source, true).run(),
+        _ => Ok(assembled),
+    }
+}
+
+// a piece of the source, split at whitespace
+#[derive(Debug, Clone, PartialEq)]
+struct Token {
+    text: String,
+    line: usize,
+    column: usize,
+}
+
+// splits the source into tokens, dropping # comments
+fn tokenize(source: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+
+    for (line_index, line) in source.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or_default();
+        let mut start = None;
+
+        for (column, char) in line.char_indices().chain([(line.len(), ' ')]) {
+            match (char.is_whitespace(), start) {
+                (false, None) => start = Some(column),
+                (true, Some(token_start)) => {
+                    tokens.push(Token {
+                        text: line[token_start..column].to_string(),
+                        line: line_index + 1,
+                        column: line[..token_start].chars().count() + 1,
+                    });
+                    start = None;
+                },
+                _ => {},
+            }
+        }
+    }
+
+    tokens
+}
+
+// parses a number literal: decimal, 0x hex or 0b binary, optionally negative
+fn parse_number(text: &str) -> Option<i64> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, text),
+    };
+
+    let val = if let Some(hex) = digits.strip_prefix("0x").or_else(|| digits.strip_prefix("0X")) {
+        i64::from_str_radix(hex, 16).ok()?
+    } else if let Some(binary) = digits.strip_prefix("0b").or_else(|| digits.strip_prefix("0B")) {
+        i64::from_str_radix(binary, 2).ok()?
+    } else if digits.starts_with(|char: char| char.is_ascii_digit()) {
+        digits.parse().ok()?
+    } else {
+        return None;
+    };
+
+    Some(if negative { -val } else { val })
+}
+
+// parses a register name (v0-vf)
+fn parse_register(text: &str) -> Option<usize> {
+    let digit = text.strip_prefix(['v', 'V'])?;
+
+    (digit.len() == 1).then(|| usize::from_str_radix(digit, 16).ok()).flatten()
+}
+
+// a macro's argument names and body
+#[derive(Debug, Clone)]
+struct Macro {
+    args: Vec<String>,
+    body: Vec<Token>,
+}
+
+// an open loop or if block
+#[derive(Debug)]
+enum Block {
+    Loop { start: usize, exits: Vec<usize>, token: Token }, // exits are the jumps out of the loop made by while
+    If { skip_jump: usize, token: Token }, // the jump over the body when the condition is false
+    Else { end_jump: usize, token: Token }, // the jump over the else body
+}
+
+// a label used before it was defined, patched in once everything is assembled
+#[derive(Debug)]
+struct Fixup {
+    offset: usize, // offset into the ROM of the opcode (or the address word after F000)
+    label: Token,
+    long: bool, // a whole 16-bit word instead of the low 12 bits of an opcode
+}
+
+struct Assembler {
+    tokens: VecDeque<Token>,
+    rom: Vec<u8>,
+    labels: BTreeMap<String, usize>,
+    constants: HashMap<String, f64>,
+    aliases: HashMap<String, usize>,
+    macros: HashMap<String, Macro>,
+    blocks: Vec<Block>,
+    fixups: Vec<Fixup>,
+    line_addresses: Vec<(usize, usize)>,
+    last_token: Token, // for errors at the end of the source
+    macro_expansions: usize,
+    jump_to_main: bool,
+}
+
+impl Assembler {
+    fn new(source: &str, jump_to_main: bool) -> Assembler {
+        Assembler {
+            tokens: tokenize(source).into(),
+            rom: Vec::new(),
+            labels: BTreeMap::new(),
+            constants: HashMap::new(),
+            aliases: HashMap::new(),
+            macros: HashMap::new(),
+            blocks: Vec::new(),
+            fixups: Vec::new(),
+            line_addresses: Vec::new(),
+            last_token: Token { text: String::new(), line: 1, column: 1 },
+            macro_expansions: 0,
+            jump_to_main,
+        }
+    }
+
+    // makes an error at a token
+    fn error<T>(token: &Token, message: impl Into<String>) -> Result<T, AsmError> {
+        Err(AsmError { line: token.line, column: token.column, message: message.into() })
+    }
+
+    // gets the address the next byte will be assembled at
+    fn here(&self) -> usize {
+        PROGRAM_START + self.rom.len()
+    }
+
+    // takes the next token
+    fn next_token(&mut self) -> Result<Token, AsmError> {
+        match self.tokens.pop_front() {
+            Some(token) => {
+                self.last_token = token.clone();
+                Ok(token)
+            },
+            None => Assembler::error(&self.last_token, "unexpected end of program"),
+        }
+    }
+
+    // takes the next token, which has to be a given one
+    fn expect(&mut self, text: &str) -> Result<Token, AsmError> {
+        let token = self.next_token()?;
+
+        if token.text != text {
+            return Assembler::error(&token, format!("expected `{text}` but found `{}`", token.text));
+        }
+
+        Ok(token)
+    }
+
+    // takes a name for something new, which can't clash with a register or a number
+    fn next_name(&mut self) -> Result<Token, AsmError> {
+        let token = self.next_token()?;
+
+        if parse_register(&token.text).is_some() || parse_number(&token.text).is_some() || token.text.starts_with(':') {
+            return Assembler::error(&token, format!("`{}` can't be used as a name", token.text));
+        }
+
+        Ok(token)
+    }
+
+    // takes a register (or an alias of one)
+    fn next_register(&mut self) -> Result<usize, AsmError> {
+        let token = self.next_token()?;
+
+        match self.register(&token) {
+            Some(reg) => Ok(reg),
+            None => Assembler::error(&token, format!("expected a register but found `{}`", token.text)),
+        }
+    }
+
+    // resolves a register (or an alias of one)
+    fn register(&self, token: &Token) -> Option<usize> {
+        parse_register(&token.text).or_else(|| self.aliases.get(&token.text).copied())
+    }
+
+    // resolves a number, constant or defined label
+    fn value(&self, token: &Token) -> Option<f64> {
+        parse_number(&token.text)
+            .map(|val| val as f64)
+            .or_else(|| self.constants.get(&token.text).copied())
+            .or_else(|| self.labels.get(&token.text).map(|&addr| addr as f64))
+    }
+
+    // takes a number that has to fit in a range
+    fn next_number(&mut self, min: i64, max: i64) -> Result<u16, AsmError> {
+        let token = self.next_token()?;
+
+        let Some(val) = self.value(&token) else {
+            return Assembler::error(&token, format!("expected a number but found `{}`", token.text));
+        };
+
+        let val = val as i64;
+        if val < min || val > max {
+            return Assembler::error(&token, format!("{val} is out of range ({min} to {max})"));
+        }
+
+        Ok(val as u16)
+    }
+
+    // takes a byte value, negative numbers are stored in two's complement
+    fn next_byte(&mut self) -> Result<u16, AsmError> {
+        Ok(self.next_number(-128, 255)? & 0xFF)
+    }
+
+    // assembles an instruction
+    fn emit(&mut self, instruction: CPUInstruction, token: &Token) -> Result<(), AsmError> {
+        let Some(opcode) = Chip8::instruction_to_opcode(&instruction) else {
+            return Assembler::error(token, "this instruction can't be encoded");
+        };
+
+        self.line_addresses.push((token.line, self.here()));
+        self.rom.extend_from_slice(&opcode.to_be_bytes());
+
+        Ok(())
+    }
+
+    // assembles an instruction that takes an address, which can be a label that is defined later on
+    fn emit_with_address(&mut self, make: fn(u16) -> CPUInstruction, token: &Token) -> Result<(), AsmError> {
+        let target = self.next_token()?;
+
+        let addr = match self.value(&target) {
+            Some(addr) if (0.0..0x1000 as f64).contains(&addr) => addr as u16,
+            Some(addr) => return Assembler::error(&target, format!("address {addr} is out of range")),
+            None => {
+                self.fixups.push(Fixup { offset: self.rom.len(), label: target, long: false });
+                0
+            },
+        };
+
+        self.emit(make(addr), token)
+    }
+
+    // overwrites the address of an already assembled jump with the current address
+    fn patch_jump(&mut self, offset: usize) {
+        let here = self.here() as u16;
+        let opcode = u16::from_be_bytes([self.rom[offset], self.rom[offset + 1]]);
+
+        self.rom[offset..offset + 2].copy_from_slice(&((opcode & 0xF000) | (here & 0x0FFF)).to_be_bytes());
+    }
+
+    // assembles the whole program
+    fn run(mut self) -> Result<Assembled, AsmError> {
+        if self.jump_to_main {
+            let token = Token { text: String::from("main"), line: 0, column: 0 };
+            self.fixups.push(Fixup { offset: 0, label: token.clone(), long: false });
+            self.rom.extend_from_slice(&0x1000u16.to_be_bytes());
+        }
+
+        while !self.tokens.is_empty() {
+            let token = self.next_token()?;
+            self.statement(token)?;
+        }
+
+        if let Some(block) = self.blocks.last() {
+            let (Block::Loop { token, .. } | Block::If { token, .. } | Block::Else { token, .. }) = block;
+            return Assembler::error(token, format!("`{}` is never closed", token.text));
+        }
+
+        for fixup in &self.fixups {
+            let Some(&addr) = self.labels.get(&fixup.label.text) else {
+                return Assembler::error(&fixup.label, format!("undefined label `{}`", fixup.label.text));
+            };
+
+            let bytes = &mut self.rom[fixup.offset..fixup.offset + 2];
+
+            if fixup.long {
+                bytes.copy_from_slice(&(addr as u16).to_be_bytes());
+            } else if addr < 0x1000 {
+                let opcode = u16::from_be_bytes([bytes[0], bytes[1]]);
+                bytes.copy_from_slice(&((opcode & 0xF000) | addr as u16).to_be_bytes());
+            } else {
+                return Assembler::error(&fixup.label, format!("`{}` is beyond 0xFFF, use `i := long`", fixup.label.text));
+            }
+        }
+
+        // keep the line map unaffected by the jump to main
+        self.line_addresses.retain(|&(line, _)| line > 0);
+
+        Ok(Assembled {
+            rom: self.rom,
+            labels: self.labels,
+            line_addresses: self.line_addresses,
+        })
+    }
+
+    // assembles a single statement starting with a token
+    fn statement(&mut self, token: Token) -> Result<(), AsmError> {
+        use CPUInstrTarget::*;
+
+        match token.text.as_str() {
+            // label definition
+            ":" => {
+                let name = self.next_name()?;
+
+                if self.labels.contains_key(&name.text) {
+                    return Assembler::error(&name, format!("label `{}` is already defined", name.text));
+                }
+
+                self.labels.insert(name.text, self.here());
+            },
+
+            ":const" => {
+                let name = self.next_name()?;
+                let val = self.next_token()?;
+
+                let Some(val) = self.value(&val) else {
+                    return Assembler::error(&val, format!("expected a number but found `{}`", val.text));
+                };
+
+                self.constants.insert(name.text, val);
+            },
+
+            ":alias" => {
+                let name = self.next_name()?;
+                let reg = self.next_register()?;
+
+                self.aliases.insert(name.text, reg);
+            },
+
+            ":calc" => {
+                let name = self.next_name()?;
+                let val = self.braced_expression()?;
+
+                self.constants.insert(name.text, val);
+            },
+
+            ":byte" => {
+                let val = match self.tokens.front().filter(|next| next.text == "{").cloned() {
+                    Some(open) => {
+                        let val = self.braced_expression()? as i64;
+                        if !(-128..=255).contains(&val) {
+                            return Assembler::error(&open, format!("{val} is out of range (-128 to 255)"));
+                        }
+                        val
+                    },
+                    None => self.next_byte()? as i64,
+                };
+
+                self.rom.push(val as u8);
+            },
+
+            ":macro" => {
+                let name = self.next_name()?;
+                let mut args = Vec::new();
+
+                loop {
+                    let arg = self.next_token()?;
+                    if arg.text == "{" {
+                        break;
+                    }
+                    args.push(arg.text);
+                }
+
+                let body = self.braced_tokens()?;
+                self.macros.insert(name.text, Macro { args, body });
+            },
+
+            ":call" => self.emit_with_address(|addr| CPUInstruction::CallSubroutine { addr: Constant(addr) }, &token)?,
+
+            // control flow
+            "loop" => self.blocks.push(Block::Loop { start: self.here(), exits: Vec::new(), token }),
+
+            "while" => {
+                // skip the jump out of the loop while the condition holds
+                self.condition(true, &token)?;
+
+                let offset = self.rom.len();
+                self.emit(CPUInstruction::Jump { addr: Constant(0) }, &token)?;
+
+                match self.blocks.iter_mut().rev().find(|block| matches!(block, Block::Loop { .. })) {
+                    Some(Block::Loop { exits, .. }) => exits.push(offset),
+                    _ => return Assembler::error(&token, "`while` outside of a loop"),
+                }
+            },
+
+            "again" => match self.blocks.pop() {
+                Some(Block::Loop { start, exits, .. }) => {
+                    self.emit(CPUInstruction::Jump { addr: Constant(start as u16) }, &token)?;
+
+                    for exit in exits {
+                        self.patch_jump(exit);
+                    }
+                },
+                _ => return Assembler::error(&token, "`again` without a `loop`"),
+            },
+
+            "if" => {
+                // work out whether it's `then` (skip one instruction) or `begin` (a block) before assembling the condition
+                let kind = self.tokens
+                    .iter()
+                    .take(5)
+                    .find(|next| next.text == "then" || next.text == "begin")
+                    .map(|next| next.text.clone());
+
+                match kind.as_deref() {
+                    Some("then") => {
+                        self.condition(false, &token)?;
+                        self.expect("then")?;
+                    },
+                    Some("begin") => {
+                        self.condition(true, &token)?;
+                        self.expect("begin")?;
+
+                        let skip_jump = self.rom.len();
+                        self.emit(CPUInstruction::Jump { addr: Constant(0) }, &token)?;
+                        self.blocks.push(Block::If { skip_jump, token });
+                    },
+                    _ => return Assembler::error(&token, "expected `then` or `begin` after the condition"),
+                }
+            },
+
+            "else" => match self.blocks.pop() {
+                Some(Block::If { skip_jump, .. }) => {
+                    let end_jump = self.rom.len();
+                    self.emit(CPUInstruction::Jump { addr: Constant(0) }, &token)?;
+                    self.patch_jump(skip_jump);
+                    self.blocks.push(Block::Else { end_jump, token });
+                },
+                _ => return Assembler::error(&token, "`else` without an `if ... begin`"),
+            },
+
+            "end" => match self.blocks.pop() {
+                Some(Block::If { skip_jump: jump, .. } | Block::Else { end_jump: jump, .. }) => self.patch_jump(jump),
+                _ => return Assembler::error(&token, "`end` without an `if ... begin`"),
+            },
+
+            // instructions without operands
+            "clear" => self.emit(CPUInstruction::ClearDisplay, &token)?,
+            "return" | ";" => self.emit(CPUInstruction::Return, &token)?,
+            "scroll-right" => self.emit(CPUInstruction::ScrollRight, &token)?,
+            "scroll-left" => self.emit(CPUInstruction::ScrollLeft, &token)?,
+            "exit" => self.emit(CPUInstruction::Exit, &token)?,
+            "hires" => self.emit(CPUInstruction::SetHighRes { enabled: true }, &token)?,
+            "lores" => self.emit(CPUInstruction::SetHighRes { enabled: false }, &token)?,
+            "audio" => self.emit(CPUInstruction::LoadAudioPattern, &token)?,
+
+            "scroll-down" => {
+                let rows = self.next_number(0, 15)?;
+                self.emit(CPUInstruction::ScrollDown { rows: Constant(rows) }, &token)?;
+            },
+
+            "scroll-up" => {
+                let rows = self.next_number(0, 15)?;
+                self.emit(CPUInstruction::ScrollUp { rows: Constant(rows) }, &token)?;
+            },
+
+            "plane" => {
+                let planes = self.next_number(0, 3)?;
+                self.emit(CPUInstruction::SelectPlanes { planes: Constant(planes) }, &token)?;
+            },
+
+            "jump" => self.emit_with_address(|addr| CPUInstruction::Jump { addr: Constant(addr) }, &token)?,
+            "jump0" => self.emit_with_address(|addr| CPUInstruction::SpecialJump { offset: Constant(addr) }, &token)?,
+
+            "sprite" => {
+                let x = self.next_register()?;
+                let y = self.next_register()?;
+                let height = self.next_number(0, 15)?;
+
+                self.emit(CPUInstruction::Draw { x_reg: VRegister(x), y_reg: VRegister(y), height_px: Constant(height) }, &token)?;
+            },
+
+            "bcd" => {
+                let x = self.next_register()?;
+                self.emit(CPUInstruction::Bcd { x_reg: VRegister(x) }, &token)?;
+            },
+
+            "save" | "load" => {
+                let x = self.next_register()?;
+                let save = token.text == "save";
+
+                // `save vx - vy` and `load vx - vy` work on a range of registers
+                let instruction = if self.tokens.front().is_some_and(|next| next.text == "-") {
+                    self.next_token()?;
+                    let y = self.next_register()?;
+                    let (x, y) = (Constant(x as u16), Constant(y as u16));
+
+                    if save { CPUInstruction::RegisterRangeDump { x, y } } else { CPUInstruction::RegisterRangeLoad { x, y } }
+                } else if save {
+                    CPUInstruction::RegisterDump { x: Constant(x as u16) }
+                } else {
+                    CPUInstruction::RegisterLoad { x: Constant(x as u16) }
+                };
+
+                self.emit(instruction, &token)?;
+            },
+
+            "saveflags" => {
+                let x = self.next_register()?;
+                self.emit(CPUInstruction::SaveFlags { x: Constant(x as u16) }, &token)?;
+            },
+
+            "loadflags" => {
+                let x = self.next_register()?;
+                self.emit(CPUInstruction::LoadFlags { x: Constant(x as u16) }, &token)?;
+            },
+
+            "delay" | "buzzer" | "pitch" => {
+                self.expect(":=")?;
+                let x = self.next_register()?;
+
+                let instruction = match token.text.as_str() {
+                    "delay" => CPUInstruction::Assignment { to: CurrentDelayTimer, from: VRegister(x) },
+                    "buzzer" => CPUInstruction::Assignment { to: CurrentSoundTimer, from: VRegister(x) },
+                    _ => CPUInstruction::SetPitch { x_reg: VRegister(x) },
+                };
+
+                self.emit(instruction, &token)?;
+            },
+
+            "i" => self.i_statement(token)?,
+
+            _ => {
+                if let Some(reg) = self.register(&token) {
+                    return self.register_statement(reg, token);
+                }
+
+                if let Some(mac) = self.macros.get(&token.text).cloned() {
+                    return self.expand_macro(mac, &token);
+                }
+
+                // numbers (and constants) on their own are data bytes
+                if let Some(val) = self.value(&token).filter(|_| !self.labels.contains_key(&token.text)) {
+                    if !(-128.0..=255.0).contains(&val) {
+                        return Assembler::error(&token, format!("{val} doesn't fit in a byte"));
+                    }
+
+                    self.rom.push(val as i64 as u8);
+                    return Ok(());
+                }
+
+                if token.text.starts_with(':') || parse_number(&token.text).is_some() {
+                    return Assembler::error(&token, format!("unknown directive `{}`", token.text));
+                }
+
+                // anything else is a subroutine call, to a label that may not have been defined yet
+                self.tokens.push_front(token.clone());
+                self.emit_with_address(|addr| CPUInstruction::CallSubroutine { addr: Constant(addr) }, &token)?;
+            },
+        }
+
+        Ok(())
+    }
+
+    // assembles a statement starting with i
+    fn i_statement(&mut self, token: Token) -> Result<(), AsmError> {
+        use CPUInstrTarget::*;
+
+        let op = self.next_token()?;
+
+        match op.text.as_str() {
+            ":=" => match self.tokens.front().map(|next| next.text.as_str()) {
+                Some("hex") | Some("bighex") => {
+                    let large = self.next_token()?.text == "bighex";
+                    let x = self.next_register()?;
+                    let from = if large { LargeSpriteAddress(x) } else { SpriteAddress(x) };
+
+                    self.emit(CPUInstruction::Assignment { to: IRegister, from }, &token)
+                },
+
+                Some("long") => {
+                    self.next_token()?;
+                    let target = self.next_token()?;
+
+                    self.emit(CPUInstruction::LongLoadI, &token)?;
+
+                    let addr = match self.value(&target) {
+                        Some(addr) if (0.0..0x10000 as f64).contains(&addr) => addr as u16,
+                        Some(addr) => return Assembler::error(&target, format!("address {addr} is out of range")),
+                        None => {
+                            self.fixups.push(Fixup { offset: self.rom.len(), label: target, long: true });
+                            0
+                        },
+                    };
+
+                    self.rom.extend_from_slice(&addr.to_be_bytes());
+                    Ok(())
+                },
+
+                _ => self.emit_with_address(|addr| CPUInstruction::Assignment { to: IRegister, from: Constant(addr) }, &token),
+            },
+
+            "+=" => {
+                let x = self.next_register()?;
+                self.emit(CPUInstruction::ALUOperation { op: ALUOperations::Add { update_vf: false }, left: IRegister, right: VRegister(x) }, &token)
+            },
+
+            _ => Assembler::error(&op, format!("expected `:=` or `+=` after i but found `{}`", op.text)),
+        }
+    }
+
+    // assembles a statement starting with a register
+    fn register_statement(&mut self, x: usize, token: Token) -> Result<(), AsmError> {
+        use CPUInstrTarget::*;
+
+        let op = self.next_token()?;
+        let operand = self.next_token()?;
+        let y = self.register(&operand);
+
+        let alu = |op| CPUInstruction::ALUOperation { op, left: VRegister(x), right: VRegister(y.unwrap_or_default()) };
+
+        let instruction = match (op.text.as_str(), y) {
+            (":=", Some(y)) => CPUInstruction::ALUOperation { op: ALUOperations::Assign, left: VRegister(x), right: VRegister(y) },
+            (":=", None) => match operand.text.as_str() {
+                "random" => {
+                    let mask = self.next_byte()?;
+                    CPUInstruction::Assignment { to: VRegister(x), from: RandomNum(mask as u8) }
+                },
+                "delay" => CPUInstruction::Assignment { to: VRegister(x), from: CurrentDelayTimer },
+                "key" => CPUInstruction::Assignment { to: VRegister(x), from: CurrentKeyPressed },
+                _ => {
+                    self.tokens.push_front(operand);
+                    CPUInstruction::Assignment { to: VRegister(x), from: Constant(self.next_byte()?) }
+                },
+            },
+            ("+=", Some(_)) => alu(ALUOperations::Add { update_vf: true }),
+            ("+=", None) => {
+                self.tokens.push_front(operand);
+                CPUInstruction::ALUOperation { op: ALUOperations::Add { update_vf: false }, left: VRegister(x), right: Constant(self.next_byte()?) }
+            },
+            ("-=", Some(_)) => alu(ALUOperations::Subtract { update_vf: true }),
+            ("-=", None) => {
+                // subtracting a constant is adding its negation
+                self.tokens.push_front(operand);
+                let val = self.next_byte()?;
+                CPUInstruction::ALUOperation { op: ALUOperations::Add { update_vf: false }, left: VRegister(x), right: Constant(val.wrapping_neg() & 0xFF) }
+            },
+            ("=-", Some(_)) => alu(ALUOperations::SubtractFlipped { update_vf: true }),
+            ("|=", Some(_)) => alu(ALUOperations::Or),
+            ("&=", Some(_)) => alu(ALUOperations::And),
+            ("^=", Some(_)) => alu(ALUOperations::Xor),
+            (">>=", Some(_)) => alu(ALUOperations::ShiftRight { update_vf: true }),
+            ("<<=", Some(_)) => alu(ALUOperations::ShiftLeft { update_vf: true }),
+            ("=-" | "|=" | "&=" | "^=" | ">>=" | "<<=", None) => {
+                return Assembler::error(&operand, format!("expected a register but found `{}`", operand.text));
+            },
+            _ => return Assembler::error(&op, format!("unknown operator `{}`", op.text)),
+        };
+
+        self.emit(instruction, &token)
+    }
+
+    // assembles the skip instruction for a condition. normally the next instruction is only executed if the condition
+    // holds (so it's skipped if it doesn't), inverted it's skipped if the condition holds
+    fn condition(&mut self, inverted: bool, token: &Token) -> Result<(), AsmError> {
+        use CPUInstrTarget::*;
+
+        let x = self.next_register()?;
+        let op = self.next_token()?;
+
+        // the keys conditions only have a register
+        if op.text == "key" || op.text == "-key" {
+            let skip_if_pressed = (op.text == "-key") != inverted;
+            return self.emit(CPUInstruction::CompareEq { eq: skip_if_pressed, left: IsKeyInVRegPressed(x), right: True }, token);
+        }
+
+        if !matches!(op.text.as_str(), "==" | "!=" | "<" | ">" | "<=" | ">=") {
+            return Assembler::error(&op, format!("unsupported comparison `{}` (expected ==, !=, <, >, <=, >=, key or -key)", op.text));
+        }
+
+        let operand = self.next_token()?;
+        let right = match self.register(&operand) {
+            Some(y) => VRegister(y),
+            None => {
+                self.tokens.push_front(operand);
+                Constant(self.next_byte()?)
+            },
+        };
+
+        let (left, equal, right) = match op.text.as_str() {
+            "==" => (VRegister(x), true, right),
+            "!=" => (VRegister(x), false, right),
+            _ => {
+                // like Octo, the other comparisons subtract into VF and then test its flag
+                if x == 0xF || matches!(right, VRegister(0xF)) {
+                    return Assembler::error(&op, format!("vf can't be compared with `{}`, it holds the result", op.text));
+                }
+
+                let flag = self.compare_into_vf(x, &op.text, right, token)?;
+                (VRegister(0xF), true, Constant(flag))
+            },
+        };
+
+        // CompareEq's eq means "skip if equal"
+        let skip_if_equal = equal == inverted;
+        self.emit(CPUInstruction::CompareEq { eq: skip_if_equal, left, right }, token)
+    }
+
+    // assembles an ordering comparison of vx with a register or constant as a subtraction whose borrow flag ends up in
+    // VF (1 if there was no borrow). returns the value VF has if the comparison holds
+    fn compare_into_vf(&mut self, x: usize, op: &str, right: CPUInstrTarget, token: &Token) -> Result<u16, AsmError> {
+        use CPUInstrTarget::*;
+
+        // < and >= subtract the right side from vx, > and <= the other way around. either way the comparison holds if
+        // there was a borrow (VF == 0) for the strict ones, and if there wasn't (VF == 1) for the others
+        let vx_first = matches!(op, "<" | ">=");
+        let flag = if matches!(op, "<" | ">") { 0 } else { 1 };
+
+        let (load, subtract) = match (right, vx_first) {
+            // vf := vx, vf -= vy (or vf := vy, vf -= vx)
+            (VRegister(y), _) => {
+                let (first, second) = if vx_first { (x, y) } else { (y, x) };
+                (
+                    CPUInstruction::ALUOperation { op: ALUOperations::Assign, left: VRegister(0xF), right: VRegister(first) },
+                    CPUInstruction::ALUOperation { op: ALUOperations::Subtract { update_vf: true }, left: VRegister(0xF), right: VRegister(second) },
+                )
+            },
+            // vf := n, vf =- vx (vx - n) or vf -= vx (n - vx)
+            (right, _) => {
+                let op = if vx_first { ALUOperations::SubtractFlipped { update_vf: true } } else { ALUOperations::Subtract { update_vf: true } };
+                (
+                    CPUInstruction::Assignment { to: VRegister(0xF), from: right },
+                    CPUInstruction::ALUOperation { op, left: VRegister(0xF), right: VRegister(x) },
+                )
+            },
+        };
+
+        self.emit(load, token)?;
+        self.emit(subtract, token)?;
+
+        Ok(flag)
+    }
+
+    // takes the tokens up to the matching }, after a { that has already been taken
+    fn braced_tokens(&mut self) -> Result<Vec<Token>, AsmError> {
+        let mut tokens = Vec::new();
+        let mut depth = 1;
+
+        loop {
+            let token = self.next_token()?;
+
+            match token.text.as_str() {
+                "{" => depth += 1,
+                "}" => depth -= 1,
+                _ => {},
+            }
+
+            if depth == 0 {
+                return Ok(tokens);
+            }
+
+            tokens.push(token);
+        }
+    }
+
+    // evaluates a { expression }
+    fn braced_expression(&mut self) -> Result<f64, AsmError> {
+        let open = self.expect("{")?;
+        let tokens = self.braced_tokens()?;
+
+        let mut pos = 0;
+        let val = self.expression(&tokens, &mut pos, &open)?;
+
+        if let Some(extra) = tokens.get(pos) {
+            return Assembler::error(extra, format!("unexpected `{}` in expression", extra.text));
+        }
+
+        Ok(val)
+    }
+
+    // evaluates an expression like Octo does: operators have no precedence and are applied right to left, so
+    // `1 + 2 * 3` is 7 and `2 * 3 + 1` is 8
+    fn expression(&self, tokens: &[Token], pos: &mut usize, start: &Token) -> Result<f64, AsmError> {
+        let left = self.term(tokens, pos, start)?;
+
+        let Some(op) = tokens.get(*pos).filter(|token| token.text != ")") else {
+            return Ok(left);
+        };
+        *pos += 1;
+
+        let right = self.expression(tokens, pos, start)?;
+
+        let val = match op.text.as_str() {
+            "+" => left + right,
+            "-" => left - right,
+            "*" => left * right,
+            "/" => left / right,
+            "%" => left % right,
+            "&" => ((left as i64) & (right as i64)) as f64,
+            "|" => ((left as i64) | (right as i64)) as f64,
+            "^" => ((left as i64) ^ (right as i64)) as f64,
+            "<<" | ">>" => {
+                let shifted = u32::try_from(right as i64).ok().and_then(|amount| match op.text.as_str() {
+                    "<<" => (left as i64).checked_shl(amount),
+                    _ => (left as i64).checked_shr(amount),
+                });
+
+                match shifted {
+                    Some(val) => val as f64,
+                    None => return Assembler::error(op, format!("can't shift by {right} (expected 0 to 63)")),
+                }
+            },
+            "pow" => left.powf(right),
+            "min" => left.min(right),
+            "max" => left.max(right),
+            "<" => (left < right) as u8 as f64,
+            ">" => (left > right) as u8 as f64,
+            "<=" => (left <= right) as u8 as f64,
+            ">=" => (left >= right) as u8 as f64,
+            "==" => (left == right) as u8 as f64,
+            "!=" => (left != right) as u8 as f64,
+            _ => return Assembler::error(op, format!("unknown operator `{}`", op.text)),
+        };
+
+        Ok(val)
+    }
+
+    // evaluates a number, name, parenthesized expression or unary operator
+    fn term(&self, tokens: &[Token], pos: &mut usize, start: &Token) -> Result<f64, AsmError> {
+        let Some(token) = tokens.get(*pos) else {
+            return Assembler::error(tokens.last().unwrap_or(start), "expression ends too early");
+        };
+        *pos += 1;
+
+        let unary = |f: fn(f64) -> f64, pos: &mut usize| Ok(f(self.term(tokens, pos, start)?));
+
+        match token.text.as_str() {
+            "(" => {
+                let val = self.expression(tokens, pos, start)?;
+
+                match tokens.get(*pos) {
+                    Some(close) if close.text == ")" => {
+                        *pos += 1;
+                        Ok(val)
+                    },
+                    _ => Assembler::error(token, "`(` is never closed"),
+                }
+            },
+            "-" => unary(|val| -val, pos),
+            "~" => unary(|val| !(val as i64) as f64, pos),
+            "!" => unary(|val| (val == 0.0) as u8 as f64, pos),
+            "abs" => unary(f64::abs, pos),
+            "sqrt" => unary(f64::sqrt, pos),
+            "floor" => unary(f64::floor, pos),
+            "ceil" => unary(f64::ceil, pos),
+            "sin" => unary(f64::sin, pos),
+            "cos" => unary(f64::cos, pos),
+            "HERE" => Ok(self.here() as f64),
+            "PI" => Ok(std::f64::consts::PI),
+            "E" => Ok(std::f64::consts::E),
+            _ => match self.value(token) {
+                Some(val) => Ok(val),
+                None => Assembler::error(token, format!("`{}` is not a number or a defined constant or label", token.text)),
+            },
+        }
+    }
+
+    // replaces a macro invocation with the macro's body, with the arguments substituted
+    fn expand_macro(&mut self, mac: Macro, token: &Token) -> Result<(), AsmError> {
+        self.macro_expansions += 1;
+        if self.macro_expansions > MAX_MACRO_EXPANSIONS {
+            return Assembler::error(token, "too many macro expansions (does a macro invoke itself?)");
+        }
+
+        let mut args = HashMap::new();
+        for arg in &mac.args {
+            args.insert(arg.clone(), self.next_token()?.text);
+        }
+
+        // the expanded tokens take the position of the invocation, so errors and the line map point at it
+        for body_token in mac.body.iter().rev() {
+            let text = args.get(&body_token.text).cloned().unwrap_or_else(|| body_token.text.clone());
+            self.tokens.push_front(Token { text, ..token.clone() });
+        }
+
+        Ok(())
+    }
+}
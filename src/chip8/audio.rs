@@ -0,0 +1,43 @@
+// the XO-CHIP audio state, a 1-bit waveform pattern played back at a rate set by the pitch register
+#[derive(Debug)]
+pub struct Chip8Audio {
+    pattern: Option<[u8; 16]>, // None until a program loads a pattern, in which case the plain buzzer is used
+    pitch: u8,
+}
+
+impl Chip8Audio {
+    pub fn new() -> Chip8Audio {
+        Chip8Audio { pattern: None, pitch: 64 }
+    }
+
+    // gets the audio pattern (128 1-bit samples, most significant bit first) immutably
+    pub fn get_pattern(&self) -> &Option<[u8; 16]> {
+        &self.pattern
+    }
+
+    // gets the audio pattern mutably
+    pub fn get_pattern_mut(&mut self) -> &mut Option<[u8; 16]> {
+        &mut self.pattern
+    }
+
+    // gets the pitch register immutably
+    pub fn get_pitch(&self) -> &u8 {
+        &self.pitch
+    }
+
+    // gets the pitch register mutably
+    pub fn get_pitch_mut(&mut self) -> &mut u8 {
+        &mut self.pitch
+    }
+
+    // gets the rate the pattern's bits are played back at, in bits per second (4000 at the default pitch of 64)
+    pub fn get_playback_rate(&self) -> f64 {
+        4000.0 * 2f64.powf((self.pitch as f64 - 64.0) / 48.0)
+    }
+}
+
+impl Default for Chip8Audio {
+    fn default() -> Self {
+        Chip8Audio::new()
+    }
+}
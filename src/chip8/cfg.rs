@@ -0,0 +1,370 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::ops::Range;
+use super::Chip8;
+use super::cpu::{CPUInstrTarget, CPUInstruction};
+use super::disasm::{data_reference, instruction_flow, read_opcode, Disassembly, DisasmSyntax, Flow, PROGRAM_START};
+
+// how control gets from one basic block to another
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum EdgeKind {
+    Fallthrough, // carries on with the next instruction (including after a skip that isn't taken, or a call returning)
+    Jump,
+    Skip, // a skip instruction skipping the next instruction
+    Computed, // BNNN, which goes to NNN plus a register, so only the base address is known
+}
+
+// a straight run of instructions that is only entered at the start and only left at the end
+#[derive(Debug, Clone, PartialEq)]
+pub struct BasicBlock {
+    pub start: usize,
+    pub instructions: Vec<usize>, // the address of every instruction
+    pub end: usize, // the address after the last instruction
+    pub successors: Vec<(usize, EdgeKind)>,
+    pub call: Option<usize>, // the subroutine called by the last instruction, if it is a call
+}
+
+// where static analysis had to give up or found something suspicious
+#[derive(Debug, Clone, PartialEq)]
+pub enum CfgWarning {
+    ComputedJump { addr: usize, base: usize }, // BNNN, only the base address was followed
+    SelfModifying { addr: usize, target: Range<usize> }, // writes memory that holds code
+    MachineCode { addr: usize }, // 0NNN, which runs code for the original CPU
+    OutsideRom { addr: usize, target: usize }, // a jump or call to an address outside the ROM
+    InvalidInstruction { addr: usize },
+}
+
+impl fmt::Display for CfgWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CfgWarning::ComputedJump { addr, base } => write!(f, "0x{addr:03X}: computed jump (BNNN), only its base 0x{base:03X} was followed"),
+            CfgWarning::SelfModifying { addr, target } => write!(f, "0x{addr:03X}: writes 0x{:03X}..0x{:03X}, which contains code (self-modifying)", target.start, target.end),
+            CfgWarning::MachineCode { addr } => write!(f, "0x{addr:03X}: calls machine code (0NNN), which can't be followed"),
+            CfgWarning::OutsideRom { addr, target } => write!(f, "0x{addr:03X}: goes to 0x{target:03X}, which is outside the ROM"),
+            CfgWarning::InvalidInstruction { addr } => write!(f, "0x{addr:03X}: invalid instruction, execution can't continue"),
+        }
+    }
+}
+
+// a region of the ROM that no path through the program executes
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnreachableRegion {
+    pub range: Range<usize>,
+    pub referenced: bool, // whether the program points I into it, i.e. it is probably data rather than dead code
+}
+
+// the control flow graph of a ROM, built without running it by following every path from PROGRAM_START (the same way
+// the disassembler finds code)
+#[derive(Debug, Clone)]
+pub struct ControlFlowGraph {
+    pub disassembly: Disassembly,
+    pub blocks: BTreeMap<usize, BasicBlock>,
+    pub subroutines: BTreeMap<usize, BTreeSet<usize>>, // the start of every block in each subroutine, by entry address
+    pub calls: BTreeSet<(usize, usize)>, // (caller, callee) subroutine entry addresses
+    pub unreachable: Vec<UnreachableRegion>,
+    pub warnings: Vec<CfgWarning>,
+}
+
+impl ControlFlowGraph {
+    // analyzes a ROM loaded at PROGRAM_START
+    pub fn new(rom: &[u8]) -> ControlFlowGraph {
+        let disassembly = Disassembly::new(rom);
+        let rom_range = PROGRAM_START..PROGRAM_START + rom.len();
+
+        // every instruction the disassembler found: its opcode, the word after it and its decoded form
+        let code = disassembly.items
+            .iter()
+            .filter(|item| item.is_code)
+            .filter_map(|item| {
+                let (opcode, long_addr) = read_opcode(rom, item.addr - PROGRAM_START)?;
+                Some((item.addr, (opcode, long_addr, Chip8::opcode_to_instruction(opcode))))
+            })
+            .collect::<BTreeMap<_, _>>();
+
+        let next_addr = |addr: usize| addr + code.get(&addr).map_or(2, |(opcode, _, _)| Chip8::instruction_length(*opcode));
+
+        // blocks start at the program start, at everything control goes to and after everything that changes the flow
+        let mut leaders = BTreeSet::from([PROGRAM_START]);
+        for (&addr, (_, _, instruction)) in &code {
+            let next = next_addr(addr);
+
+            match instruction_flow(instruction) {
+                Flow::Next => {},
+                Flow::Skip => leaders.extend([next, next_addr(next)]),
+                Flow::Jump(target) | Flow::Call(target) => leaders.extend([target, next]),
+                Flow::Stop => {
+                    leaders.insert(next);
+                },
+            }
+        }
+
+        let mut blocks = BTreeMap::new();
+        let mut warnings = Vec::new();
+
+        for &start in leaders.iter().filter(|addr| code.contains_key(addr)) {
+            let mut block = BasicBlock { start, instructions: Vec::new(), end: start, successors: Vec::new(), call: None };
+            let mut i_register = None; // the value of I, while it is known
+            let mut addr = start;
+
+            loop {
+                let (_, long_addr, instruction) = &code[&addr];
+                let next = next_addr(addr);
+                block.instructions.push(addr);
+                block.end = next;
+
+                // track I to catch writes into code
+                if let Some(target) = data_reference(instruction, *long_addr) {
+                    i_register = Some(target);
+                }
+
+                let written = match instruction {
+                    CPUInstruction::Bcd { .. } => Some(3),
+                    CPUInstruction::RegisterDump { x: CPUInstrTarget::Constant(x) } => Some(*x as usize + 1),
+                    CPUInstruction::RegisterRangeDump { x: CPUInstrTarget::Constant(x), y: CPUInstrTarget::Constant(y) } => Some(x.abs_diff(*y) as usize + 1),
+                    _ => None,
+                };
+
+                if let Some(len) = written {
+                    let overwrites_code = i_register.is_some_and(|i| {
+                        code.range(i.saturating_sub(3)..i + len).any(|(&code_addr, (opcode, _, _))| code_addr + Chip8::instruction_length(*opcode) > i)
+                    });
+                    if let (true, Some(i)) = (overwrites_code, i_register) {
+                        warnings.push(CfgWarning::SelfModifying { addr, target: i..i + len });
+                    }
+
+                    // some interpreters move I past what FX55 wrote, so it isn't known anymore
+                    i_register = None;
+                }
+
+                if data_reference(instruction, *long_addr).is_none() && matches!(
+                    instruction,
+                    CPUInstruction::Assignment { to: CPUInstrTarget::IRegister, .. }
+                    | CPUInstruction::ALUOperation { left: CPUInstrTarget::IRegister, .. }
+                ) {
+                    i_register = None;
+                }
+
+                // where control can go after the instruction, or None if the block carries on
+                let edges = match (instruction_flow(instruction), instruction) {
+                    (Flow::Jump(target), CPUInstruction::SpecialJump { .. }) => {
+                        warnings.push(CfgWarning::ComputedJump { addr, base: target });
+                        Some(vec![(target, EdgeKind::Computed)])
+                    },
+                    (Flow::Jump(target), _) => Some(vec![(target, EdgeKind::Jump)]),
+                    (Flow::Call(target), _) => {
+                        if !rom_range.contains(&target) {
+                            warnings.push(CfgWarning::OutsideRom { addr, target });
+                        }
+                        block.call = Some(target);
+                        Some(vec![(next, EdgeKind::Fallthrough)])
+                    },
+                    (Flow::Skip, _) => Some(vec![(next, EdgeKind::Fallthrough), (next_addr(next), EdgeKind::Skip)]),
+                    (Flow::Stop, CPUInstruction::CallMachineCode { .. }) => {
+                        warnings.push(CfgWarning::MachineCode { addr });
+                        Some(Vec::new())
+                    },
+                    (Flow::Stop, CPUInstruction::Unknown { .. } | CPUInstruction::ALUOperation { .. }) => {
+                        warnings.push(CfgWarning::InvalidInstruction { addr });
+                        Some(Vec::new())
+                    },
+                    (Flow::Stop, _) => Some(Vec::new()),
+                    (Flow::Next, _) if leaders.contains(&next) || !code.contains_key(&next) => {
+                        Some(code.contains_key(&next).then_some((next, EdgeKind::Fallthrough)).into_iter().collect())
+                    },
+                    (Flow::Next, _) => None,
+                };
+
+                let Some(edges) = edges else {
+                    addr = next;
+                    continue;
+                };
+
+                for (target, kind) in edges {
+                    if rom_range.contains(&target) {
+                        block.successors.push((target, kind));
+                    } else {
+                        warnings.push(CfgWarning::OutsideRom { addr, target });
+                    }
+                }
+                break;
+            }
+
+            blocks.insert(start, block);
+        }
+
+        // a subroutine is every block reachable from its entry without following calls
+        let entries = std::iter::once(PROGRAM_START)
+            .chain(blocks.values().filter_map(|block| block.call))
+            .filter(|entry| blocks.contains_key(entry))
+            .collect::<BTreeSet<_>>();
+
+        let mut subroutines = BTreeMap::new();
+        let mut calls = BTreeSet::new();
+
+        for &entry in &entries {
+            let mut members = BTreeSet::new();
+            let mut pending = vec![entry];
+
+            while let Some(start) = pending.pop() {
+                if !members.insert(start) {
+                    continue;
+                }
+
+                let block = &blocks[&start];
+                pending.extend(block.successors.iter().map(|&(target, _)| target).filter(|target| blocks.contains_key(target)));
+                if let Some(callee) = block.call {
+                    calls.insert((entry, callee));
+                }
+            }
+
+            subroutines.insert(entry, members);
+        }
+
+        // everything the disassembler didn't find code in is unreachable, either data or dead code
+        let references = code
+            .values()
+            .filter_map(|(_, long_addr, instruction)| data_reference(instruction, *long_addr))
+            .collect::<BTreeSet<_>>();
+
+        let mut unreachable: Vec<UnreachableRegion> = Vec::new();
+        for item in disassembly.items.iter().filter(|item| !item.is_code) {
+            let range = item.addr..item.addr + item.bytes.len();
+            let referenced = references.range(range.clone()).next().is_some();
+
+            match unreachable.last_mut() {
+                Some(region) if region.range.end == range.start => {
+                    region.range.end = range.end;
+                    region.referenced |= referenced;
+                },
+                _ => unreachable.push(UnreachableRegion { range, referenced }),
+            }
+        }
+
+        warnings.sort_by_key(|warning| match warning {
+            CfgWarning::ComputedJump { addr, .. }
+            | CfgWarning::SelfModifying { addr, .. }
+            | CfgWarning::MachineCode { addr }
+            | CfgWarning::OutsideRom { addr, .. }
+            | CfgWarning::InvalidInstruction { addr } => *addr,
+        });
+        warnings.dedup();
+
+        ControlFlowGraph { disassembly, blocks, subroutines, calls, unreachable, warnings }
+    }
+
+    // names an address after its label
+    pub fn get_name(&self, addr: usize) -> String {
+        match self.disassembly.labels.get(&addr) {
+            Some(label) => label.clone(),
+            None => format!("0x{addr:03X}"),
+        }
+    }
+
+    // writes a summary of the subroutines, call graph, unreachable regions and warnings
+    pub fn report(&self) -> String {
+        let mut out = format!("{} basic blocks in {} subroutines\n", self.blocks.len(), self.subroutines.len());
+
+        out.push_str("\nsubroutines:\n");
+        for (entry, members) in &self.subroutines {
+            let callees = self.calls
+                .iter()
+                .filter(|(caller, _)| caller == entry)
+                .map(|&(_, callee)| self.get_name(callee))
+                .collect::<Vec<_>>();
+
+            out.push_str(&format!("  {} ({} blocks)", self.get_name(*entry), members.len()));
+            if !callees.is_empty() {
+                out.push_str(&format!(" calls {}", callees.join(", ")));
+            }
+            out.push('\n');
+        }
+
+        if !self.unreachable.is_empty() {
+            out.push_str("\nunreachable regions:\n");
+            for region in &self.unreachable {
+                let kind = if region.referenced { "data" } else { "unreferenced" };
+                out.push_str(&format!("  0x{:03X}..0x{:03X} ({} bytes, {kind})\n", region.range.start, region.range.end, region.range.len()));
+            }
+        }
+
+        if !self.warnings.is_empty() {
+            out.push_str("\nwarnings:\n");
+            for warning in &self.warnings {
+                out.push_str(&format!("  {warning}\n"));
+            }
+        }
+
+        out
+    }
+
+    // exports the basic blocks as a Graphviz DOT graph, one cluster per subroutine (blocks shared by several
+    // subroutines are drawn in the first one)
+    pub fn to_dot(&self) -> String {
+        let mut out = String::from("digraph cfg {\n  node [shape=box fontname=monospace];\n");
+        let mut drawn = BTreeSet::new();
+
+        for (entry, members) in &self.subroutines {
+            out.push_str(&format!("  subgraph cluster_{entry:03X} {{\n    label=\"{}\";\n", dot_escape(&self.get_name(*entry))));
+
+            for &start in members.iter().filter(|start| drawn.insert(**start)) {
+                let block = &self.blocks[&start];
+                let mut label = String::new();
+
+                if let Some(name) = self.disassembly.labels.get(&start) {
+                    label.push_str(&format!("{name}:\\l"));
+                }
+                for &addr in &block.instructions {
+                    let text = self.disassembly.items
+                        .iter()
+                        .find(|item| item.addr == addr)
+                        .map(|item| self.disassembly.item_text(item, DisasmSyntax::Classic))
+                        .unwrap_or_default();
+                    label.push_str(&format!("{addr:04X}: {}\\l", dot_escape(&text)));
+                }
+
+                out.push_str(&format!("    b{start:03X} [label=\"{label}\"];\n"));
+            }
+
+            out.push_str("  }\n");
+        }
+
+        for block in self.blocks.values() {
+            for (target, kind) in &block.successors {
+                let style = match kind {
+                    EdgeKind::Fallthrough => "",
+                    EdgeKind::Jump => " [color=blue]",
+                    EdgeKind::Skip => " [color=darkgreen label=skip]",
+                    EdgeKind::Computed => " [color=red style=dotted label=computed]",
+                };
+                out.push_str(&format!("  b{:03X} -> b{target:03X}{style};\n", block.start));
+            }
+
+            if let Some(callee) = block.call.filter(|callee| self.blocks.contains_key(callee)) {
+                out.push_str(&format!("  b{:03X} -> b{callee:03X} [style=dashed label=call];\n", block.start));
+            }
+        }
+
+        out.push_str("}\n");
+        out
+    }
+
+    // exports the call graph as a Graphviz DOT graph
+    pub fn call_graph_to_dot(&self) -> String {
+        let mut out = String::from("digraph calls {\n  node [shape=box fontname=monospace];\n");
+
+        for entry in self.subroutines.keys() {
+            out.push_str(&format!("  s{entry:03X} [label=\"{}\"];\n", dot_escape(&self.get_name(*entry))));
+        }
+        for (caller, callee) in &self.calls {
+            out.push_str(&format!("  s{caller:03X} -> s{callee:03X};\n"));
+        }
+
+        out.push_str("}\n");
+        out
+    }
+}
+
+// escapes text for a quoted DOT string
+fn dot_escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
@@ -0,0 +1,177 @@
+use std::collections::BTreeSet;
+use super::Chip8;
+use super::disasm::DisasmItem;
+use super::memory::MEMORY_SIZE;
+
+// what happened to a byte of memory, as bit flags
+pub const EXECUTED: u8 = 1;
+pub const READ: u8 = 2;
+pub const WRITTEN: u8 = 4;
+
+// the first line of a coverage file
+const HEADER: &str = "chip8-coverage 1";
+
+// turns flags into the `xrw` letters used in coverage files (a dash for each missing flag)
+fn flags_to_text(flags: u8) -> String {
+    [(EXECUTED, 'x'), (READ, 'r'), (WRITTEN, 'w')]
+        .iter()
+        .map(|&(flag, letter)| if flags & flag != 0 { letter } else { '-' })
+        .collect()
+}
+
+// which bytes of memory were executed, read as data and written. call record before every instruction
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chip8Coverage {
+    flags: Vec<u8>, // by address
+    warned: BTreeSet<usize>, // self-modifying addresses that have been logged already
+}
+
+impl Chip8Coverage {
+    pub fn new() -> Chip8Coverage {
+        Chip8Coverage {
+            flags: Vec::new(),
+            warned: BTreeSet::new(),
+        }
+    }
+
+    // gets the flags of an address
+    pub fn get_flags(&self, addr: usize) -> u8 {
+        self.flags.get(addr).copied().unwrap_or(0)
+    }
+
+    // adds flags to an address, warning the first time an address turns out to be both executed and written.
+    // addresses past the end of memory (e.g. the rest of an instruction at its very end) are ignored
+    fn mark(&mut self, addr: usize, flags: u8) {
+        if addr >= MEMORY_SIZE {
+            return;
+        }
+
+        if self.flags.len() <= addr {
+            self.flags.resize(addr + 1, 0);
+        }
+
+        self.flags[addr] |= flags;
+
+        if self.flags[addr] & (EXECUTED | WRITTEN) == EXECUTED | WRITTEN && self.warned.insert(addr) {
+            log::warn!("self-modifying code: 0x{addr:03X} was both executed and written");
+        }
+    }
+
+    // records the instruction that is about to be executed, along with the memory it is going to read and write
+    pub fn record(&mut self, c8: &Chip8) {
+        let pc = *c8.registers.get_pc_register() as usize;
+        let Ok(opcode) = c8.memory.get_memory_at_u16(pc) else {
+            return;
+        };
+
+        for addr in pc..pc + Chip8::instruction_length(opcode) {
+            self.mark(addr, EXECUTED);
+        }
+
+        let accesses = c8.memory_accesses(&Chip8::opcode_to_instruction(opcode));
+        for addr in accesses.reads {
+            self.mark(addr, READ);
+        }
+        for addr in accesses.writes {
+            self.mark(addr, WRITTEN);
+        }
+    }
+
+    // adds everything recorded in another coverage, e.g. from an earlier run
+    pub fn merge(&mut self, other: &Chip8Coverage) {
+        for (addr, &flags) in other.flags.iter().enumerate().filter(|(_, flags)| **flags != 0) {
+            self.mark(addr, flags);
+        }
+    }
+
+    // gets the addresses that were both executed and written, i.e. self-modifying code
+    pub fn get_self_modifying(&self) -> Vec<usize> {
+        self.flags
+            .iter()
+            .enumerate()
+            .filter(|(_, flags)| **flags & (EXECUTED | WRITTEN) == EXECUTED | WRITTEN)
+            .map(|(addr, _)| addr)
+            .collect()
+    }
+
+    // counts the bytes in a range of addresses that have all of some flags
+    pub fn count(&self, addrs: std::ops::Range<usize>, flags: u8) -> usize {
+        addrs.filter(|&addr| self.get_flags(addr) & flags == flags).count()
+    }
+
+    // writes the coverage file: a header line, then an `ADDR xrw` line (hex address) for every byte that was touched
+    pub fn to_text(&self) -> String {
+        let mut out = format!("{HEADER}\n");
+
+        for (addr, &flags) in self.flags.iter().enumerate().filter(|(_, flags)| **flags != 0) {
+            out.push_str(&format!("{addr:03X} {}\n", flags_to_text(flags)));
+        }
+
+        out
+    }
+
+    // reads a coverage file written by to_text
+    pub fn from_text(text: &str) -> Result<Chip8Coverage, String> {
+        let mut lines = text.lines().enumerate();
+        if lines.next().map(|(_, line)| line.trim()) != Some(HEADER) {
+            return Err(String::from("not a coverage file"));
+        }
+
+        let mut coverage = Chip8Coverage::new();
+        for (i, line) in lines.filter(|(_, line)| !line.trim().is_empty()) {
+            let parsed = line.split_once(' ').and_then(|(addr, letters)| {
+                let addr = usize::from_str_radix(addr, 16).ok()?;
+                let flags = letters.trim().chars().try_fold(0, |flags, letter| match letter {
+                    'x' => Some(flags | EXECUTED),
+                    'r' => Some(flags | READ),
+                    'w' => Some(flags | WRITTEN),
+                    '-' => Some(flags),
+                    _ => None,
+                })?;
+
+                Some((addr, flags))
+            });
+
+            let (addr, flags) = parsed.ok_or(format!("line {}: expected `ADDR xrw`", i + 1))?;
+            if addr >= MEMORY_SIZE {
+                return Err(format!("line {}: address {addr:X} is past the end of memory", i + 1));
+            }
+
+            if coverage.flags.len() <= addr {
+                coverage.flags.resize(addr + 1, 0);
+            }
+            coverage.flags[addr] |= flags;
+        }
+
+        Ok(coverage)
+    }
+
+    // describes the coverage of a disassembly item, for Disassembly::to_annotated_text
+    pub fn annotate(&self, item: &DisasmItem) -> Option<String> {
+        let flags = (item.addr..item.addr + item.bytes.len()).fold(0, |flags, addr| flags | self.get_flags(addr));
+
+        let mut notes = Vec::new();
+        if flags & EXECUTED != 0 {
+            notes.push("executed");
+        } else if item.is_code {
+            notes.push("NEVER EXECUTED");
+        }
+        if flags & READ != 0 {
+            notes.push("read");
+        }
+        if flags & WRITTEN != 0 {
+            notes.push("written");
+        }
+        if flags & (EXECUTED | WRITTEN) == EXECUTED | WRITTEN {
+            notes.push("SELF-MODIFYING");
+        }
+
+        (!notes.is_empty()).then(|| format!("[{}]", notes.join(", ")))
+    }
+}
+
+impl Default for Chip8Coverage {
+    fn default() -> Self {
+        Chip8Coverage::new()
+    }
+}
@@ -1,6 +1,10 @@
+use std::ops::Range;
 use super::Chip8;
+use super::error::*;
+use super::memory::BIG_FONT_ADDR;
 use super::sprites::Chip8Sprite;
-use rand::prelude::*;
+use super::stack::MAPPED_STACK_ADDR;
+use super::trace::{TraceEntry, TraceSnapshot};
 
 // "targets" of a CPU instruction. can be read from for an operation or written to
 #[derive(Debug, Clone, Copy)]
@@ -14,6 +18,7 @@ pub enum CPUInstrTarget {
     CurrentDelayTimer,
     CurrentSoundTimer,
     SpriteAddress(usize),
+    LargeSpriteAddress(usize),
     RandomNum(u8),
     True,
 }
@@ -33,12 +38,82 @@ pub enum ALUOperations {
     Unknown,
 }
 
+// the memory an instruction reads and writes, see Chip8::memory_accesses
+#[derive(Debug, Clone, PartialEq)]
+pub struct MemoryAccesses {
+    pub reads: Range<usize>,
+    pub writes: Range<usize>,
+}
+
+// the broad kinds of instructions, see CPUInstruction::class
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum InstructionClass {
+    Flow, // jumps, calls, returns and exit
+    Skip, // conditional skips that don't involve keys
+    Load, // loading constants, registers and sprite addresses into registers
+    Alu,
+    Random,
+    Timer,
+    Input, // key skips and waiting for a key
+    Display, // drawing, clearing, scrolling and switching modes or planes
+    Memory, // BCD and register dumps/loads
+    Audio,
+    Flags, // saving and loading the RPL flags
+    Unknown,
+}
+
+impl InstructionClass {
+    pub const ALL: [InstructionClass; 12] = [
+        InstructionClass::Flow,
+        InstructionClass::Skip,
+        InstructionClass::Load,
+        InstructionClass::Alu,
+        InstructionClass::Random,
+        InstructionClass::Timer,
+        InstructionClass::Input,
+        InstructionClass::Display,
+        InstructionClass::Memory,
+        InstructionClass::Audio,
+        InstructionClass::Flags,
+        InstructionClass::Unknown,
+    ];
+
+    // gets the lowercase name of the class
+    pub fn name(&self) -> &'static str {
+        match self {
+            InstructionClass::Flow => "flow",
+            InstructionClass::Skip => "skip",
+            InstructionClass::Load => "load",
+            InstructionClass::Alu => "alu",
+            InstructionClass::Random => "random",
+            InstructionClass::Timer => "timer",
+            InstructionClass::Input => "input",
+            InstructionClass::Display => "display",
+            InstructionClass::Memory => "memory",
+            InstructionClass::Audio => "audio",
+            InstructionClass::Flags => "flags",
+            InstructionClass::Unknown => "unknown",
+        }
+    }
+
+    // gets a class by its name
+    pub fn from_name(name: &str) -> Option<InstructionClass> {
+        InstructionClass::ALL.into_iter().find(|class| class.name() == name)
+    }
+}
+
 // all possible CPU instructions
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub enum CPUInstruction {
     CallMachineCode { addr: CPUInstrTarget, },
     ClearDisplay,
     Return,
+    ScrollDown { rows: CPUInstrTarget },
+    ScrollUp { rows: CPUInstrTarget },
+    ScrollRight,
+    ScrollLeft,
+    Exit,
+    SetHighRes { enabled: bool },
     Jump { addr: CPUInstrTarget, },
     CallSubroutine { addr: CPUInstrTarget, },
     CompareEq{ eq: bool, left: CPUInstrTarget, right: CPUInstrTarget, },
@@ -46,13 +121,72 @@ pub enum CPUInstruction {
     ALUOperation { op: ALUOperations, left: CPUInstrTarget, right: CPUInstrTarget },
     SpecialJump { offset: CPUInstrTarget },
     Draw { x_reg: CPUInstrTarget, y_reg: CPUInstrTarget, height_px: CPUInstrTarget, },
-    BCD { x_reg: CPUInstrTarget },
+    Bcd { x_reg: CPUInstrTarget },
     RegisterDump { x: CPUInstrTarget },
     RegisterLoad { x: CPUInstrTarget },
+    RegisterRangeDump { x: CPUInstrTarget, y: CPUInstrTarget },
+    RegisterRangeLoad { x: CPUInstrTarget, y: CPUInstrTarget },
+    LongLoadI,
+    SelectPlanes { planes: CPUInstrTarget },
+    LoadAudioPattern,
+    SetPitch { x_reg: CPUInstrTarget },
+    SaveFlags { x: CPUInstrTarget },
+    LoadFlags { x: CPUInstrTarget },
     Unknown { opcode: u16 },
 }
 
+impl CPUInstruction {
+    // gets the broad kind of the instruction, e.g. for filtering traces
+    pub fn class(&self) -> InstructionClass {
+        match self {
+            CPUInstruction::CallMachineCode { .. }
+            | CPUInstruction::Return
+            | CPUInstruction::Exit
+            | CPUInstruction::Jump { .. }
+            | CPUInstruction::CallSubroutine { .. }
+            | CPUInstruction::SpecialJump { .. } => InstructionClass::Flow,
+
+            CPUInstruction::CompareEq { left: CPUInstrTarget::IsKeyInVRegPressed(_), .. } => InstructionClass::Input,
+            CPUInstruction::CompareEq { .. } => InstructionClass::Skip,
+
+            CPUInstruction::Assignment { from: CPUInstrTarget::RandomNum(_), .. } => InstructionClass::Random,
+            CPUInstruction::Assignment { from: CPUInstrTarget::CurrentKeyPressed, .. } => InstructionClass::Input,
+            CPUInstruction::Assignment { to: CPUInstrTarget::CurrentDelayTimer | CPUInstrTarget::CurrentSoundTimer, .. }
+            | CPUInstruction::Assignment { from: CPUInstrTarget::CurrentDelayTimer | CPUInstrTarget::CurrentSoundTimer, .. } => InstructionClass::Timer,
+            CPUInstruction::Assignment { .. } | CPUInstruction::LongLoadI => InstructionClass::Load,
+
+            CPUInstruction::ALUOperation { .. } => InstructionClass::Alu,
+
+            CPUInstruction::ClearDisplay
+            | CPUInstruction::ScrollDown { .. }
+            | CPUInstruction::ScrollUp { .. }
+            | CPUInstruction::ScrollRight
+            | CPUInstruction::ScrollLeft
+            | CPUInstruction::SetHighRes { .. }
+            | CPUInstruction::Draw { .. }
+            | CPUInstruction::SelectPlanes { .. } => InstructionClass::Display,
+
+            CPUInstruction::Bcd { .. }
+            | CPUInstruction::RegisterDump { .. }
+            | CPUInstruction::RegisterLoad { .. }
+            | CPUInstruction::RegisterRangeDump { .. }
+            | CPUInstruction::RegisterRangeLoad { .. } => InstructionClass::Memory,
+
+            CPUInstruction::LoadAudioPattern | CPUInstruction::SetPitch { .. } => InstructionClass::Audio,
+
+            CPUInstruction::SaveFlags { .. } | CPUInstruction::LoadFlags { .. } => InstructionClass::Flags,
+
+            CPUInstruction::Unknown { .. } => InstructionClass::Unknown,
+        }
+    }
+}
+
 impl super::Chip8 {
+    // gets the length of the instruction starting with the given opcode in bytes (F000 NNNN takes up 4 bytes, everything else 2)
+    pub fn instruction_length(opcode: u16) -> usize {
+        if opcode == 0xF000 { 4 } else { 2 }
+    }
+
     // converts a numerical opcode into a CPUInstruction
     pub fn opcode_to_instruction(opcode: u16) -> CPUInstruction {
         let instruction_type = (opcode & 0xF000) >> 12;
@@ -64,7 +198,7 @@ impl super::Chip8 {
         ];
 
         match instruction_type {
-            // 0xNNN: calls machine code at address 0xNNN (implemented: 0x0E0 = display clear, 0x0EE = return from subroutine)
+            // 0xNNN: calls machine code at address 0xNNN (implemented: 0x0E0 = display clear, 0x0EE = return from subroutine, and the SUPER-CHIP display instructions)
             0x0 => {
                 match instruction_operands {
                     // 0x00E0 = clear display
@@ -73,6 +207,28 @@ impl super::Chip8 {
                     // 0x0EE = return from subroutine
                     0x0EE => CPUInstruction::Return,
 
+                    // 0x00CN = scroll display down by N pixels
+                    0x0C0..=0x0CF => CPUInstruction::ScrollDown {
+                        rows: CPUInstrTarget::Constant(instruction_operands_list[2]),
+                    },
+
+                    // 0x00DN = scroll display up by N pixels
+                    0x0D0..=0x0DF => CPUInstruction::ScrollUp {
+                        rows: CPUInstrTarget::Constant(instruction_operands_list[2]),
+                    },
+
+                    // 0x00F(B/C) = scroll display (right/left) by 4 pixels
+                    0x0FB => CPUInstruction::ScrollRight,
+                    0x0FC => CPUInstruction::ScrollLeft,
+
+                    // 0x00FD = exit the interpreter
+                    0x0FD => CPUInstruction::Exit,
+
+                    // 0x00F(E/F) = switch to (lo-res/hi-res) mode
+                    0x0FE | 0x0FF => CPUInstruction::SetHighRes {
+                        enabled: instruction_operands == 0x0FF,
+                    },
+
                     // 0xNNN = call machine code at NNN
                     _ => CPUInstruction::CallMachineCode {
                         addr: CPUInstrTarget::Constant(instruction_operands),
@@ -97,6 +253,18 @@ impl super::Chip8 {
                 right: CPUInstrTarget::Constant(instruction_operands & 0xFF),
             },
 
+            // 0x5XY2: saves Vx..Vy to I..I+|x-y| without changing I
+            0x5 if instruction_operands_list[2] == 0x2 => CPUInstruction::RegisterRangeDump {
+                x: CPUInstrTarget::Constant(instruction_operands_list[0]),
+                y: CPUInstrTarget::Constant(instruction_operands_list[1]),
+            },
+
+            // 0x5XY3: loads Vx..Vy from I..I+|x-y| without changing I
+            0x5 if instruction_operands_list[2] == 0x3 => CPUInstruction::RegisterRangeLoad {
+                x: CPUInstrTarget::Constant(instruction_operands_list[0]),
+                y: CPUInstrTarget::Constant(instruction_operands_list[1]),
+            },
+
             // 0x(5/9)XY0: checks if Vx (=/!)= Vy
             0x5 | 0x9 => CPUInstruction::CompareEq {
                 eq: (instruction_type == 0x5),
@@ -145,7 +313,7 @@ impl super::Chip8 {
                 from: CPUInstrTarget::Constant(instruction_operands),
             },
 
-            // 0xBNNN: sets the PC to V0 + NNN, i.e. "special jump"
+            // 0xBNNN: sets the PC to V0 + NNN, i.e. "special jump" (BXNN: Vx + XNN with the jump quirk)
             0xB => CPUInstruction::SpecialJump {
                 offset: CPUInstrTarget::Constant(instruction_operands),
             },
@@ -156,7 +324,7 @@ impl super::Chip8 {
                 from: CPUInstrTarget::RandomNum((instruction_operands & 0xFF) as u8),
             },
 
-            // 0xDXYN: draws sprite at (Vx, Vy) with a height of N pixels
+            // 0xDXYN: draws sprite at (Vx, Vy) with a height of N pixels (DXY0 draws a 16x16 sprite)
             0xD => CPUInstruction::Draw {
                 x_reg: CPUInstrTarget::VRegister(instruction_operands_list[0] as usize),
                 y_reg: CPUInstrTarget::VRegister(instruction_operands_list[1] as usize),
@@ -173,6 +341,17 @@ impl super::Chip8 {
             // 0xFXNN: various
             0xF => {
                 match instruction_operands & 0xFF {
+                    // 0xF000 NNNN: sets I register to the 16-bit address NNNN in the next two bytes
+                    0x00 if instruction_operands == 0x000 => CPUInstruction::LongLoadI,
+
+                    // 0xFN01: selects the bitplanes to draw on (bit mask N)
+                    0x01 => CPUInstruction::SelectPlanes {
+                        planes: CPUInstrTarget::Constant(instruction_operands_list[0]),
+                    },
+
+                    // 0xF002: loads the 16 byte audio pattern from I..I+15
+                    0x02 if instruction_operands == 0x002 => CPUInstruction::LoadAudioPattern,
+
                     // 0xFX(07/0A): sets Vx to the current (delay timer/key pressed)
                     0x07 | 0x0A => CPUInstruction::Assignment {
                         to: CPUInstrTarget::VRegister(instruction_operands_list[0] as usize),
@@ -206,8 +385,19 @@ impl super::Chip8 {
                         from: CPUInstrTarget::SpriteAddress(instruction_operands_list[0] as usize),
                     },
 
+                    // 0xFX30: sets I register to large sprite address for val in Vx
+                    0x30 => CPUInstruction::Assignment {
+                        to: CPUInstrTarget::IRegister,
+                        from: CPUInstrTarget::LargeSpriteAddress(instruction_operands_list[0] as usize),
+                    },
+
+                    // 0xFX3A: sets the audio pitch to Vx
+                    0x3A => CPUInstruction::SetPitch {
+                        x_reg: CPUInstrTarget::VRegister(instruction_operands_list[0] as usize),
+                    },
+
                     // 0xFX33: BCD Vx into I..I+2
-                    0x33 => CPUInstruction::BCD {
+                    0x33 => CPUInstruction::Bcd {
                         x_reg: CPUInstrTarget::VRegister(instruction_operands_list[0] as usize),
                     },
 
@@ -221,6 +411,16 @@ impl super::Chip8 {
                         x: CPUInstrTarget::Constant(instruction_operands_list[0]),
                     },
 
+                    // 0xFX75: saves V0..Vx to the RPL user flags
+                    0x75 => CPUInstruction::SaveFlags {
+                        x: CPUInstrTarget::Constant(instruction_operands_list[0]),
+                    },
+
+                    // 0xFX85: loads V0..Vx from the RPL user flags
+                    0x85 => CPUInstruction::LoadFlags {
+                        x: CPUInstrTarget::Constant(instruction_operands_list[0]),
+                    },
+
                     _ => CPUInstruction::Unknown { opcode }
                 }
             }
@@ -230,12 +430,211 @@ impl super::Chip8 {
         }
     }
 
+    // converts a CPUInstruction back into its numerical opcode (the inverse of opcode_to_instruction). returns None for
+    // instructions that have no encoding, like a timer being assigned a constant. F000 NNNN only gives the F000 part
+    pub fn instruction_to_opcode(instruction: &CPUInstruction) -> Option<u16> {
+        use CPUInstrTarget::*;
+
+        // checks that operands fit in their fields
+        let nibble = |val: usize| (val < 0x10).then_some(val as u16);
+        let byte = |val: u16| (val < 0x100).then_some(val);
+        let addr = |val: u16| (val < 0x1000).then_some(val);
+
+        let opcode = match *instruction {
+            CPUInstruction::CallMachineCode { addr: Constant(target) } => addr(target)?,
+            CPUInstruction::ClearDisplay => 0x00E0,
+            CPUInstruction::Return => 0x00EE,
+            CPUInstruction::ScrollDown { rows: Constant(rows) } => 0x00C0 | nibble(rows as usize)?,
+            CPUInstruction::ScrollUp { rows: Constant(rows) } => 0x00D0 | nibble(rows as usize)?,
+            CPUInstruction::ScrollRight => 0x00FB,
+            CPUInstruction::ScrollLeft => 0x00FC,
+            CPUInstruction::Exit => 0x00FD,
+            CPUInstruction::SetHighRes { enabled } => if enabled { 0x00FF } else { 0x00FE },
+            CPUInstruction::Jump { addr: Constant(target) } => 0x1000 | addr(target)?,
+            CPUInstruction::CallSubroutine { addr: Constant(target) } => 0x2000 | addr(target)?,
+
+            CPUInstruction::CompareEq { eq, left: VRegister(x), right: Constant(val) } => {
+                (if eq { 0x3000 } else { 0x4000 }) | nibble(x)? << 8 | byte(val)?
+            },
+            CPUInstruction::CompareEq { eq, left: VRegister(x), right: VRegister(y) } => {
+                (if eq { 0x5000 } else { 0x9000 }) | nibble(x)? << 8 | nibble(y)? << 4
+            },
+            CPUInstruction::CompareEq { eq, left: IsKeyInVRegPressed(x), right: True } => {
+                0xE000 | nibble(x)? << 8 | if eq { 0x9E } else { 0xA1 }
+            },
+
+            CPUInstruction::Assignment { to, from } => match (to, from) {
+                (VRegister(x), Constant(val)) => 0x6000 | nibble(x)? << 8 | byte(val)?,
+                (IRegister, Constant(target)) => 0xA000 | addr(target)?,
+                (VRegister(x), RandomNum(mask)) => 0xC000 | nibble(x)? << 8 | mask as u16,
+                (VRegister(x), CurrentDelayTimer) => 0xF007 | nibble(x)? << 8,
+                (VRegister(x), CurrentKeyPressed) => 0xF00A | nibble(x)? << 8,
+                (CurrentDelayTimer, VRegister(x)) => 0xF015 | nibble(x)? << 8,
+                (CurrentSoundTimer, VRegister(x)) => 0xF018 | nibble(x)? << 8,
+                (IRegister, SpriteAddress(x)) => 0xF029 | nibble(x)? << 8,
+                (IRegister, LargeSpriteAddress(x)) => 0xF030 | nibble(x)? << 8,
+                _ => return None,
+            },
+
+            CPUInstruction::ALUOperation { op, left, right } => match (op, left, right) {
+                (ALUOperations::Add { update_vf: false }, VRegister(x), Constant(val)) => 0x7000 | nibble(x)? << 8 | byte(val)?,
+                (ALUOperations::Add { update_vf: false }, IRegister, VRegister(x)) => 0xF01E | nibble(x)? << 8,
+                (op, VRegister(x), VRegister(y)) => {
+                    let n = match op {
+                        ALUOperations::Assign => 0x0,
+                        ALUOperations::Or => 0x1,
+                        ALUOperations::And => 0x2,
+                        ALUOperations::Xor => 0x3,
+                        ALUOperations::Add { update_vf: true } => 0x4,
+                        ALUOperations::Subtract { update_vf: true } => 0x5,
+                        ALUOperations::ShiftRight { update_vf: true } => 0x6,
+                        ALUOperations::SubtractFlipped { update_vf: true } => 0x7,
+                        ALUOperations::ShiftLeft { update_vf: true } => 0xE,
+                        _ => return None,
+                    };
+
+                    0x8000 | nibble(x)? << 8 | nibble(y)? << 4 | n
+                },
+                _ => return None,
+            },
+
+            CPUInstruction::SpecialJump { offset: Constant(target) } => 0xB000 | addr(target)?,
+            CPUInstruction::Draw { x_reg: VRegister(x), y_reg: VRegister(y), height_px: Constant(height) } => {
+                0xD000 | nibble(x)? << 8 | nibble(y)? << 4 | nibble(height as usize)?
+            },
+            CPUInstruction::Bcd { x_reg: VRegister(x) } => 0xF033 | nibble(x)? << 8,
+            CPUInstruction::RegisterDump { x: Constant(x) } => 0xF055 | nibble(x as usize)? << 8,
+            CPUInstruction::RegisterLoad { x: Constant(x) } => 0xF065 | nibble(x as usize)? << 8,
+            CPUInstruction::RegisterRangeDump { x: Constant(x), y: Constant(y) } => 0x5002 | nibble(x as usize)? << 8 | nibble(y as usize)? << 4,
+            CPUInstruction::RegisterRangeLoad { x: Constant(x), y: Constant(y) } => 0x5003 | nibble(x as usize)? << 8 | nibble(y as usize)? << 4,
+            CPUInstruction::LongLoadI => 0xF000,
+            CPUInstruction::SelectPlanes { planes: Constant(planes) } => 0xF001 | nibble(planes as usize)? << 8,
+            CPUInstruction::LoadAudioPattern => 0xF002,
+            CPUInstruction::SetPitch { x_reg: VRegister(x) } => 0xF03A | nibble(x)? << 8,
+            CPUInstruction::SaveFlags { x: Constant(x) } => 0xF075 | nibble(x as usize)? << 8,
+            CPUInstruction::LoadFlags { x: Constant(x) } => 0xF085 | nibble(x as usize)? << 8,
+            CPUInstruction::Unknown { opcode } => opcode,
+
+            _ => return None,
+        };
+
+        Some(opcode)
+    }
+
+    // works out the memory an instruction will read and write if it is executed now (not counting fetching the instruction
+    // itself), as ranges of addresses that are empty if there is no access. used by watchpoints and coverage tracking
+    pub fn memory_accesses(&self, instruction: &CPUInstruction) -> MemoryAccesses {
+        let i = *self.registers.get_i_register() as usize;
+        let reg_count = |x: &CPUInstrTarget, y: Option<&CPUInstrTarget>| match (x, y) {
+            (CPUInstrTarget::Constant(x), None) => *x as usize + 1,
+            (CPUInstrTarget::Constant(x), Some(CPUInstrTarget::Constant(y))) => x.abs_diff(*y) as usize + 1,
+            _ => 0,
+        };
+
+        // the memory mapped stack is the only other memory that gets touched
+        let stack_top = MAPPED_STACK_ADDR + 2 * self.stack.get_depth();
+        let mapped_stack = self.quirks.memory_mapped_stack;
+
+        let (reads, writes) = match instruction {
+            CPUInstruction::Draw { height_px: CPUInstrTarget::Constant(height), .. } => {
+                let sprite_size = if *height == 0 { 32 } else { *height as usize };
+                (i..i + sprite_size * self.output.selected_plane_indices().len(), 0..0)
+            },
+            CPUInstruction::Bcd { .. } => (0..0, i..i + 3),
+            CPUInstruction::RegisterDump { x } => (0..0, i..i + reg_count(x, None)),
+            CPUInstruction::RegisterLoad { x } => (i..i + reg_count(x, None), 0..0),
+            CPUInstruction::RegisterRangeDump { x, y } => (0..0, i..i + reg_count(x, Some(y))),
+            CPUInstruction::RegisterRangeLoad { x, y } => (i..i + reg_count(x, Some(y)), 0..0),
+            CPUInstruction::LoadAudioPattern => (i..i + 16, 0..0),
+            CPUInstruction::CallSubroutine { .. } if mapped_stack => (0..0, stack_top..stack_top + 2),
+            CPUInstruction::Return if mapped_stack && stack_top > MAPPED_STACK_ADDR => (stack_top - 2..stack_top, 0..0),
+            _ => (0..0, 0..0),
+        };
+
+        MemoryAccesses { reads, writes }
+    }
+
+    // applies the fault policy to a memory address, wrapping it around the address space if allowed
+    fn resolve_address(&self, addr: usize) -> Result<usize, Chip8ErrorReason> {
+        let size = self.get_address_space_size();
+
+        if addr < size {
+            Ok(addr)
+        } else if self.fault_policies.memory_out_of_bounds == FaultPolicy::Wrap {
+            Ok(addr % size)
+        } else {
+            Err(Chip8ErrorReason::MemoryOutOfBounds { addr })
+        }
+    }
+
+    // applies the fault policy to a V register index
+    fn resolve_register(&self, reg: usize) -> Result<usize, Chip8ErrorReason> {
+        if reg < 16 {
+            Ok(reg)
+        } else if self.fault_policies.invalid_register == FaultPolicy::Wrap {
+            Ok(reg % 16)
+        } else {
+            Err(Chip8ErrorReason::InvalidRegister { reg })
+        }
+    }
+
+    // applies the fault policy to a key number
+    fn resolve_key(&self, key: usize) -> Result<usize, Chip8ErrorReason> {
+        if key < 16 {
+            Ok(key)
+        } else if self.fault_policies.invalid_key == FaultPolicy::Wrap {
+            Ok(key % 16)
+        } else {
+            Err(Chip8ErrorReason::InvalidKey { key })
+        }
+    }
+
+    // reads a byte of memory, applying the fault policy to the address
+    fn read_memory(&self, addr: usize) -> Result<u8, Chip8ErrorReason> {
+        let addr = self.resolve_address(addr)?;
+        self.memory.get_memory_at(addr).copied()
+    }
+
+    // writes a byte of memory, applying the fault policy to the address
+    fn write_memory(&mut self, addr: usize, val: u8) -> Result<(), Chip8ErrorReason> {
+        let addr = self.resolve_address(addr)?;
+        *self.memory.get_memory_at_mut(addr)? = val;
+
+        Ok(())
+    }
+
+    // skips the next instruction, which may be the 4 byte F000 NNNN instruction
+    fn skip_next_instruction(&mut self) -> Result<(), Chip8ErrorReason> {
+        let pc = *self.registers.get_pc_register() as usize;
+        let next_opcode = ((self.read_memory(pc)? as u16) << 8) + self.read_memory(pc + 1)? as u16;
+
+        for _ in 0..Chip8::instruction_length(next_opcode) / 2 {
+            self.registers.skip_next_instr();
+        }
+
+        Ok(())
+    }
+
+    // reads a V register, applying the fault policy to the register index
+    fn read_v_register(&self, reg: usize) -> Result<u8, Chip8ErrorReason> {
+        let reg = self.resolve_register(reg)?;
+        self.registers.get_v_register(reg).copied()
+    }
+
+    // writes a V register, applying the fault policy to the register index
+    fn write_v_register(&mut self, reg: usize, val: u8) -> Result<(), Chip8ErrorReason> {
+        let reg = self.resolve_register(reg)?;
+        *self.registers.get_v_register_mut(reg)? = val;
+
+        Ok(())
+    }
+
     // evaluates a CPUInstrTarget immutably, but still requires mutable CPU instance
-    pub fn evaluate_cpu_instr_target(&mut self, target: &CPUInstrTarget) -> usize {
-        match target {
-            CPUInstrTarget::IRegister => self.registers.get_i_register().clone() as usize,
-            CPUInstrTarget::VRegister(reg) => self.registers.get_v_register(*reg).clone() as usize,
-            CPUInstrTarget::MemoryAddress(addr) => self.memory.get_memory_at(*addr).clone() as usize,
+    pub fn evaluate_cpu_instr_target(&mut self, target: &CPUInstrTarget) -> Result<usize, Chip8ErrorReason> {
+        let val = match target {
+            CPUInstrTarget::IRegister => *self.registers.get_i_register() as usize,
+            CPUInstrTarget::VRegister(reg) => self.read_v_register(*reg)? as usize,
+            CPUInstrTarget::MemoryAddress(addr) => self.read_memory(*addr)? as usize,
             CPUInstrTarget::Constant(val) => *val as usize,
             CPUInstrTarget::CurrentKeyPressed => {
                 let key_opt = self.input.get_current_key();
@@ -243,201 +642,404 @@ impl super::Chip8 {
                 if let Some(key) = key_opt {
                     key
                 } else {
-                    *self.registers.get_pc_register_mut() -= 2;
+                    self.registers.repeat_instr();
                     0
                 }
             },
-            CPUInstrTarget::IsKeyInVRegPressed(reg) => if self.input.get_keys_status()[self.registers.get_v_register(*reg).clone() as usize] { 1 } else { 0 },
-            CPUInstrTarget::CurrentDelayTimer => self.timers.get_delay().clone() as usize,
-            CPUInstrTarget::CurrentSoundTimer => self.timers.get_sound().clone() as usize,
-            CPUInstrTarget::SpriteAddress(sprite) => (self.registers.get_v_register(*sprite).clone() as usize * 5),
-            CPUInstrTarget::RandomNum(mask) => (rand::random::<u8>() & mask) as usize,
+            CPUInstrTarget::IsKeyInVRegPressed(reg) => {
+                let key = self.resolve_key(self.read_v_register(*reg)? as usize)?;
+
+                if self.input.get_keys_status()[key] { 1 } else { 0 }
+            },
+            CPUInstrTarget::CurrentDelayTimer => *self.timers.get_delay() as usize,
+            CPUInstrTarget::CurrentSoundTimer => *self.timers.get_sound() as usize,
+            CPUInstrTarget::SpriteAddress(sprite) => self.read_v_register(*sprite)? as usize * 5,
+            CPUInstrTarget::LargeSpriteAddress(sprite) => BIG_FONT_ADDR + (self.read_v_register(*sprite)? as usize & 0xF) * 10,
+            CPUInstrTarget::RandomNum(mask) => (self.random.next_byte() & mask) as usize,
             CPUInstrTarget::True => 1,
-        }
+        };
+
+        Ok(val)
     }
 
     // evaluates a CPUInstrTarget mutably (not all types are valid)
-    pub fn set_cpu_instr_target(&mut self, target: CPUInstrTarget, val: usize) {
+    pub fn set_cpu_instr_target(&mut self, target: CPUInstrTarget, val: usize) -> Result<(), Chip8ErrorReason> {
         match target {
-            CPUInstrTarget::IRegister => *self.registers.get_i_register_mut() = (val as u16),
-            CPUInstrTarget::VRegister(reg) => *self.registers.get_v_register_mut(reg) = (val as u8),
-            CPUInstrTarget::MemoryAddress(addr) => *self.memory.get_memory_at_mut(addr) = (val as u8),
-            CPUInstrTarget::CurrentDelayTimer => *self.timers.get_delay_mut() = (val as u8),
-            CPUInstrTarget::CurrentSoundTimer => *self.timers.get_sound_mut() = (val as u8),
+            CPUInstrTarget::IRegister => *self.registers.get_i_register_mut() = val as u16,
+            CPUInstrTarget::VRegister(reg) => self.write_v_register(reg, val as u8)?,
+            CPUInstrTarget::MemoryAddress(addr) => self.write_memory(addr, val as u8)?,
+            CPUInstrTarget::CurrentDelayTimer => *self.timers.get_delay_mut() = val as u8,
+            CPUInstrTarget::CurrentSoundTimer => *self.timers.get_sound_mut() = val as u8,
 
-            _ => panic!("Attempted to assign {val} to an immutable value: {target:?}"),
+            _ => return Err(Chip8ErrorReason::ImmutableTarget),
         }
+
+        Ok(())
     }
 
     // executes a CPUInstruction
-    fn execute_instruction(&mut self, instr: CPUInstruction) {
+    fn execute_instruction(&mut self, instr: CPUInstruction) -> Result<StepOutcome, Chip8ErrorReason> {
         match instr {
-            // calls machine code at given address
-            CPUInstruction::CallMachineCode { addr } => panic!("Machine code operations are not supported. Panicked at: {instr:?}"),
+            // calls machine code at given address, which is not supported
+            CPUInstruction::CallMachineCode { addr } => {
+                let addr = self.evaluate_cpu_instr_target(&addr)?;
+                return Err(Chip8ErrorReason::MachineCodeCall { addr: addr as u16 });
+            },
 
             // clear display
             CPUInstruction::ClearDisplay => self.output.clear_display(),
 
+            // scrolls the display down by a number of pixels
+            CPUInstruction::ScrollDown { rows } => {
+                let rows = self.evaluate_cpu_instr_target(&rows)?;
+                self.output.scroll_down(rows);
+            },
+
+            // scrolls the display up by a number of pixels
+            CPUInstruction::ScrollUp { rows } => {
+                let rows = self.evaluate_cpu_instr_target(&rows)?;
+                self.output.scroll_up(rows);
+            },
+
+            // scrolls the display right by 4 pixels
+            CPUInstruction::ScrollRight => self.output.scroll_right(4),
+
+            // scrolls the display left by 4 pixels
+            CPUInstruction::ScrollLeft => self.output.scroll_left(4),
+
+            // exits the interpreter, the PC stays on the exit instruction so nothing else gets executed
+            CPUInstruction::Exit => {
+                self.registers.repeat_instr();
+                return Ok(StepOutcome::Exited);
+            },
+
+            // switches between lo-res and hi-res mode
+            CPUInstruction::SetHighRes { enabled } => self.output.set_hires(enabled),
+
             // returns from subroutine by popping return address from stack and jumping there
             CPUInstruction::Return => {
-                let return_address = self.memory.pop_from_stack_u16();
+                let return_address = self.stack.pop(&self.memory, self.quirks.memory_mapped_stack)?;
                 self.registers.jump_to(return_address);
             },
 
             // jumps (sets PC) to given address
             CPUInstruction::Jump { addr } => {
-                let addr = self.evaluate_cpu_instr_target(&addr);
+                let addr = self.evaluate_cpu_instr_target(&addr)?;
                 self.registers.jump_to(addr as u16);
             }
 
             // calls subroutine at given address by pushing current PC to stack and jumping to subroutine addr
             CPUInstruction::CallSubroutine { addr } => {
-                let current_pc = self.registers.get_pc_register().clone();
-                self.memory.push_to_stack_u16(current_pc);
+                let current_pc = *self.registers.get_pc_register();
+                self.stack.push(&mut self.memory, current_pc, self.quirks.stack_depth, self.quirks.memory_mapped_stack)?;
 
-                let jump_addr = self.evaluate_cpu_instr_target(&addr);
+                let jump_addr = self.evaluate_cpu_instr_target(&addr)?;
                 self.registers.jump_to(jump_addr as u16);
             },
 
             // compares the two given arguments, if eq is true, skip the next instruction if they are equal (do nothing if not). if eq is false, skip the next instruction if they are not equal (do nothing if equal)
             CPUInstruction::CompareEq { eq, left, right } => {
-                let left_val = self.evaluate_cpu_instr_target(&left);
-                let right_val = self.evaluate_cpu_instr_target(&right);
+                let left_val = self.evaluate_cpu_instr_target(&left)?;
+                let right_val = self.evaluate_cpu_instr_target(&right)?;
 
                 let skip = if eq { left_val == right_val } else { left_val != right_val };
 
                 if skip {
-                    self.registers.skip_next_instr();
+                    self.skip_next_instruction()?;
                 }
             },
 
-            // assigns a given value to a given target
+            // assigns a given value to a given target, waiting (by not advancing the PC) if a key is needed but none is pressed
             CPUInstruction::Assignment { to, from } => {
-                let from_val = self.evaluate_cpu_instr_target(&from);
-                self.set_cpu_instr_target(to, from_val);
+                if let CPUInstrTarget::CurrentKeyPressed = from {
+                    if self.input.get_current_key().is_none() {
+                        self.registers.repeat_instr();
+                        return Ok(StepOutcome::WaitingForKey);
+                    }
+                }
+
+                let from_val = self.evaluate_cpu_instr_target(&from)?;
+                self.set_cpu_instr_target(to, from_val)?;
             }
 
             // performs an ALU operation
             CPUInstruction::ALUOperation { op, left, right } => {
-                let left_val = self.evaluate_cpu_instr_target(&left) as isize;
-                let right_val = self.evaluate_cpu_instr_target(&right) as isize;
+                let left_val = self.evaluate_cpu_instr_target(&left)? as isize;
+                let right_val = self.evaluate_cpu_instr_target(&right)? as isize;
+
+                // the shift quirk decides whether Vx or Vy gets shifted, and the VF reset quirk decides whether the logic operations clear VF
+                let shift_val = if self.quirks.shift_uses_vy { right_val } else { left_val };
+                let vf_reset = self.quirks.vf_reset;
 
                 let (mut result, update_vf, new_vf) = match op {
                     ALUOperations::Assign => (right_val, false, false),
                     ALUOperations::Add { update_vf } => (left_val + right_val, update_vf, (left_val+right_val) > 0xFF),
                     ALUOperations::Subtract { update_vf } => (left_val - right_val, update_vf, left_val >= right_val),
                     ALUOperations::SubtractFlipped { update_vf } => (right_val - left_val, update_vf, right_val >= left_val),
-                    ALUOperations::Or => (left_val | right_val, true, false),
-                    ALUOperations::And => (left_val & right_val, true, false),
-                    ALUOperations::Xor => (left_val ^ right_val, true, false),
-                    ALUOperations::ShiftRight { update_vf } => (left_val >> 1, update_vf, (left_val & 1) == 1),
-                    ALUOperations::ShiftLeft { update_vf } => (left_val << 1, update_vf, (left_val & 0x80) > 0),
-                    ALUOperations::Unknown => panic!("Unknown ALU operation, panicked at instruction: {instr:?}"),
+                    ALUOperations::Or => (left_val | right_val, vf_reset, false),
+                    ALUOperations::And => (left_val & right_val, vf_reset, false),
+                    ALUOperations::Xor => (left_val ^ right_val, vf_reset, false),
+                    ALUOperations::ShiftRight { update_vf } => (shift_val >> 1, update_vf, (shift_val & 1) == 1),
+                    ALUOperations::ShiftLeft { update_vf } => ((shift_val << 1) & 0xFF, update_vf, (shift_val & 0x80) > 0),
+                    ALUOperations::Unknown => return Err(Chip8ErrorReason::UnknownALUOperation),
                 };
 
                 while result < 0 {
                     result += 256;
                 }
 
-                self.set_cpu_instr_target(left, result as usize);
+                self.set_cpu_instr_target(left, result as usize)?;
 
                 if update_vf {
-                    *self.registers.get_v_register_mut(0xF) = if new_vf { 1 } else { 0 };
+                    *self.registers.get_v_register_mut(0xF)? = if new_vf { 1 } else { 0 };
                 }
             },
 
-            // performs a "special jump": sets PC to V0 + a given number
+            // performs a "special jump": sets PC to V0 + a given number (or Vx + the number, where x is its highest nibble, with the jump quirk)
             CPUInstruction::SpecialJump { offset } => {
-                let v0_val = self.registers.get_v_register(0).clone() as usize;
-                let offset_val = self.evaluate_cpu_instr_target(&offset);
+                let offset_val = self.evaluate_cpu_instr_target(&offset)?;
+                let reg = if self.quirks.jump_with_vx { (offset_val & 0xF00) >> 8 } else { 0 };
+                let reg_val = self.read_v_register(reg)? as usize;
 
-                *self.registers.get_pc_register_mut() = (v0_val + offset_val) as u16;
+                *self.registers.get_pc_register_mut() = (reg_val + offset_val) as u16;
             }
 
-            // draws a sprite to the screen at (Vx, Vy) with a height of N pixels. VF gets set to 1 if any pixels were flipped from white to black, and 0 otherwise
+            // draws a sprite to the screen at (Vx, Vy) with a height of N pixels, or a 16x16 sprite if N is 0. VF gets set to 1 if any pixels were flipped from white to black, and 0 otherwise.
+            // in hi-res mode VF instead gets set to the number of rows where pixels were flipped from white to black
             CPUInstruction::Draw { x_reg, y_reg, height_px } => {
+                // with the display wait quirk, only one sprite can be drawn per frame
+                if self.quirks.display_wait {
+                    if !self.vblank {
+                        self.registers.repeat_instr();
+                        return Ok(StepOutcome::WaitingForVBlank);
+                    }
+
+                    self.vblank = false;
+                }
+
                 // evalulate instruction targets
-                let x_reg_val = self.evaluate_cpu_instr_target(&x_reg);
-                let y_reg_val = self.evaluate_cpu_instr_target(&y_reg);
-                let height_px_val = self.evaluate_cpu_instr_target(&height_px);
+                let x_reg_val = self.evaluate_cpu_instr_target(&x_reg)?;
+                let y_reg_val = self.evaluate_cpu_instr_target(&y_reg)?;
+                let mut height_px_val = self.evaluate_cpu_instr_target(&height_px)?;
+
+                if height_px_val >= 16 && self.fault_policies.invalid_sprite == FaultPolicy::Wrap {
+                    height_px_val %= 16;
+                }
+
+                // draw a sprite on each selected plane, the sprite data for each plane comes right after the data for the previous one
+                let mut i_reg_val = self.resolve_address(*self.registers.get_i_register() as usize)?;
+                let mut collided_rows = 0;
 
-                // create sprite instance
-                let i_reg_val = self.registers.get_i_register().clone() as usize;
-                let sprite = Chip8Sprite::new(&self.memory, i_reg_val, height_px_val);
+                for plane in self.output.selected_plane_indices() {
+                    // create sprite instance
+                    let (sprite, sprite_size) = if height_px_val == 0 {
+                        (Chip8Sprite::new_large(&self.memory, i_reg_val)?, 32)
+                    } else {
+                        (Chip8Sprite::new(&self.memory, i_reg_val, height_px_val)?, height_px_val)
+                    };
 
-                // draw sprite
-                let new_vf_value = self.output.draw_sprite_on_display(x_reg_val, y_reg_val, sprite);
+                    // draw sprite
+                    let plane_collided_rows = self.output.draw_sprite_on_plane(plane, x_reg_val, y_reg_val, sprite, self.quirks.clip_sprites);
+                    collided_rows = collided_rows.max(plane_collided_rows);
+
+                    i_reg_val += sprite_size;
+                }
 
                 // update VF
-                *self.registers.get_v_register_mut(0xF) = if new_vf_value { 1 } else { 0 };
+                let new_vf_value = if self.output.is_hires() { collided_rows } else { collided_rows.min(1) };
+                *self.registers.get_v_register_mut(0xF)? = new_vf_value as u8;
             },
 
             // decomposes Vx into BCD at addresses I..I+2
-            CPUInstruction::BCD { x_reg } => {
+            CPUInstruction::Bcd { x_reg } => {
                 // extract digits
-                let mut x_reg_val = self.evaluate_cpu_instr_target(&x_reg);
+                let mut x_reg_val = self.evaluate_cpu_instr_target(&x_reg)?;
                 let mut digits = [0; 3];
-                
-                for i in 0..3 {
-                    digits[i] = x_reg_val % 10;
+
+                for digit in &mut digits {
+                    *digit = x_reg_val % 10;
                     x_reg_val /= 10;
                 }
 
                 // write to memory
-                let i_reg_val = self.registers.get_i_register().clone() as usize;
-                *self.memory.get_memory_at_mut(i_reg_val) = digits[2] as u8;
-                *self.memory.get_memory_at_mut(i_reg_val + 1) = digits[1] as u8;
-                *self.memory.get_memory_at_mut(i_reg_val + 2) = digits[0] as u8;
+                let i_reg_val = *self.registers.get_i_register() as usize;
+                self.write_memory(i_reg_val, digits[2] as u8)?;
+                self.write_memory(i_reg_val + 1, digits[1] as u8)?;
+                self.write_memory(i_reg_val + 2, digits[0] as u8)?;
             },
 
             // dumps V0..Vx starting at address I
             CPUInstruction::RegisterDump { x } => {
-                let x_val = self.evaluate_cpu_instr_target(&x);
+                let x_val = self.evaluate_cpu_instr_target(&x)?;
 
                 // get starting address (I register)
-                let i_reg_val = self.registers.get_i_register().clone() as usize;
+                let i_reg_val = *self.registers.get_i_register() as usize;
 
                 // dump registers to memory
                 for i in 0..=x_val {
-                    let vi_val = self.registers.get_v_register(i).clone();
-                    *self.memory.get_memory_at_mut(i + i_reg_val) = vi_val;
+                    let vi_val = self.read_v_register(i)?;
+                    self.write_memory(i + i_reg_val, vi_val)?;
+                }
+
+                if self.quirks.load_store_increments_i {
+                    *self.registers.get_i_register_mut() = (i_reg_val + x_val + 1) as u16;
                 }
             },
 
             // loads V0..Vx starting at address I
             CPUInstruction::RegisterLoad { x } => {
-                let x_val = self.evaluate_cpu_instr_target(&x);
+                let x_val = self.evaluate_cpu_instr_target(&x)?;
 
                 // get starting address (I register)
-                let i_reg_val = self.registers.get_i_register().clone() as usize;
+                let i_reg_val = *self.registers.get_i_register() as usize;
 
                 // load registers from memory
                 for i in 0..=x_val {
-                    let mem_val = self.memory.get_memory_at(i_reg_val + i).clone();
-                    *self.registers.get_v_register_mut(i) = mem_val;
+                    let mem_val = self.read_memory(i_reg_val + i)?;
+                    self.write_v_register(i, mem_val)?;
+                }
+
+                if self.quirks.load_store_increments_i {
+                    *self.registers.get_i_register_mut() = (i_reg_val + x_val + 1) as u16;
+                }
+            },
+
+            // dumps Vx..Vy (in either order) starting at address I
+            CPUInstruction::RegisterRangeDump { x, y } => {
+                let x_val = self.evaluate_cpu_instr_target(&x)?;
+                let y_val = self.evaluate_cpu_instr_target(&y)?;
+                let i_reg_val = *self.registers.get_i_register() as usize;
+
+                for i in 0..=x_val.abs_diff(y_val) {
+                    let reg = if x_val <= y_val { x_val + i } else { x_val - i };
+                    let reg_val = self.read_v_register(reg)?;
+                    self.write_memory(i_reg_val + i, reg_val)?;
+                }
+            },
+
+            // loads Vx..Vy (in either order) starting at address I
+            CPUInstruction::RegisterRangeLoad { x, y } => {
+                let x_val = self.evaluate_cpu_instr_target(&x)?;
+                let y_val = self.evaluate_cpu_instr_target(&y)?;
+                let i_reg_val = *self.registers.get_i_register() as usize;
+
+                for i in 0..=x_val.abs_diff(y_val) {
+                    let reg = if x_val <= y_val { x_val + i } else { x_val - i };
+                    let mem_val = self.read_memory(i_reg_val + i)?;
+                    self.write_v_register(reg, mem_val)?;
+                }
+            },
+
+            // sets I to the 16-bit address following the instruction, and moves the PC past it
+            CPUInstruction::LongLoadI => {
+                let pc = *self.registers.get_pc_register() as usize;
+                let addr = ((self.read_memory(pc)? as u16) << 8) + self.read_memory(pc + 1)? as u16;
+
+                *self.registers.get_i_register_mut() = addr;
+                self.registers.skip_next_instr();
+            },
+
+            // selects the planes to draw on
+            CPUInstruction::SelectPlanes { planes } => {
+                let planes = self.evaluate_cpu_instr_target(&planes)?;
+                self.output.set_selected_planes(planes as u8);
+            },
+
+            // loads the 16 byte audio pattern starting at address I
+            CPUInstruction::LoadAudioPattern => {
+                let i_reg_val = *self.registers.get_i_register() as usize;
+                let mut pattern = [0; 16];
+
+                for (i, byte) in pattern.iter_mut().enumerate() {
+                    *byte = self.read_memory(i_reg_val + i)?;
+                }
+
+                *self.audio.get_pattern_mut() = Some(pattern);
+            },
+
+            // sets the audio pitch
+            CPUInstruction::SetPitch { x_reg } => {
+                let pitch = self.evaluate_cpu_instr_target(&x_reg)?;
+                *self.audio.get_pitch_mut() = pitch as u8;
+            },
+
+            // saves V0..Vx to the RPL user flags
+            CPUInstruction::SaveFlags { x } => {
+                let x_val = self.evaluate_cpu_instr_target(&x)?;
+                let mut flags = self.rpl_storage.load();
+
+                for (i, flag) in flags.iter_mut().enumerate().take(x_val + 1) {
+                    *flag = self.read_v_register(i)?;
+                }
+
+                self.rpl_storage.save(&flags);
+            },
+
+            // loads V0..Vx from the RPL user flags
+            CPUInstruction::LoadFlags { x } => {
+                let x_val = self.evaluate_cpu_instr_target(&x)?;
+                let flags = self.rpl_storage.load();
+
+                for (i, &flag) in flags.iter().enumerate().take(x_val + 1) {
+                    self.write_v_register(i, flag)?;
                 }
             },
 
             // unknown instruction
-            CPUInstruction::Unknown { opcode } => panic!("Unknown instruction: {opcode:4X}."),
+            CPUInstruction::Unknown { .. } => return Err(Chip8ErrorReason::UnknownInstruction),
         }
+
+        Ok(StepOutcome::Executed)
     }
 
-    // executes the next instruction (instruction at PC)
-    pub fn execute_next_instruction(&mut self) {
+    // executes the next instruction (instruction at PC). faults are handled according to the fault policies: halting leaves the PC on the faulting instruction and returns the error, skipping moves on to the next instruction
+    pub fn execute_next_instruction(&mut self) -> Result<StepOutcome, Chip8Error> {
         // first, read the instruction opcode at PC and convert it into a CPUInstruction
-        let pc = self.registers.get_pc_register().clone() as usize;
-        let opcode = self.memory.get_memory_at_u16(pc);
+        // a fetch fault (the PC outside the address space) goes through the fault policies like any other fault, with
+        // the opcode counted as 0
+        let pc = *self.registers.get_pc_register();
+        let fetched = self.read_memory(pc as usize)
+            .and_then(|higher| Ok(((higher as u16) << 8) + self.read_memory(pc as usize + 1)? as u16));
+        let opcode = *fetched.as_ref().unwrap_or(&0);
         let instruction = Chip8::opcode_to_instruction(opcode);
 
+        // the tracer compares against the state before the instruction, which is only captured while tracing
+        let trace_snapshot = self.tracer.is_some().then(|| TraceSnapshot::capture(self, &instruction));
+        let cycles = self.instruction_cycles(&instruction);
 
-        // println!("{opcode:4X}: {instruction:?}");
+        // increase PC by 2 before executing next instruction as to not interfere with jumps (wrapping around the 64K
+        // address space like XO-CHIP, the PC can reach 0xFFFE with extended memory)
+        self.registers.skip_next_instr();
 
+        // now, execute that instruction
+        let outcome = match fetched.and_then(|_| self.execute_instruction(instruction)) {
+            Ok(outcome) => outcome,
+            Err(reason) => {
+                let error = Chip8Error { pc, opcode, reason };
+
+                match self.fault_policies.get_policy(&reason) {
+                    FaultPolicy::Halt => {
+                        self.registers.jump_to(pc);
+                        return Err(error);
+                    },
+                    FaultPolicy::Skip | FaultPolicy::Wrap => StepOutcome::Skipped(error),
+                }
+            },
+        };
+
+        if let Some(snapshot) = trace_snapshot {
+            let entry = TraceEntry::new(self, pc, opcode, instruction, &snapshot);
+            if let Some(tracer) = self.tracer.as_mut() {
+                tracer.record(entry);
+            }
+        }
 
-        // increase PC by 2 before executing next instruction as to not interfere with jumps
-        *self.registers.get_pc_register_mut() += 2;
+        self.instruction_count += 1;
 
-        // now, execute that instruction
-        self.execute_instruction(instruction);
+        let skipped = matches!(instruction, CPUInstruction::CompareEq { .. }) && *self.registers.get_pc_register() != pc.wrapping_add(2);
+        self.charge_cycles(cycles, skipped, &outcome);
+        self.update_buzzer();
+
+        Ok(outcome)
     }
-}
\ No newline at end of file
+}
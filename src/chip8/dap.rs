@@ -0,0 +1,581 @@
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::sync::mpsc;
+use std::thread;
+use std::time;
+use crate::json::Json;
+use super::Chip8;
+use super::asm::{self, Assembled};
+use super::debugger::{Breakpoint, Chip8Debugger, Condition, StopReason, RUN_LIMIT};
+use super::quirks::Quirks;
+
+// the only thread there is
+const THREAD_ID: u64 = 1;
+
+// variable references of the scopes
+const REGISTERS_REFERENCE: u64 = 1;
+const TIMERS_REFERENCE: u64 = 2;
+const KEYPAD_REFERENCE: u64 = 3;
+
+// how long a frame lasts while running
+const FRAME_DURATION: time::Duration = time::Duration::from_micros(16_667);
+
+// reads a message framed with a Content-Length header, returning None at the end of the input
+fn read_message(reader: &mut impl BufRead) -> io::Result<Option<Json>> {
+    let mut length = None;
+
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+
+        let line = line.trim_end();
+        if line.is_empty() {
+            if length.is_some() {
+                break;
+            }
+            continue;
+        }
+
+        if let Some((name, val)) = line.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                length = val.trim().parse::<usize>().ok();
+            }
+        }
+    }
+
+    let mut body = vec![0; length.unwrap_or(0)];
+    reader.read_exact(&mut body)?;
+
+    Json::parse(&String::from_utf8_lossy(&body))
+        .map(Some)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))
+}
+
+// encodes bytes as base64, which is how readMemory returns them
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::new();
+
+    for chunk in bytes.chunks(3) {
+        let bits = chunk.iter().enumerate().fold(0u32, |bits, (i, byte)| bits | (*byte as u32) << (16 - 8 * i));
+
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(bits >> (18 - 6 * i) & 0x3F) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+
+    out
+}
+
+// parses a memory reference, which is just an address (hex with 0x, otherwise decimal)
+fn parse_address(text: &str) -> Option<usize> {
+    match text.strip_prefix("0x") {
+        Some(hex) => usize::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
+
+// the program being debugged
+#[derive(Debug)]
+struct Session {
+    c8: Chip8,
+    source_path: Option<String>, // the source file breakpoints are set in, if known
+    assembled: Option<Assembled>, // the line map and labels, if there is one
+}
+
+impl Session {
+    // starts a session as described by the arguments of a launch request
+    fn launch(args: &Json) -> Result<Session, String> {
+        let program_path = args.get("program").as_str().ok_or("launch needs a program")?;
+
+        // a source file is assembled, a ROM can come with a symbol map from `asm --symbols`
+        let (rom, source_path, assembled) = if program_path.ends_with(".8o") {
+            let source = fs::read_to_string(program_path).map_err(|e| format!("could not read {program_path}: {e}"))?;
+            let assembled = asm::assemble(&source).map_err(|e| format!("{program_path}:{e}"))?;
+
+            (assembled.rom.clone(), Some(program_path.to_string()), Some(assembled))
+        } else {
+            let rom = fs::read(program_path).map_err(|e| format!("could not read {program_path}: {e}"))?;
+            let assembled = match args.get("symbols").as_str() {
+                Some(path) => {
+                    let map = fs::read_to_string(path).map_err(|e| format!("could not read {path}: {e}"))?;
+                    Some(Assembled::from_symbol_map(rom.clone(), &map).map_err(|e| format!("{path}:{e}"))?)
+                },
+                None => None,
+            };
+
+            (rom, args.get("source").as_str().map(String::from), assembled)
+        };
+
+        let mut quirks = match args.get("quirks").as_str() {
+            Some(preset) => Quirks::from_preset_name(preset).ok_or(format!("unknown quirks preset: {preset}"))?,
+            None => Quirks::default(),
+        };
+
+        for (name, enabled) in args.get("quirk").as_object().unwrap_or_default() {
+            let enabled = enabled.as_bool().ok_or(format!("quirk {name} needs true or false"))?;
+            if !quirks.set_by_name(name, enabled) {
+                return Err(format!("unknown quirk: {name}"));
+            }
+        }
+
+        let mut c8 = Chip8::new_with_program(&crate::program_8_to_16(&rom));
+        c8.quirks = quirks;
+        if let Some(seed) = args.get("seed").as_u64() {
+            c8.random.reseed(seed);
+        }
+
+        Ok(Session { c8, source_path, assembled })
+    }
+
+    // describes the source file for DAP
+    fn source(&self) -> Json {
+        match &self.source_path {
+            Some(path) => Json::object([
+                ("name", Json::from(path.rsplit(['/', '\\']).next().unwrap_or(path))),
+                ("path", Json::from(path.as_str())),
+            ]),
+            None => Json::Null,
+        }
+    }
+
+    // names the subroutine an address is in, after the closest label before it
+    fn frame_name(&self, addr: usize) -> String {
+        let label = self.assembled.as_ref().and_then(|assembled| {
+            assembled.labels
+                .iter()
+                .filter(|&(_, &label_addr)| label_addr <= addr)
+                .max_by_key(|&(_, &label_addr)| label_addr)
+        });
+
+        match label {
+            Some((name, &label_addr)) if label_addr == addr => name.clone(),
+            Some((name, &label_addr)) => format!("{name}+0x{:X}", addr - label_addr),
+            None => format!("0x{addr:03X}"),
+        }
+    }
+
+    // describes a stack frame at an address
+    fn frame(&self, id: usize, addr: usize) -> Json {
+        let mut frame = Json::object([
+            ("id", Json::from(id)),
+            ("name", Json::from(self.frame_name(addr))),
+            ("line", Json::from(0)),
+            ("column", Json::from(0)),
+            ("instructionPointerReference", Json::from(format!("0x{addr:03X}"))),
+        ]);
+
+        if let Some(line) = self.assembled.as_ref().and_then(|assembled| assembled.get_address_line(addr)) {
+            frame.set("line", Json::from(line));
+            frame.set("column", Json::from(1));
+            frame.set("source", self.source());
+        }
+
+        frame
+    }
+}
+
+// a variable for the variables response
+fn variable(name: &str, val: String) -> Json {
+    Json::object([
+        ("name", Json::from(name)),
+        ("value", Json::from(val)),
+        ("variablesReference", Json::from(0)),
+    ])
+}
+
+// what to report after a response
+enum Stop {
+    Entry,
+    Reason(StopReason),
+}
+
+// a Debug Adapter Protocol server debugging one program at a time, writing responses and events to a writer
+#[derive(Debug)]
+pub struct Chip8DapServer<W: Write> {
+    writer: W,
+    seq: u64,
+    session: Option<Session>,
+    debugger: Chip8Debugger,
+    breakpoint_ids: Vec<usize>,
+    instructions_per_frame: usize,
+    stop_on_entry: bool,
+    running: bool,
+    done: bool,
+}
+
+impl<W: Write> Chip8DapServer<W> {
+    // creates a server, the timers are ticked every instructions_per_frame instructions
+    pub fn new(writer: W, instructions_per_frame: usize) -> Chip8DapServer<W> {
+        Chip8DapServer {
+            writer,
+            seq: 1,
+            session: None,
+            debugger: Chip8Debugger::new(instructions_per_frame),
+            breakpoint_ids: Vec::new(),
+            instructions_per_frame,
+            stop_on_entry: false,
+            running: false,
+            done: false,
+        }
+    }
+
+    // writes a message with a Content-Length header
+    fn send(&mut self, mut message: Json) -> io::Result<()> {
+        message.set("seq", Json::from(self.seq));
+        self.seq += 1;
+
+        let body = message.to_string();
+        write!(self.writer, "Content-Length: {}\r\n\r\n{body}", body.len())?;
+        self.writer.flush()
+    }
+
+    // sends an event, with no body if it is Null
+    fn send_event(&mut self, event: &str, body: Json) -> io::Result<()> {
+        let mut message = Json::object([
+            ("seq", Json::from(0)),
+            ("type", Json::from("event")),
+            ("event", Json::from(event)),
+        ]);
+        if !body.is_null() {
+            message.set("body", body);
+        }
+
+        self.send(message)
+    }
+
+    // sends a stopped event, or exited and terminated events if the program is done
+    fn send_stop(&mut self, reason: &StopReason) -> io::Result<()> {
+        self.running = false;
+
+        let (reason, description, hit) = match reason {
+            StopReason::Exited => {
+                self.send_event("exited", Json::object([("exitCode", Json::from(0))]))?;
+                return self.send_event("terminated", Json::Null);
+            },
+            StopReason::Breakpoint(id) => ("breakpoint", None, vec![Json::from(*id)]),
+            StopReason::Watchpoint { .. } => ("data breakpoint", None, Vec::new()),
+            StopReason::Paused => ("pause", None, Vec::new()),
+            StopReason::Fault(e) => ("exception", Some(e.to_string()), Vec::new()),
+            StopReason::LimitReached => ("step", Some(format!("gave up after {RUN_LIMIT} instructions")), Vec::new()),
+            StopReason::Stepped | StopReason::Returned => ("step", None, Vec::new()),
+        };
+
+        let mut body = Json::object([
+            ("reason", Json::from(reason)),
+            ("threadId", Json::from(THREAD_ID)),
+            ("allThreadsStopped", Json::from(true)),
+        ]);
+        if let Some(description) = description {
+            body.set("text", Json::from(description));
+        }
+        if !hit.is_empty() {
+            body.set("hitBreakpointIds", Json::from(hit));
+        }
+
+        self.send_event("stopped", body)
+    }
+
+    // gets the session, failing if nothing has been launched yet
+    fn session(&mut self) -> Result<&mut Session, String> {
+        self.session.as_mut().ok_or_else(|| String::from("no program has been launched"))
+    }
+
+    // replaces the breakpoints, returning their descriptions
+    fn set_breakpoints(&mut self, args: &Json) -> Result<Json, String> {
+        for id in self.breakpoint_ids.drain(..) {
+            self.debugger.delete(id);
+        }
+
+        let path = args.get("source").get("path").as_str().map(String::from);
+        let session = self.session.as_ref().ok_or("no program has been launched")?;
+        let source_matches = session.source_path.is_none() || path == session.source_path;
+
+        // resolve every breakpoint first, the response has to list them in the order they were requested
+        let resolved = args.get("breakpoints")
+            .as_array()
+            .unwrap_or_default()
+            .iter()
+            .map(|requested| {
+                let line = requested.get("line").as_u64().unwrap_or(0) as usize;
+
+                let condition = requested.get("condition").as_str().filter(|condition| !condition.trim().is_empty());
+                let condition = condition.map(Condition::parse).transpose().map_err(|e| (line, e))?;
+
+                // a line without an instruction gets the first instruction after it
+                let target = session.assembled
+                    .as_ref()
+                    .filter(|_| source_matches)
+                    .and_then(|assembled| assembled.line_addresses.iter().filter(|&&(source_line, _)| source_line >= line).min());
+
+                match target {
+                    Some(&(line, addr)) => Ok((Breakpoint { addr: Some(addr as u16), condition }, line)),
+                    None if session.assembled.is_none() => Err((line, String::from("no line map (launch a .8o file or pass symbols)"))),
+                    None => Err((line, String::from("no instruction at or after this line"))),
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let breakpoints = resolved
+            .into_iter()
+            .map(|resolved| match resolved {
+                Ok((breakpoint, line)) => {
+                    let id = self.debugger.add_breakpoint(breakpoint);
+                    self.breakpoint_ids.push(id);
+                    Json::object([("id", Json::from(id)), ("verified", Json::from(true)), ("line", Json::from(line))])
+                },
+                Err((line, message)) => Json::object([("verified", Json::from(false)), ("line", Json::from(line)), ("message", Json::from(message))]),
+            })
+            .collect::<Vec<_>>();
+
+        Ok(Json::object([("breakpoints", Json::from(breakpoints))]))
+    }
+
+    // describes the stack, the current instruction first and then every call on the return stack
+    fn stack_trace(&mut self) -> Result<Json, String> {
+        let session = self.session()?;
+        let pc = *session.c8.registers.get_pc_register() as usize;
+
+        let frames = std::iter::once(pc)
+            .chain(session.c8.get_call_stack().iter().rev().map(|&addr| (addr as usize).saturating_sub(2)))
+            .enumerate()
+            .map(|(id, addr)| session.frame(id, addr))
+            .collect::<Vec<_>>();
+
+        Ok(Json::object([("totalFrames", Json::from(frames.len())), ("stackFrames", Json::from(frames))]))
+    }
+
+    // lists the variables of a scope
+    fn variables(&mut self, args: &Json) -> Result<Json, String> {
+        let c8 = &self.session()?.c8;
+
+        let variables = match args.get("variablesReference").as_u64() {
+            Some(REGISTERS_REFERENCE) => {
+                let mut variables = (0..16)
+                    .map(|reg| variable(&format!("V{reg:X}"), format!("0x{:02X}", c8.registers.get_v_register(reg).unwrap())))
+                    .collect::<Vec<_>>();
+
+                let mut i = variable("I", format!("0x{:03X}", c8.registers.get_i_register()));
+                i.set("memoryReference", Json::from(format!("0x{:03X}", c8.registers.get_i_register())));
+                variables.push(i);
+
+                variables.push(variable("PC", format!("0x{:03X}", c8.registers.get_pc_register())));
+                variables.push(variable("SP", c8.stack.get_depth().to_string()));
+                variables
+            },
+            Some(TIMERS_REFERENCE) => vec![
+                variable("DT", c8.timers.get_delay().to_string()),
+                variable("ST", c8.timers.get_sound().to_string()),
+            ],
+            Some(KEYPAD_REFERENCE) => c8.input.get_keys_status()
+                .iter()
+                .enumerate()
+                .map(|(key, pressed)| variable(&format!("{key:X}"), String::from(if *pressed { "pressed" } else { "released" })))
+                .collect(),
+            _ => return Err(String::from("unknown variables reference")),
+        };
+
+        Ok(Json::object([("variables", Json::from(variables))]))
+    }
+
+    // reads memory, returning it base64 encoded
+    fn read_memory(&mut self, args: &Json) -> Result<Json, String> {
+        let c8 = &self.session()?.c8;
+
+        let reference = args.get("memoryReference").as_str().and_then(parse_address).ok_or("invalid memory reference")?;
+        let offset = args.get("offset").as_f64().unwrap_or(0.0) as i64;
+        let count = args.get("count").as_u64().unwrap_or(0) as usize;
+
+        let size = c8.memory.get_size();
+        let start = (reference as i64).saturating_add(offset).clamp(0, size as i64) as usize;
+        let end = start.saturating_add(count).min(size);
+
+        Ok(Json::object([
+            ("address", Json::from(format!("0x{start:03X}"))),
+            ("data", Json::from(base64(&c8.memory.get_bytes()[start..end]))),
+            ("unreadableBytes", Json::from(count - (end - start))),
+        ]))
+    }
+
+    // handles a request, returning the body of the response. stopping events are sent after the response
+    fn handle_request(&mut self, command: &str, args: &Json) -> Result<(Json, Option<Stop>), String> {
+        let mut stop = None;
+
+        let body = match command {
+            "initialize" => Json::object([
+                ("supportsConfigurationDoneRequest", Json::from(true)),
+                ("supportsConditionalBreakpoints", Json::from(true)),
+                ("supportsReadMemoryRequest", Json::from(true)),
+                ("supportsTerminateRequest", Json::from(true)),
+            ]),
+
+            "launch" => {
+                self.session = Some(Session::launch(args)?);
+                self.stop_on_entry = args.get("stopOnEntry").as_bool().unwrap_or(false);
+                Json::Null
+            },
+
+            "setBreakpoints" => self.set_breakpoints(args)?,
+
+            "setExceptionBreakpoints" => Json::object([("breakpoints", Json::Array(Vec::new()))]),
+
+            "configurationDone" => {
+                self.session()?;
+                if self.stop_on_entry {
+                    stop = Some(Stop::Entry);
+                } else {
+                    self.running = true;
+                }
+                Json::Null
+            },
+
+            "threads" => Json::object([("threads", Json::from(vec![
+                Json::object([("id", Json::from(THREAD_ID)), ("name", Json::from("CHIP-8"))]),
+            ]))]),
+
+            "stackTrace" => self.stack_trace()?,
+
+            "scopes" => Json::object([("scopes", Json::from(vec![
+                Json::object([("name", Json::from("Registers")), ("variablesReference", Json::from(REGISTERS_REFERENCE)), ("expensive", Json::from(false))]),
+                Json::object([("name", Json::from("Timers")), ("variablesReference", Json::from(TIMERS_REFERENCE)), ("expensive", Json::from(false))]),
+                Json::object([("name", Json::from("Keypad")), ("variablesReference", Json::from(KEYPAD_REFERENCE)), ("expensive", Json::from(false))]),
+            ]))]),
+
+            "variables" => self.variables(args)?,
+
+            "readMemory" => self.read_memory(args)?,
+
+            "continue" => {
+                self.session()?;
+                self.running = true;
+                Json::object([("allThreadsContinued", Json::from(true))])
+            },
+
+            "next" | "stepIn" | "stepOut" => {
+                let session = self.session.as_mut().ok_or("no program has been launched")?;
+                let reason = match command {
+                    "next" => self.debugger.step_over(&mut session.c8, RUN_LIMIT),
+                    "stepIn" => self.debugger.step(&mut session.c8),
+                    _ => self.debugger.step_out(&mut session.c8, RUN_LIMIT).unwrap_or_else(|| self.debugger.step(&mut session.c8)),
+                };
+                stop = Some(Stop::Reason(reason));
+                Json::Null
+            },
+
+            "pause" => {
+                stop = Some(Stop::Reason(StopReason::Paused));
+                Json::Null
+            },
+
+            "disconnect" | "terminate" => {
+                self.done = true;
+                Json::Null
+            },
+
+            _ => return Err(format!("unsupported request: {command}")),
+        };
+
+        Ok((body, stop))
+    }
+
+    // handles a message from the client
+    fn handle_message(&mut self, message: &Json) -> io::Result<()> {
+        if message.get("type").as_str() != Some("request") {
+            return Ok(());
+        }
+
+        let command = message.get("command").as_str().unwrap_or_default();
+        let result = self.handle_request(command, message.get("arguments"));
+
+        let mut response = Json::object([
+            ("seq", Json::from(0)),
+            ("type", Json::from("response")),
+            ("request_seq", message.get("seq").clone()),
+            ("success", Json::from(result.is_ok())),
+            ("command", Json::from(command)),
+        ]);
+
+        let stop = match result {
+            Ok((body, stop)) => {
+                if !body.is_null() {
+                    response.set("body", body);
+                }
+                stop
+            },
+            Err(e) => {
+                response.set("message", Json::from(e));
+                None
+            },
+        };
+
+        self.send(response)?;
+
+        // breakpoints can only be set once there is a program and its line map
+        if command == "launch" && self.session.is_some() {
+            self.send_event("initialized", Json::Null)?;
+        }
+
+        match stop {
+            Some(Stop::Entry) => {
+                self.running = false;
+                self.send_event("stopped", Json::object([
+                    ("reason", Json::from("entry")),
+                    ("threadId", Json::from(THREAD_ID)),
+                    ("allThreadsStopped", Json::from(true)),
+                ]))
+            },
+            Some(Stop::Reason(reason)) => self.send_stop(&reason),
+            None => Ok(()),
+        }
+    }
+
+    // runs a frame's worth of instructions if the program is running, reporting if it stopped
+    fn run_frame(&mut self) -> io::Result<()> {
+        let Some(session) = self.session.as_mut().filter(|_| self.running) else {
+            return Ok(());
+        };
+
+        match self.debugger.resume(&mut session.c8, self.instructions_per_frame) {
+            Some(reason) => self.send_stop(&reason),
+            None => Ok(()),
+        }
+    }
+
+    // serves requests read from a reader (a thread reads it, so the program can run while waiting for requests)
+    // until the client disconnects or the input ends
+    pub fn serve<R: Read + Send + 'static>(&mut self, reader: R) -> io::Result<()> {
+        let (message_sender, messages) = mpsc::channel();
+        thread::spawn(move || {
+            let mut reader = BufReader::new(reader);
+            while let Ok(Some(message)) = read_message(&mut reader) {
+                if message_sender.send(message).is_err() {
+                    break;
+                }
+            }
+        });
+
+        while !self.done {
+            if self.running {
+                while let Ok(message) = messages.try_recv() {
+                    self.handle_message(&message)?;
+                }
+
+                self.run_frame()?;
+                thread::sleep(FRAME_DURATION);
+            } else {
+                match messages.recv() {
+                    Ok(message) => self.handle_message(&message)?,
+                    Err(_) => break,
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,645 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use super::Chip8;
+use super::cpu::CPUInstruction;
+use super::disasm::{format_instruction, DisasmSyntax};
+use super::error::{Chip8Error, StepOutcome};
+use super::render::{RenderMode, Renderer};
+
+// the most instructions step over and run until return go through before giving up, so an endless loop can't hang them
+pub const RUN_LIMIT: usize = 1_000_000;
+
+// something a condition can look at
+#[derive(Debug, Clone, PartialEq)]
+pub enum Operand {
+    VRegister(usize),
+    IRegister,
+    ProgramCounter,
+    DelayTimer,
+    SoundTimer,
+    StackDepth,
+    Memory(Box<Operand>), // the byte at an address
+    Number(u16),
+}
+
+impl Operand {
+    // parses a register name (v0-vf, i, pc, dt, st, sp), a hex number or [OPERAND] for the byte at an address
+    pub fn parse(text: &str) -> Result<Operand, String> {
+        let text = text.trim();
+        let lower = text.to_lowercase();
+
+        if let Some(inner) = text.strip_prefix('[').and_then(|text| text.strip_suffix(']')) {
+            return Ok(Operand::Memory(Box::new(Operand::parse(inner)?)));
+        }
+
+        let operand = match lower.as_str() {
+            "i" => Operand::IRegister,
+            "pc" => Operand::ProgramCounter,
+            "dt" => Operand::DelayTimer,
+            "st" => Operand::SoundTimer,
+            "sp" => Operand::StackDepth,
+            _ => match lower.strip_prefix('v').filter(|digit| digit.len() == 1) {
+                Some(digit) => Operand::VRegister(usize::from_str_radix(digit, 16).map_err(|_| format!("unknown register: {text}"))?),
+                None => Operand::Number(parse_hex(text)?),
+            },
+        };
+
+        Ok(operand)
+    }
+
+    // gets the current value
+    pub fn evaluate(&self, c8: &Chip8) -> u16 {
+        match self {
+            Operand::VRegister(reg) => c8.registers.get_v_register(*reg).copied().unwrap_or(0) as u16,
+            Operand::IRegister => *c8.registers.get_i_register(),
+            Operand::ProgramCounter => *c8.registers.get_pc_register(),
+            Operand::DelayTimer => *c8.timers.get_delay() as u16,
+            Operand::SoundTimer => *c8.timers.get_sound() as u16,
+            Operand::StackDepth => c8.stack.get_depth() as u16,
+            Operand::Memory(addr) => c8.memory.get_memory_at(addr.evaluate(c8) as usize).copied().unwrap_or(0) as u16,
+            Operand::Number(val) => *val,
+        }
+    }
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Operand::VRegister(reg) => write!(f, "v{reg:x}"),
+            Operand::IRegister => write!(f, "i"),
+            Operand::ProgramCounter => write!(f, "pc"),
+            Operand::DelayTimer => write!(f, "dt"),
+            Operand::SoundTimer => write!(f, "st"),
+            Operand::StackDepth => write!(f, "sp"),
+            Operand::Memory(addr) => write!(f, "[{addr}]"),
+            Operand::Number(val) => write!(f, "0x{val:X}"),
+        }
+    }
+}
+
+// a comparison between two operands, like `v0 == 5` or `[i] != 0`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Condition {
+    pub left: Operand,
+    pub comparison: String,
+    pub right: Operand,
+}
+
+impl Condition {
+    // parses LEFT OP RIGHT, where OP is one of == != < <= > >=
+    pub fn parse(text: &str) -> Result<Condition, String> {
+        let (pos, comparison) = ["==", "!=", "<=", ">=", "<", ">"]
+            .iter()
+            .filter_map(|op| text.find(op).map(|pos| (pos, *op)))
+            .min_by_key(|&(pos, op)| (pos, std::cmp::Reverse(op.len())))
+            .ok_or_else(|| format!("no comparison in condition: {text}"))?;
+
+        Ok(Condition {
+            left: Operand::parse(&text[..pos])?,
+            comparison: comparison.to_string(),
+            right: Operand::parse(&text[pos + comparison.len()..])?,
+        })
+    }
+
+    // returns true if the condition currently holds
+    pub fn evaluate(&self, c8: &Chip8) -> bool {
+        let (left, right) = (self.left.evaluate(c8), self.right.evaluate(c8));
+
+        match self.comparison.as_str() {
+            "==" => left == right,
+            "!=" => left != right,
+            "<" => left < right,
+            "<=" => left <= right,
+            ">" => left > right,
+            _ => left >= right,
+        }
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} {}", self.left, self.comparison, self.right)
+    }
+}
+
+// stops execution before the instruction at an address, or whenever a condition holds, or both
+#[derive(Debug, Clone, PartialEq)]
+pub struct Breakpoint {
+    pub addr: Option<u16>,
+    pub condition: Option<Condition>,
+}
+
+// stops execution after an instruction reads or writes memory in a range
+#[derive(Debug, Clone, PartialEq)]
+pub struct Watchpoint {
+    pub addr: usize,
+    pub len: usize,
+    pub on_read: bool,
+    pub on_write: bool,
+}
+
+// why execution stopped
+#[derive(Debug, Clone, PartialEq)]
+pub enum StopReason {
+    Stepped,
+    Paused, // the frontend stopped resuming
+    Breakpoint(usize), // the id of the breakpoint
+    Watchpoint { id: usize, addr: usize, write: bool },
+    Returned,
+    Exited,
+    Fault(Chip8Error),
+    LimitReached,
+}
+
+// what the frontend should do after a command
+#[derive(Debug, Clone, PartialEq)]
+pub enum CommandOutcome {
+    Output(String),
+    Continue, // keep calling resume until it stops
+    Quit,
+}
+
+// the help text listing every command
+pub const HELP: &str = "\
+addresses and values are in hex, counts are in decimal
+  step [N], s        execute N instructions (default 1)
+  next, n            execute one instruction, running a whole subroutine if it's a call
+  finish, out        run until the current subroutine returns
+  continue, c        run until something stops execution
+  break ADDR [if CONDITION], break if CONDITION
+                     stop before the instruction at ADDR and/or when CONDITION holds, like `v0 == 5` or `[i] != 0`
+  watch [r|w|rw] ADDR [LENGTH]
+                     stop after memory at ADDR is read and/or written (default rw and 1 byte)
+  delete [ID]        delete a breakpoint or watchpoint (all of them if no id is given)
+  info               list breakpoints and watchpoints
+  regs, r            show the registers, timers and call stack
+  set REG VALUE      set v0-vf, i, pc, dt or st
+  x ADDR [LENGTH]    dump memory as hex (default 64 bytes)
+  poke ADDR BYTE...  write bytes to memory
+  dis [ADDR] [COUNT] disassemble COUNT instructions around ADDR (default around the PC)
+  screen [MODE]      show the display (blocks, half, quadrant or braille)
+  key K on|off       press or release a key
+  quit, q            exit";
+
+// parses a hex number, with or without a 0x prefix
+fn parse_hex(text: &str) -> Result<u16, String> {
+    let digits = text.trim().trim_start_matches("0x").trim_start_matches("0X");
+
+    u16::from_str_radix(digits, 16).map_err(|_| format!("invalid hex number: {text}"))
+}
+
+// a debugger driving a Chip8 instance: stepping, breakpoints and watchpoints, plus a command language for them
+#[derive(Debug)]
+pub struct Chip8Debugger {
+    breakpoints: BTreeMap<usize, Breakpoint>,
+    watchpoints: BTreeMap<usize, Watchpoint>,
+    next_id: usize,
+    instructions_per_frame: usize,
+    instructions_since_frame: usize,
+    resume_pc: Option<u16>, // where execution stopped, so resuming doesn't stop at the same breakpoint again straight away
+}
+
+impl Chip8Debugger {
+    // creates a debugger, the timers are ticked every instructions_per_frame instructions
+    pub fn new(instructions_per_frame: usize) -> Chip8Debugger {
+        Chip8Debugger {
+            breakpoints: BTreeMap::new(),
+            watchpoints: BTreeMap::new(),
+            next_id: 1,
+            instructions_per_frame: instructions_per_frame.max(1),
+            instructions_since_frame: 0,
+            resume_pc: None,
+        }
+    }
+
+    // gets the breakpoints by id
+    pub fn get_breakpoints(&self) -> &BTreeMap<usize, Breakpoint> {
+        &self.breakpoints
+    }
+
+    // gets the watchpoints by id
+    pub fn get_watchpoints(&self) -> &BTreeMap<usize, Watchpoint> {
+        &self.watchpoints
+    }
+
+    // adds a breakpoint, returning its id
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) -> usize {
+        self.next_id += 1;
+        self.breakpoints.insert(self.next_id - 1, breakpoint);
+        self.next_id - 1
+    }
+
+    // adds a watchpoint, returning its id
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) -> usize {
+        self.next_id += 1;
+        self.watchpoints.insert(self.next_id - 1, watchpoint);
+        self.next_id - 1
+    }
+
+    // deletes a breakpoint or watchpoint, returns false if there is none with the id
+    pub fn delete(&mut self, id: usize) -> bool {
+        self.breakpoints.remove(&id).is_some() || self.watchpoints.remove(&id).is_some()
+    }
+
+    // deletes every breakpoint and watchpoint
+    pub fn delete_all(&mut self) {
+        self.breakpoints.clear();
+        self.watchpoints.clear();
+    }
+
+    // executes one instruction, checking breakpoints before it (unless it's where execution last stopped) and
+    // watchpoints after it. returns why execution should stop, if it should
+    fn execute(&mut self, c8: &mut Chip8) -> Option<StopReason> {
+        let pc = *c8.registers.get_pc_register();
+
+        if self.resume_pc.take() != Some(pc) {
+            let hit = self.breakpoints
+                .iter()
+                .find(|(_, breakpoint)| {
+                    breakpoint.addr.is_none_or(|addr| addr == pc) && breakpoint.condition.as_ref().is_none_or(|condition| condition.evaluate(c8))
+                });
+
+            if let Some((&id, _)) = hit {
+                return Some(StopReason::Breakpoint(id));
+            }
+        }
+
+        let accesses = c8.memory.get_memory_at_u16(pc as usize)
+            .ok()
+            .map(|opcode| c8.memory_accesses(&Chip8::opcode_to_instruction(opcode)));
+
+        let result = c8.execute_next_instruction();
+
+        self.instructions_since_frame += 1;
+        if self.instructions_since_frame >= self.instructions_per_frame {
+            self.instructions_since_frame = 0;
+            c8.timer_tick();
+        }
+
+        match result {
+            Err(e) => return Some(StopReason::Fault(e)),
+            Ok(StepOutcome::Exited) => return Some(StopReason::Exited),
+            Ok(_) => {},
+        }
+
+        let accesses = accesses?;
+        for (&id, watchpoint) in &self.watchpoints {
+            let range = watchpoint.addr..watchpoint.addr + watchpoint.len;
+
+            for (write, accessed, enabled) in [(false, &accesses.reads, watchpoint.on_read), (true, &accesses.writes, watchpoint.on_write)] {
+                if let Some(addr) = accessed.clone().find(|addr| enabled && range.contains(addr)) {
+                    return Some(StopReason::Watchpoint { id, addr, write });
+                }
+            }
+        }
+
+        None
+    }
+
+    // notes where execution stopped and passes the reason on
+    fn stop(&mut self, c8: &Chip8, reason: StopReason) -> StopReason {
+        self.resume_pc = Some(*c8.registers.get_pc_register());
+        reason
+    }
+
+    // executes a single instruction
+    pub fn step(&mut self, c8: &mut Chip8) -> StopReason {
+        self.resume_pc = Some(*c8.registers.get_pc_register());
+        let reason = self.execute(c8).unwrap_or(StopReason::Stepped);
+
+        self.stop(c8, reason)
+    }
+
+    // runs until something stops execution or `done` returns true (with the given reason), or the limit is reached
+    fn run_until(&mut self, c8: &mut Chip8, limit: usize, done: impl Fn(&Chip8) -> bool, done_reason: StopReason) -> StopReason {
+        for _ in 0..limit {
+            if let Some(reason) = self.execute(c8) {
+                return self.stop(c8, reason);
+            }
+
+            if done(c8) {
+                return self.stop(c8, done_reason);
+            }
+        }
+
+        self.stop(c8, StopReason::LimitReached)
+    }
+
+    // runs up to limit instructions, returning None if nothing stopped execution (so it can be resumed in chunks)
+    pub fn resume(&mut self, c8: &mut Chip8, limit: usize) -> Option<StopReason> {
+        for _ in 0..limit {
+            if let Some(reason) = self.execute(c8) {
+                return Some(self.stop(c8, reason));
+            }
+        }
+
+        None
+    }
+
+    // executes one instruction, or a whole subroutine if the instruction is a call
+    pub fn step_over(&mut self, c8: &mut Chip8, limit: usize) -> StopReason {
+        let pc = *c8.registers.get_pc_register();
+        let is_call = c8.memory.get_memory_at_u16(pc as usize)
+            .is_ok_and(|opcode| matches!(Chip8::opcode_to_instruction(opcode), CPUInstruction::CallSubroutine { .. }));
+
+        if !is_call {
+            return self.step(c8);
+        }
+
+        let depth = c8.stack.get_depth();
+        self.resume_pc = Some(pc);
+        self.run_until(c8, limit, |c8| *c8.registers.get_pc_register() == pc.wrapping_add(2) && c8.stack.get_depth() == depth, StopReason::Stepped)
+    }
+
+    // runs until the current subroutine returns, returns None if not in a subroutine
+    pub fn step_out(&mut self, c8: &mut Chip8, limit: usize) -> Option<StopReason> {
+        let depth = c8.stack.get_depth();
+        if depth == 0 {
+            return None;
+        }
+
+        self.resume_pc = Some(*c8.registers.get_pc_register());
+        Some(self.run_until(c8, limit, |c8| c8.stack.get_depth() < depth, StopReason::Returned))
+    }
+
+    // describes why execution stopped, followed by the disassembly around the PC
+    pub fn describe_stop(&self, c8: &Chip8, reason: &StopReason) -> String {
+        let pc = *c8.registers.get_pc_register();
+
+        let description = match reason {
+            StopReason::Stepped => format!("stopped at 0x{pc:03X}"),
+            StopReason::Paused => format!("paused at 0x{pc:03X}"),
+            StopReason::Breakpoint(id) => format!("breakpoint {id} hit at 0x{pc:03X}"),
+            StopReason::Watchpoint { id, addr, write } => {
+                format!("watchpoint {id}: 0x{addr:03X} was {}, stopped at 0x{pc:03X}", if *write { "written" } else { "read" })
+            },
+            StopReason::Returned => format!("returned to 0x{pc:03X}"),
+            StopReason::Exited => String::from("the program exited"),
+            StopReason::Fault(e) => format!("halted: {e}"),
+            StopReason::LimitReached => format!("gave up after {RUN_LIMIT} instructions, stopped at 0x{pc:03X}"),
+        };
+
+        format!("{description}\n{}", disassemble_around(c8, pc as usize, 3, 4))
+    }
+
+    // runs a command (see HELP), returning its output or what the frontend should do next
+    pub fn run_command(&mut self, c8: &mut Chip8, line: &str) -> CommandOutcome {
+        match self.command(c8, line) {
+            Ok(outcome) => outcome,
+            Err(e) => CommandOutcome::Output(e),
+        }
+    }
+
+    fn command(&mut self, c8: &mut Chip8, line: &str) -> Result<CommandOutcome, String> {
+        let line = line.trim();
+        let (command, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let args = rest.split_whitespace().collect::<Vec<_>>();
+
+        let output = match command {
+            "step" | "s" => {
+                let count = args.first().map_or(Ok(1), |count| count.parse().map_err(|_| format!("invalid count: {count}")))?;
+                let mut reason = StopReason::Stepped;
+
+                for _ in 0..count {
+                    reason = self.step(c8);
+                    if reason != StopReason::Stepped {
+                        break;
+                    }
+                }
+
+                self.describe_stop(c8, &reason)
+            },
+
+            "next" | "n" => {
+                let reason = self.step_over(c8, RUN_LIMIT);
+                self.describe_stop(c8, &reason)
+            },
+
+            "finish" | "out" => match self.step_out(c8, RUN_LIMIT) {
+                Some(reason) => self.describe_stop(c8, &reason),
+                None => String::from("not in a subroutine"),
+            },
+
+            "continue" | "c" => return Ok(CommandOutcome::Continue),
+
+            "break" | "b" => {
+                let (addr, condition) = match rest.trim().strip_prefix("if ") {
+                    Some(condition) => (None, Some(condition)),
+                    None => match rest.split_once(" if ") {
+                        Some((addr, condition)) => (Some(addr), Some(condition)),
+                        None if !rest.trim().is_empty() => (Some(rest), None),
+                        None => return Err(String::from("usage: break ADDR [if CONDITION] or break if CONDITION")),
+                    },
+                };
+
+                let breakpoint = Breakpoint {
+                    addr: addr.map(parse_hex).transpose()?,
+                    condition: condition.map(Condition::parse).transpose()?,
+                };
+
+                let id = self.add_breakpoint(breakpoint);
+                format!("breakpoint {id} set")
+            },
+
+            "watch" | "w" => {
+                let (on_read, on_write, args) = match args.first() {
+                    Some(&"r") => (true, false, &args[1..]),
+                    Some(&"w") => (false, true, &args[1..]),
+                    Some(&"rw") => (true, true, &args[1..]),
+                    _ => (true, true, &args[..]),
+                };
+
+                let addr = parse_hex(args.first().ok_or("usage: watch [r|w|rw] ADDR [LENGTH]")?)? as usize;
+                let len = args.get(1).map_or(Ok(1), |len| parse_hex(len))? as usize;
+
+                let id = self.add_watchpoint(Watchpoint { addr, len, on_read, on_write });
+                format!("watchpoint {id} set")
+            },
+
+            "delete" | "d" => match args.first() {
+                Some(id) => {
+                    let id = id.parse().map_err(|_| format!("invalid id: {id}"))?;
+                    if !self.delete(id) {
+                        return Err(format!("no breakpoint or watchpoint {id}"));
+                    }
+
+                    format!("deleted {id}")
+                },
+                None => {
+                    self.delete_all();
+                    String::from("deleted all breakpoints and watchpoints")
+                },
+            },
+
+            "info" => {
+                let mut out = Vec::new();
+
+                for (id, breakpoint) in &self.breakpoints {
+                    let addr = breakpoint.addr.map(|addr| format!(" at 0x{addr:03X}")).unwrap_or_default();
+                    let condition = breakpoint.condition.as_ref().map(|condition| format!(" if {condition}")).unwrap_or_default();
+                    out.push(format!("{id}: breakpoint{addr}{condition}"));
+                }
+
+                for (id, watchpoint) in &self.watchpoints {
+                    let kind = match (watchpoint.on_read, watchpoint.on_write) {
+                        (true, true) => "read/write",
+                        (true, false) => "read",
+                        _ => "write",
+                    };
+                    out.push(format!("{id}: {kind} watchpoint on 0x{:03X}..0x{:03X}", watchpoint.addr, watchpoint.addr + watchpoint.len));
+                }
+
+                if out.is_empty() { String::from("no breakpoints or watchpoints") } else { out.join("\n") }
+            },
+
+            "regs" | "r" => format_registers(c8),
+
+            "set" => {
+                let [reg, val] = args[..] else {
+                    return Err(String::from("usage: set REG VALUE"));
+                };
+                let val = parse_hex(val)?;
+
+                match Operand::parse(reg)? {
+                    Operand::VRegister(reg) => *c8.registers.get_v_register_mut(reg).map_err(|e| e.to_string())? = val as u8,
+                    Operand::IRegister => *c8.registers.get_i_register_mut() = val,
+                    Operand::ProgramCounter => *c8.registers.get_pc_register_mut() = val,
+                    Operand::DelayTimer => *c8.timers.get_delay_mut() = val as u8,
+                    Operand::SoundTimer => *c8.timers.get_sound_mut() = val as u8,
+                    _ => return Err(format!("can't set {reg}")),
+                }
+
+                format_registers(c8)
+            },
+
+            "x" => {
+                let addr = parse_hex(args.first().ok_or("usage: x ADDR [LENGTH]")?)? as usize;
+                let len = args.get(1).map_or(Ok(0x40), |len| parse_hex(len))? as usize;
+
+                hex_dump(c8, addr, len)
+            },
+
+            "poke" => {
+                let addr = parse_hex(args.first().ok_or("usage: poke ADDR BYTE...")?)? as usize;
+
+                for (i, byte) in args[1..].iter().enumerate() {
+                    let byte = parse_hex(byte)?;
+                    *c8.memory.get_memory_at_mut(addr + i).map_err(|e| e.to_string())? = byte as u8;
+                }
+
+                hex_dump(c8, addr, args.len() - 1)
+            },
+
+            "dis" => {
+                let addr = args.first().map_or(Ok(*c8.registers.get_pc_register()), |addr| parse_hex(addr))? as usize;
+                let count = args.get(1).map_or(Ok(8), |count| count.parse().map_err(|_| format!("invalid count: {count}")))?;
+
+                disassemble_around(c8, addr, count / 2, count - count / 2)
+            },
+
+            "screen" => match args.first() {
+                Some(mode) => {
+                    let mode = RenderMode::from_name(mode).ok_or(format!("unknown render mode: {mode}"))?;
+                    c8.output.render(&Renderer::new(mode))
+                },
+                None => c8.output.get_display_as_str(),
+            },
+
+            "key" => {
+                let [key, state] = args[..] else {
+                    return Err(String::from("usage: key K on|off"));
+                };
+                let key = usize::from_str_radix(key, 16).ok().filter(|&key| key < 16).ok_or(format!("invalid key: {key}"))?;
+
+                c8.input.get_keys_status_mut()[key] = match state {
+                    "on" | "down" => true,
+                    "off" | "up" => false,
+                    _ => return Err(format!("invalid key state: {state} (expected on or off)")),
+                };
+
+                format!("key {key:X} {state}")
+            },
+
+            "help" | "h" | "?" => String::from(HELP),
+
+            "quit" | "q" => return Ok(CommandOutcome::Quit),
+
+            _ => return Err(format!("unknown command: {command} (type help for a list)")),
+        };
+
+        Ok(CommandOutcome::Output(output))
+    }
+}
+
+impl Default for Chip8Debugger {
+    fn default() -> Self {
+        Chip8Debugger::new(1)
+    }
+}
+
+// formats the registers, timers and call stack
+pub fn format_registers(c8: &Chip8) -> String {
+    let v = (0..16)
+        .map(|reg| format!("V{reg:X}={:02X}", c8.registers.get_v_register(reg).unwrap()))
+        .collect::<Vec<_>>();
+
+    let stack = c8.get_call_stack()
+        .iter()
+        .map(|addr| format!("0x{addr:03X}"))
+        .collect::<Vec<_>>();
+
+    format!(
+        "{}\n{}\nI={:04X} PC={:04X} DT={:02X} ST={:02X} SP={}\nstack: [{}]",
+        v[..8].join(" "),
+        v[8..].join(" "),
+        c8.registers.get_i_register(),
+        c8.registers.get_pc_register(),
+        c8.timers.get_delay(),
+        c8.timers.get_sound(),
+        c8.stack.get_depth(),
+        stack.join(", "),
+    )
+}
+
+// dumps memory as hex, 16 bytes per line
+pub fn hex_dump(c8: &Chip8, addr: usize, len: usize) -> String {
+    let end = (addr + len).min(c8.memory.get_size());
+
+    (addr..end)
+        .step_by(16)
+        .map(|line| {
+            let bytes = (line..(line + 16).min(end))
+                .map(|addr| format!("{:02X}", c8.memory.get_bytes()[addr]))
+                .collect::<Vec<_>>();
+
+            format!("{line:04X}: {}", bytes.join(" "))
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+// disassembles the instructions before and after an address (assuming the instructions before it are 2 bytes each),
+// marking the one at the PC
+pub fn disassemble_around(c8: &Chip8, addr: usize, before: usize, after: usize) -> String {
+    let pc = *c8.registers.get_pc_register() as usize;
+    let mut lines = Vec::new();
+    let mut line_addr = addr.saturating_sub(2 * before);
+
+    while lines.len() < before + after {
+        let Ok(opcode) = c8.memory.get_memory_at_u16(line_addr) else {
+            break;
+        };
+        let long_addr = c8.memory.get_memory_at_u16(line_addr + 2).unwrap_or(0);
+        let length = Chip8::instruction_length(opcode);
+
+        let text = format_instruction(opcode, long_addr, DisasmSyntax::Classic, &|addr| format!("0x{addr:03X}"))
+            .unwrap_or_else(|| String::from("???"));
+        let bytes = if length == 4 { format!("{opcode:04X}{long_addr:04X}") } else { format!("{opcode:04X}") };
+        let marker = if line_addr == pc { "=>" } else { "  " };
+
+        lines.push(format!("{marker} {line_addr:04X}: {bytes:<8} {text}"));
+        line_addr += length;
+    }
+
+    lines.join("\n")
+}
@@ -0,0 +1,376 @@
+use std::collections::{BTreeMap, BTreeSet};
+use super::Chip8;
+use super::cpu::{ALUOperations, CPUInstrTarget, CPUInstruction};
+
+// the address programs are loaded at and start executing from
+pub const PROGRAM_START: usize = 0x200;
+
+// the assembly syntax to write instructions in
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DisasmSyntax {
+    Classic, // Cowgod's mnemonics, e.g. LD V0, 0x12
+    Octo, // Octo's syntax, e.g. v0 := 0x12
+}
+
+impl DisasmSyntax {
+    // gets a syntax by name
+    pub fn from_name(name: &str) -> Option<DisasmSyntax> {
+        match name {
+            "classic" => Some(DisasmSyntax::Classic),
+            "octo" => Some(DisasmSyntax::Octo),
+            _ => None,
+        }
+    }
+}
+
+// a line of a disassembly, either an instruction or some data bytes
+#[derive(Debug, Clone, PartialEq)]
+pub struct DisasmItem {
+    pub addr: usize,
+    pub bytes: Vec<u8>,
+    pub is_code: bool,
+}
+
+// a disassembled ROM: code found by following every path the program can take from its start, everything else is data
+#[derive(Debug, Clone)]
+pub struct Disassembly {
+    pub items: Vec<DisasmItem>,
+    pub labels: BTreeMap<usize, String>,
+}
+
+// what a traced instruction does to the flow of the program
+pub(super) enum Flow {
+    Next, // carries on with the next instruction
+    Skip, // carries on with the next instruction, or skips it
+    Jump(usize), // goes somewhere else
+    Call(usize), // goes somewhere else and comes back
+    Stop, // doesn't go anywhere that can be followed
+}
+
+// works out where an instruction can go next
+pub(super) fn instruction_flow(instruction: &CPUInstruction) -> Flow {
+    match instruction {
+        CPUInstruction::Jump { addr: CPUInstrTarget::Constant(addr) } => Flow::Jump(*addr as usize),
+        CPUInstruction::CallSubroutine { addr: CPUInstrTarget::Constant(addr) } => Flow::Call(*addr as usize),
+        CPUInstruction::CompareEq { .. } => Flow::Skip,
+
+        // the target of BNNN depends on a register, but it usually points at a table of jumps starting at NNN
+        CPUInstruction::SpecialJump { offset: CPUInstrTarget::Constant(addr) } => Flow::Jump(*addr as usize),
+
+        CPUInstruction::Return
+        | CPUInstruction::Exit
+        | CPUInstruction::CallMachineCode { .. }
+        | CPUInstruction::Unknown { .. }
+        | CPUInstruction::ALUOperation { op: ALUOperations::Unknown, .. } => Flow::Stop,
+
+        _ => Flow::Next,
+    }
+}
+
+// gets the data address an instruction points I at, if any
+pub(super) fn data_reference(instruction: &CPUInstruction, long_addr: u16) -> Option<usize> {
+    match instruction {
+        CPUInstruction::Assignment { to: CPUInstrTarget::IRegister, from: CPUInstrTarget::Constant(addr) } => Some(*addr as usize),
+        CPUInstruction::LongLoadI => Some(long_addr as usize),
+        _ => None,
+    }
+}
+
+// reads the opcode at an offset into the ROM, along with the word after it (the address of F000 NNNN)
+pub(super) fn read_opcode(rom: &[u8], offset: usize) -> Option<(u16, u16)> {
+    let word = |offset: usize| Some(((*rom.get(offset)? as u16) << 8) | *rom.get(offset + 1)? as u16);
+    let opcode = word(offset)?;
+
+    if Chip8::instruction_length(opcode) == 4 {
+        Some((opcode, word(offset + 2)?))
+    } else {
+        Some((opcode, 0))
+    }
+}
+
+impl Disassembly {
+    // disassembles a ROM loaded at PROGRAM_START
+    pub fn new(rom: &[u8]) -> Disassembly {
+        let end = PROGRAM_START + rom.len();
+        let in_rom = |addr: usize| (PROGRAM_START..end).contains(&addr);
+
+        // follow every path through the program, noting where instructions start and what gets jumped to, called and pointed at
+        let mut code = BTreeSet::new();
+        let mut jumps = BTreeSet::new();
+        let mut calls = BTreeSet::new();
+        let mut data = BTreeSet::new();
+        let mut pending = vec![PROGRAM_START];
+
+        while let Some(addr) = pending.pop() {
+            if !in_rom(addr) || code.contains(&addr) {
+                continue;
+            }
+
+            let Some((opcode, long_addr)) = read_opcode(rom, addr - PROGRAM_START) else {
+                continue;
+            };
+            let instruction = Chip8::opcode_to_instruction(opcode);
+            let next = addr + Chip8::instruction_length(opcode);
+
+            code.insert(addr);
+
+            if let Some(target) = data_reference(&instruction, long_addr) {
+                data.insert(target);
+            }
+
+            match instruction_flow(&instruction) {
+                Flow::Next => pending.push(next),
+                Flow::Skip => {
+                    pending.push(next);
+
+                    if let Some((next_opcode, _)) = read_opcode(rom, next - PROGRAM_START) {
+                        pending.push(next + Chip8::instruction_length(next_opcode));
+                    }
+                },
+                Flow::Jump(target) => {
+                    jumps.insert(target);
+                    pending.push(target);
+                },
+                Flow::Call(target) => {
+                    calls.insert(target);
+                    pending.push(target);
+                    pending.push(next);
+                },
+                Flow::Stop => {},
+            }
+        }
+
+        // lay the ROM out as instructions where code was found, and data lines of up to 8 bytes everywhere else
+        let mut items = Vec::new();
+        let mut addr = PROGRAM_START;
+
+        while addr < end {
+            let length = if code.contains(&addr) {
+                read_opcode(rom, addr - PROGRAM_START).map_or(1, |(opcode, _)| Chip8::instruction_length(opcode))
+            } else {
+                (addr + 1..end)
+                    .take(7)
+                    .take_while(|addr| !code.contains(addr) && !jumps.contains(addr) && !calls.contains(addr) && !data.contains(addr))
+                    .count() + 1
+            };
+
+            items.push(DisasmItem {
+                addr,
+                bytes: rom[addr - PROGRAM_START..addr + length - PROGRAM_START].to_vec(),
+                is_code: code.contains(&addr),
+            });
+
+            addr += length;
+        }
+
+        // only label addresses that start a line (code that is jumped into the middle of gets referred to by its address)
+        let line_starts = items.iter().map(|item| item.addr).collect::<BTreeSet<_>>();
+        let mut labels = BTreeMap::new();
+
+        for (targets, prefix) in [(&data, "data"), (&jumps, "label"), (&calls, "sub")] {
+            for &target in targets.iter().filter(|target| line_starts.contains(target)) {
+                labels.insert(target, format!("{prefix}_{target:03X}"));
+            }
+        }
+
+        if line_starts.contains(&PROGRAM_START) {
+            labels.insert(PROGRAM_START, String::from("main"));
+        }
+
+        Disassembly { items, labels }
+    }
+
+    // returns true if the byte at an address was found to be part of an instruction
+    pub fn is_code(&self, addr: usize) -> bool {
+        self.items
+            .iter()
+            .any(|item| item.is_code && (item.addr..item.addr + item.bytes.len()).contains(&addr))
+    }
+
+    // writes an address as its label if it has one
+    fn address_text(&self, addr: usize) -> String {
+        match self.labels.get(&addr) {
+            Some(label) => label.clone(),
+            None => format!("0x{addr:03X}"),
+        }
+    }
+
+    // writes the text of a single item (without its address, bytes or label)
+    pub fn item_text(&self, item: &DisasmItem, syntax: DisasmSyntax) -> String {
+        let instruction_text = item.is_code
+            .then(|| read_opcode(&item.bytes, 0))
+            .flatten()
+            .and_then(|(opcode, long_addr)| format_instruction(opcode, long_addr, syntax, &|addr| self.address_text(addr)));
+
+        if let Some(text) = instruction_text {
+            return text;
+        }
+
+        let bytes = item.bytes.iter().map(|byte| format!("0x{byte:02X}")).collect::<Vec<_>>();
+
+        match syntax {
+            DisasmSyntax::Classic => format!("DB {}", bytes.join(", ")),
+            DisasmSyntax::Octo => bytes.join(" "),
+        }
+    }
+
+    // writes the whole disassembly as text. the Octo syntax version can be assembled again
+    pub fn to_text(&self, syntax: DisasmSyntax) -> String {
+        self.to_annotated_text(syntax, |_| None)
+    }
+
+    // writes the whole disassembly as text, with an optional comment after each item
+    pub fn to_annotated_text(&self, syntax: DisasmSyntax, annotate: impl Fn(&DisasmItem) -> Option<String>) -> String {
+        let mut out = String::new();
+
+        for item in &self.items {
+            let bytes = item.bytes.iter().map(|byte| format!("{byte:02X}")).collect::<String>();
+            let text = self.item_text(item, syntax);
+            let annotation = annotate(item).map(|note| format!(" {note}")).unwrap_or_default();
+
+            match syntax {
+                DisasmSyntax::Classic => {
+                    if let Some(label) = self.labels.get(&item.addr) {
+                        out.push_str(&format!("{label}:\n"));
+                    }
+
+                    out.push_str(&format!("{:04X}: {bytes:<16} {text}", item.addr));
+                    if !annotation.is_empty() {
+                        out.push_str(&format!(" ;{annotation}"));
+                    }
+                },
+
+                DisasmSyntax::Octo => {
+                    if let Some(label) = self.labels.get(&item.addr) {
+                        out.push_str(&format!(": {label}\n"));
+                    }
+
+                    out.push_str(&format!("\t{text:<48} # {:04X}: {bytes}{annotation}", item.addr));
+                },
+            }
+
+            out.push('\n');
+        }
+
+        out
+    }
+}
+
+// writes a single instruction, returns None if it has no mnemonic in the syntax (so it should be written as data)
+pub fn format_instruction(opcode: u16, long_addr: u16, syntax: DisasmSyntax, address_text: &dyn Fn(usize) -> String) -> Option<String> {
+    let instruction = Chip8::opcode_to_instruction(opcode);
+    let classic = syntax == DisasmSyntax::Classic;
+
+    let reg = |reg: &usize| if classic { format!("V{reg:X}") } else { format!("v{reg:x}") };
+    let num = |num: &u16| format!("0x{num:02X}");
+    let addr = |addr: &u16| address_text(*addr as usize);
+
+    let text = match &instruction {
+        CPUInstruction::CallMachineCode { addr: CPUInstrTarget::Constant(target) } if classic => format!("SYS {}", addr(target)),
+        CPUInstruction::ClearDisplay => String::from(if classic { "CLS" } else { "clear" }),
+        CPUInstruction::Return => String::from(if classic { "RET" } else { "return" }),
+        CPUInstruction::ScrollDown { rows: CPUInstrTarget::Constant(rows) } => if classic { format!("SCD {rows}") } else { format!("scroll-down {rows}") },
+        CPUInstruction::ScrollUp { rows: CPUInstrTarget::Constant(rows) } => if classic { format!("SCU {rows}") } else { format!("scroll-up {rows}") },
+        CPUInstruction::ScrollRight => String::from(if classic { "SCR" } else { "scroll-right" }),
+        CPUInstruction::ScrollLeft => String::from(if classic { "SCL" } else { "scroll-left" }),
+        CPUInstruction::Exit => String::from(if classic { "EXIT" } else { "exit" }),
+        CPUInstruction::SetHighRes { enabled } => String::from(match (classic, enabled) {
+            (true, true) => "HIGH",
+            (true, false) => "LOW",
+            (false, true) => "hires",
+            (false, false) => "lores",
+        }),
+        CPUInstruction::Jump { addr: CPUInstrTarget::Constant(target) } => if classic { format!("JP {}", addr(target)) } else { format!("jump {}", addr(target)) },
+        CPUInstruction::CallSubroutine { addr: CPUInstrTarget::Constant(target) } => {
+            let target = addr(target);
+
+            // Octo calls a subroutine by just writing its name
+            match (classic, target.starts_with("0x")) {
+                (true, _) => format!("CALL {target}"),
+                (false, true) => format!(":call {target}"),
+                (false, false) => target,
+            }
+        },
+        CPUInstruction::CompareEq { eq, left, right } => {
+            // Octo writes the condition for executing the next instruction, which is the opposite of the condition for skipping it
+            let (skip, octo_op) = if *eq { ("SE", "!=") } else { ("SNE", "==") };
+
+            match (left, right) {
+                (CPUInstrTarget::VRegister(x), CPUInstrTarget::Constant(n)) => if classic {
+                    format!("{skip} {}, {}", reg(x), num(n))
+                } else {
+                    format!("if {} {octo_op} {} then", reg(x), num(n))
+                },
+                (CPUInstrTarget::VRegister(x), CPUInstrTarget::VRegister(y)) => if classic {
+                    format!("{skip} {}, {}", reg(x), reg(y))
+                } else {
+                    format!("if {} {octo_op} {} then", reg(x), reg(y))
+                },
+                (CPUInstrTarget::IsKeyInVRegPressed(x), CPUInstrTarget::True) => match (classic, eq) {
+                    (true, true) => format!("SKP {}", reg(x)),
+                    (true, false) => format!("SKNP {}", reg(x)),
+                    (false, true) => format!("if {} -key then", reg(x)),
+                    (false, false) => format!("if {} key then", reg(x)),
+                },
+                _ => return None,
+            }
+        },
+        CPUInstruction::Assignment { to, from } => match (to, from) {
+            (CPUInstrTarget::VRegister(x), CPUInstrTarget::Constant(n)) => if classic { format!("LD {}, {}", reg(x), num(n)) } else { format!("{} := {}", reg(x), num(n)) },
+            (CPUInstrTarget::IRegister, CPUInstrTarget::Constant(target)) => if classic { format!("LD I, {}", addr(target)) } else { format!("i := {}", addr(target)) },
+            (CPUInstrTarget::VRegister(x), CPUInstrTarget::RandomNum(mask)) => if classic { format!("RND {}, 0x{mask:02X}", reg(x)) } else { format!("{} := random 0x{mask:02X}", reg(x)) },
+            (CPUInstrTarget::VRegister(x), CPUInstrTarget::CurrentDelayTimer) => if classic { format!("LD {}, DT", reg(x)) } else { format!("{} := delay", reg(x)) },
+            (CPUInstrTarget::VRegister(x), CPUInstrTarget::CurrentKeyPressed) => if classic { format!("LD {}, K", reg(x)) } else { format!("{} := key", reg(x)) },
+            (CPUInstrTarget::CurrentDelayTimer, CPUInstrTarget::VRegister(x)) => if classic { format!("LD DT, {}", reg(x)) } else { format!("delay := {}", reg(x)) },
+            (CPUInstrTarget::CurrentSoundTimer, CPUInstrTarget::VRegister(x)) => if classic { format!("LD ST, {}", reg(x)) } else { format!("buzzer := {}", reg(x)) },
+            (CPUInstrTarget::IRegister, CPUInstrTarget::SpriteAddress(x)) => if classic { format!("LD F, {}", reg(x)) } else { format!("i := hex {}", reg(x)) },
+            (CPUInstrTarget::IRegister, CPUInstrTarget::LargeSpriteAddress(x)) => if classic { format!("LD HF, {}", reg(x)) } else { format!("i := bighex {}", reg(x)) },
+            _ => return None,
+        },
+        CPUInstruction::ALUOperation { op, left, right } => match (left, right) {
+            (CPUInstrTarget::IRegister, CPUInstrTarget::VRegister(x)) => if classic { format!("ADD I, {}", reg(x)) } else { format!("i += {}", reg(x)) },
+            (CPUInstrTarget::VRegister(x), CPUInstrTarget::Constant(n)) => if classic { format!("ADD {}, {}", reg(x), num(n)) } else { format!("{} += {}", reg(x), num(n)) },
+            (CPUInstrTarget::VRegister(x), CPUInstrTarget::VRegister(y)) => {
+                let (mnemonic, octo_op) = match op {
+                    ALUOperations::Assign => ("LD", ":="),
+                    ALUOperations::Or => ("OR", "|="),
+                    ALUOperations::And => ("AND", "&="),
+                    ALUOperations::Xor => ("XOR", "^="),
+                    ALUOperations::Add { .. } => ("ADD", "+="),
+                    ALUOperations::Subtract { .. } => ("SUB", "-="),
+                    ALUOperations::SubtractFlipped { .. } => ("SUBN", "=-"),
+                    ALUOperations::ShiftRight { .. } => ("SHR", ">>="),
+                    ALUOperations::ShiftLeft { .. } => ("SHL", "<<="),
+                    ALUOperations::Unknown => return None,
+                };
+
+                if classic { format!("{mnemonic} {}, {}", reg(x), reg(y)) } else { format!("{} {octo_op} {}", reg(x), reg(y)) }
+            },
+            _ => return None,
+        },
+        CPUInstruction::SpecialJump { offset: CPUInstrTarget::Constant(target) } => if classic { format!("JP V0, {}", addr(target)) } else { format!("jump0 {}", addr(target)) },
+        CPUInstruction::Draw { x_reg: CPUInstrTarget::VRegister(x), y_reg: CPUInstrTarget::VRegister(y), height_px: CPUInstrTarget::Constant(n) } => {
+            if classic { format!("DRW {}, {}, {n}", reg(x), reg(y)) } else { format!("sprite {} {} {n}", reg(x), reg(y)) }
+        },
+        CPUInstruction::Bcd { x_reg: CPUInstrTarget::VRegister(x) } => if classic { format!("LD B, {}", reg(x)) } else { format!("bcd {}", reg(x)) },
+        CPUInstruction::RegisterDump { x: CPUInstrTarget::Constant(x) } => if classic { format!("LD [I], {}", reg(&(*x as usize))) } else { format!("save {}", reg(&(*x as usize))) },
+        CPUInstruction::RegisterLoad { x: CPUInstrTarget::Constant(x) } => if classic { format!("LD {}, [I]", reg(&(*x as usize))) } else { format!("load {}", reg(&(*x as usize))) },
+        CPUInstruction::RegisterRangeDump { x: CPUInstrTarget::Constant(x), y: CPUInstrTarget::Constant(y) } => {
+            let (x, y) = (reg(&(*x as usize)), reg(&(*y as usize)));
+            if classic { format!("SAVE {x} - {y}") } else { format!("save {x} - {y}") }
+        },
+        CPUInstruction::RegisterRangeLoad { x: CPUInstrTarget::Constant(x), y: CPUInstrTarget::Constant(y) } => {
+            let (x, y) = (reg(&(*x as usize)), reg(&(*y as usize)));
+            if classic { format!("LOAD {x} - {y}") } else { format!("load {x} - {y}") }
+        },
+        CPUInstruction::LongLoadI => if classic { format!("LD I, LONG {}", addr(&long_addr)) } else { format!("i := long {}", addr(&long_addr)) },
+        CPUInstruction::SelectPlanes { planes: CPUInstrTarget::Constant(planes) } => if classic { format!("PLANE {planes}") } else { format!("plane {planes}") },
+        CPUInstruction::LoadAudioPattern => String::from(if classic { "AUDIO" } else { "audio" }),
+        CPUInstruction::SetPitch { x_reg: CPUInstrTarget::VRegister(x) } => if classic { format!("PITCH {}", reg(x)) } else { format!("pitch := {}", reg(x)) },
+        CPUInstruction::SaveFlags { x: CPUInstrTarget::Constant(x) } => if classic { format!("LD R, {}", reg(&(*x as usize))) } else { format!("saveflags {}", reg(&(*x as usize))) },
+        CPUInstruction::LoadFlags { x: CPUInstrTarget::Constant(x) } => if classic { format!("LD {}, R", reg(&(*x as usize))) } else { format!("loadflags {}", reg(&(*x as usize))) },
+        _ => return None,
+    };
+
+    Some(text)
+}
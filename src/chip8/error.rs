@@ -0,0 +1,146 @@
+use std::fmt;
+
+// the reason an instruction could not be executed
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Chip8ErrorReason {
+    MachineCodeCall { addr: u16 },
+    UnknownInstruction,
+    UnknownALUOperation,
+    ImmutableTarget,
+    MemoryOutOfBounds { addr: usize },
+    InvalidRegister { reg: usize },
+    InvalidKey { key: usize },
+    InvalidSpriteHeight { height: usize },
+    StackOverflow { depth: usize },
+    StackUnderflow,
+}
+
+impl fmt::Display for Chip8ErrorReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Chip8ErrorReason::MachineCodeCall { addr } => write!(f, "machine code call to {addr:#05X} is not supported"),
+            Chip8ErrorReason::UnknownInstruction => write!(f, "unknown instruction"),
+            Chip8ErrorReason::UnknownALUOperation => write!(f, "unknown ALU operation"),
+            Chip8ErrorReason::ImmutableTarget => write!(f, "attempted to assign to an immutable target"),
+            Chip8ErrorReason::MemoryOutOfBounds { addr } => write!(f, "memory address {addr:#06X} is out of bounds"),
+            Chip8ErrorReason::InvalidRegister { reg } => write!(f, "V register {reg} does not exist"),
+            Chip8ErrorReason::InvalidKey { key } => write!(f, "key {key:#04X} does not exist"),
+            Chip8ErrorReason::InvalidSpriteHeight { height } => write!(f, "sprite height {height} is too large"),
+            Chip8ErrorReason::StackOverflow { depth } => write!(f, "stack overflow, {depth} return addresses are already on the stack"),
+            Chip8ErrorReason::StackUnderflow => write!(f, "stack underflow, returned with an empty stack"),
+        }
+    }
+}
+
+// an error that occurred while executing an instruction, along with where it happened
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Chip8Error {
+    pub pc: u16,
+    pub opcode: u16,
+    pub reason: Chip8ErrorReason,
+}
+
+impl fmt::Display for Chip8Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04X} at {:#05X}: {}", self.opcode, self.pc, self.reason)
+    }
+}
+
+impl std::error::Error for Chip8Error {}
+
+// what happens when a fault occurs. Wrap only applies to out of range values (addresses, registers, keys, sprite heights), and acts like Skip for everything else
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FaultPolicy {
+    Halt,
+    Skip,
+    Wrap,
+}
+
+impl FaultPolicy {
+    // parses a policy from its name
+    pub fn from_name(name: &str) -> Option<FaultPolicy> {
+        match name {
+            "halt" => Some(FaultPolicy::Halt),
+            "skip" => Some(FaultPolicy::Skip),
+            "wrap" => Some(FaultPolicy::Wrap),
+            _ => None,
+        }
+    }
+}
+
+// the policy used for each kind of fault
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Chip8FaultPolicies {
+    pub machine_code: FaultPolicy,
+    pub unknown_instruction: FaultPolicy,
+    pub immutable_target: FaultPolicy,
+    pub memory_out_of_bounds: FaultPolicy,
+    pub invalid_register: FaultPolicy,
+    pub invalid_key: FaultPolicy,
+    pub invalid_sprite: FaultPolicy,
+    pub stack: FaultPolicy,
+}
+
+impl Chip8FaultPolicies {
+    // creates a new set of policies, halting on every fault
+    pub fn new() -> Chip8FaultPolicies {
+        Chip8FaultPolicies {
+            machine_code: FaultPolicy::Halt,
+            unknown_instruction: FaultPolicy::Halt,
+            immutable_target: FaultPolicy::Halt,
+            memory_out_of_bounds: FaultPolicy::Halt,
+            invalid_register: FaultPolicy::Halt,
+            invalid_key: FaultPolicy::Halt,
+            invalid_sprite: FaultPolicy::Halt,
+            stack: FaultPolicy::Halt,
+        }
+    }
+
+    // gets the policy that applies to a given fault
+    pub fn get_policy(&self, reason: &Chip8ErrorReason) -> FaultPolicy {
+        match reason {
+            Chip8ErrorReason::MachineCodeCall { .. } => self.machine_code,
+            Chip8ErrorReason::UnknownInstruction | Chip8ErrorReason::UnknownALUOperation => self.unknown_instruction,
+            Chip8ErrorReason::ImmutableTarget => self.immutable_target,
+            Chip8ErrorReason::MemoryOutOfBounds { .. } => self.memory_out_of_bounds,
+            Chip8ErrorReason::InvalidRegister { .. } => self.invalid_register,
+            Chip8ErrorReason::InvalidKey { .. } => self.invalid_key,
+            Chip8ErrorReason::InvalidSpriteHeight { .. } => self.invalid_sprite,
+            Chip8ErrorReason::StackOverflow { .. } | Chip8ErrorReason::StackUnderflow => self.stack,
+        }
+    }
+
+    // sets the policy for a fault kind by name, returns false if the name is not a known fault kind
+    pub fn set_policy_by_name(&mut self, fault: &str, policy: FaultPolicy) -> bool {
+        let slot = match fault {
+            "machine_code" => &mut self.machine_code,
+            "unknown_instruction" => &mut self.unknown_instruction,
+            "immutable_target" => &mut self.immutable_target,
+            "memory_out_of_bounds" => &mut self.memory_out_of_bounds,
+            "invalid_register" => &mut self.invalid_register,
+            "invalid_key" => &mut self.invalid_key,
+            "invalid_sprite" => &mut self.invalid_sprite,
+            "stack" => &mut self.stack,
+            _ => return false,
+        };
+
+        *slot = policy;
+        true
+    }
+}
+
+impl Default for Chip8FaultPolicies {
+    fn default() -> Self {
+        Chip8FaultPolicies::new()
+    }
+}
+
+// the result of successfully stepping the CPU
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StepOutcome {
+    Executed,
+    WaitingForKey,
+    WaitingForVBlank,
+    Exited,
+    Skipped(Chip8Error),
+}
@@ -0,0 +1,157 @@
+use std::fmt::Debug;
+use std::io::Write;
+use super::Chip8;
+use super::render::Renderer;
+
+// somewhere to show the display, e.g. a terminal or a canvas
+pub trait VideoSink: Debug + Send {
+    // shows the display, called after every frame in which it changed (see Chip8::present)
+    fn present(&mut self, c8: &Chip8);
+}
+
+// something that can sound the buzzer
+pub trait AudioSink: Debug + Send {
+    // turns the buzzer on or off, called right after the instruction or vertical blank that started or stopped the
+    // sound timer (see Chip8::get_frame_position). the machine is passed along for the XO-CHIP audio pattern and pitch
+    fn set_buzzer(&mut self, on: bool, c8: &Chip8);
+
+    // called at every vertical blank, before the timers tick, for sinks that work a frame at a time
+    fn end_frame(&mut self, _c8: &Chip8) {}
+}
+
+// where the keypad is read from
+pub trait InputSource: Debug + Send {
+    // gets the status of every key as a bit mask (bit 0 = key 0), called before every frame. None leaves the keys as
+    // they are, e.g. while a movie is playing
+    fn poll_keys(&mut self) -> Option<u16>;
+}
+
+// the keyboard keys for each CHIP-8 key, in order of 0123456789ABCDEF: the left side of a QWERTY keyboard laid out like
+// the COSMAC VIP keypad (1234/QWER/ASDF/ZXCV for 123C/456D/789E/A0BF)
+pub const KEYBOARD_LAYOUT: &str = "x123qweasdzc4rfv";
+
+// gets the CHIP-8 key a keyboard key stands for, see KEYBOARD_LAYOUT
+pub fn keyboard_key(key: char) -> Option<usize> {
+    KEYBOARD_LAYOUT.find(key.to_ascii_lowercase())
+}
+
+// shows the display in a terminal, starting at the top left corner. after the first frame only the cells that changed
+// are drawn again, using cursor addressing so the terminal doesn't flicker
+#[derive(Debug, Default)]
+pub struct TerminalVideo {
+    renderer: Renderer,
+    shown: Vec<Vec<String>>, // the cells on the terminal, by row
+}
+
+impl TerminalVideo {
+    pub fn new() -> TerminalVideo {
+        TerminalVideo::with_renderer(Renderer::default())
+    }
+
+    pub fn with_renderer(renderer: Renderer) -> TerminalVideo {
+        TerminalVideo { renderer, shown: Vec::new() }
+    }
+}
+
+impl VideoSink for TerminalVideo {
+    fn present(&mut self, c8: &Chip8) {
+        let cells = self.renderer.render_cells(&c8.output.get_display_as_ints());
+        let columns = self.renderer.mode.columns_per_cell();
+        let mut out = String::new();
+
+        // everything is drawn on the first frame and when the resolution changes
+        let full = self.shown.len() != cells.len() || self.shown.first().map(Vec::len) != cells.first().map(Vec::len);
+        if full {
+            out.push_str("\x1B[0m\x1B[2J");
+        }
+
+        for (y, row) in cells.iter().enumerate() {
+            let mut x = 0;
+
+            while x < row.len() {
+                if !full && self.shown[y][x] == row[x] {
+                    x += 1;
+                    continue;
+                }
+
+                // a run of changed cells only needs the cursor moved once
+                out.push_str(&format!("\x1B[{};{}H", y + 1, x * columns + 1));
+                while x < row.len() && (full || self.shown[y][x] != row[x]) {
+                    out.push_str(&row[x]);
+                    x += 1;
+                }
+            }
+        }
+
+        if self.renderer.palette.is_some() {
+            out.push_str("\x1B[0m");
+        }
+
+        self.shown = cells;
+
+        let mut stdout = std::io::stdout().lock();
+        if let Err(e) = stdout.write_all(out.as_bytes()).and_then(|_| stdout.flush()) {
+            log::warn!("could not draw the display: {e}");
+        }
+    }
+}
+
+// rings the terminal bell when the buzzer turns on, which is as close as a terminal gets to a buzzer
+#[derive(Debug, Default)]
+pub struct TerminalBell;
+
+impl TerminalBell {
+    pub fn new() -> TerminalBell {
+        TerminalBell
+    }
+}
+
+impl AudioSink for TerminalBell {
+    fn set_buzzer(&mut self, on: bool, _c8: &Chip8) {
+        if on {
+            print!("\x07");
+            if let Err(e) = std::io::stdout().flush() {
+                log::warn!("could not ring the terminal bell: {e}");
+            }
+        }
+    }
+}
+
+impl super::Chip8 {
+    // shows the display on the video sink, if there is one. run_frame does this whenever the display changed
+    pub fn present(&mut self) {
+        if let Some(mut sink) = self.video_sink.take() {
+            sink.present(self);
+            self.video_sink = Some(sink);
+        }
+    }
+
+    // sets the keys from the input source, if there is one
+    pub(super) fn poll_input(&mut self) {
+        if let Some(keys) = self.input_source.as_mut().and_then(|source| source.poll_keys()) {
+            self.input.set_keys_mask(keys);
+        }
+    }
+
+    // tells the audio sink that a frame is over
+    pub(super) fn end_audio_frame(&mut self) {
+        if let Some(mut sink) = self.audio_sink.take() {
+            sink.end_frame(self);
+            self.audio_sink = Some(sink);
+        }
+    }
+
+    // tells the audio sink when the buzzer turns on or off
+    pub(super) fn update_buzzer(&mut self) {
+        let on = *self.timers.get_sound() > 0;
+
+        if on != self.buzzer {
+            self.buzzer = on;
+
+            if let Some(mut sink) = self.audio_sink.take() {
+                sink.set_buzzer(on, self);
+                self.audio_sink = Some(sink);
+            }
+        }
+    }
+}
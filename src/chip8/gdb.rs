@@ -0,0 +1,410 @@
+use std::collections::BTreeMap;
+use std::io::{self, Read, Write};
+use super::Chip8;
+use super::debugger::{Breakpoint, Chip8Debugger, StopReason};
+
+// the registers as gdb sees them, in `g` packet order: V0-VF, I, PC, SP (the call stack depth), DT and ST. 16 bit
+// registers are sent big endian like everything else on a CHIP-8
+pub const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.chip8.core">
+    <reg name="v0" bitsize="8" type="uint8" regnum="0"/>
+    <reg name="v1" bitsize="8" type="uint8"/>
+    <reg name="v2" bitsize="8" type="uint8"/>
+    <reg name="v3" bitsize="8" type="uint8"/>
+    <reg name="v4" bitsize="8" type="uint8"/>
+    <reg name="v5" bitsize="8" type="uint8"/>
+    <reg name="v6" bitsize="8" type="uint8"/>
+    <reg name="v7" bitsize="8" type="uint8"/>
+    <reg name="v8" bitsize="8" type="uint8"/>
+    <reg name="v9" bitsize="8" type="uint8"/>
+    <reg name="va" bitsize="8" type="uint8"/>
+    <reg name="vb" bitsize="8" type="uint8"/>
+    <reg name="vc" bitsize="8" type="uint8"/>
+    <reg name="vd" bitsize="8" type="uint8"/>
+    <reg name="ve" bitsize="8" type="uint8"/>
+    <reg name="vf" bitsize="8" type="uint8"/>
+    <reg name="i" bitsize="16" type="data_ptr"/>
+    <reg name="pc" bitsize="16" type="code_ptr"/>
+    <reg name="sp" bitsize="8" type="uint8"/>
+    <reg name="dt" bitsize="8" type="uint8"/>
+    <reg name="st" bitsize="8" type="uint8"/>
+  </feature>
+</target>
+"#;
+
+// register numbers of the registers after VF
+const REG_I: usize = 16;
+const REG_PC: usize = 17;
+const REG_SP: usize = 18;
+const REG_DT: usize = 19;
+const REG_ST: usize = 20;
+const REG_COUNT: usize = 21;
+
+// signals sent in stop replies
+const SIGINT: u8 = 2;
+const SIGILL: u8 = 4;
+const SIGTRAP: u8 = 5;
+
+// what the client wants after a packet has been handled
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GdbState {
+    Stopped, // waiting for more packets
+    Running, // continue was requested, call run until it stops
+    Detached, // the client detached or killed the program, the connection is done
+}
+
+// converts bytes to lowercase hex
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+// converts hex to bytes
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+// parses a hex number from a packet
+fn parse_hex(text: &str) -> Option<usize> {
+    usize::from_str_radix(text, 16).ok()
+}
+
+// a gdb remote serial protocol stub debugging a Chip8 instance over a connection (normally a TcpStream)
+#[derive(Debug)]
+pub struct Chip8GdbStub<T: Read + Write> {
+    connection: T,
+    debugger: Chip8Debugger,
+    breakpoints: BTreeMap<u16, usize>, // breakpoint ids by address
+    no_ack: bool,
+}
+
+impl<T: Read + Write> Chip8GdbStub<T> {
+    // creates a stub talking over a connection, the timers are ticked every instructions_per_frame instructions
+    pub fn new(connection: T, instructions_per_frame: usize) -> Chip8GdbStub<T> {
+        Chip8GdbStub {
+            connection,
+            debugger: Chip8Debugger::new(instructions_per_frame),
+            breakpoints: BTreeMap::new(),
+            no_ack: false,
+        }
+    }
+
+    // gets the connection, e.g. to check for an interrupt while running
+    pub fn get_connection_mut(&mut self) -> &mut T {
+        &mut self.connection
+    }
+
+    // reads a byte, returning None at the end of the connection
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        let mut byte = [0];
+
+        match self.connection.read(&mut byte)? {
+            0 => Ok(None),
+            _ => Ok(Some(byte[0])),
+        }
+    }
+
+    // reads the next packet, returning None at the end of the connection. an interrupt (0x03) outside a packet is
+    // returned as a packet of its own
+    fn read_packet(&mut self) -> io::Result<Option<String>> {
+        loop {
+            match self.read_byte()? {
+                None => return Ok(None),
+                Some(0x03) => return Ok(Some(String::from("\x03"))),
+                Some(b'$') => {},
+                Some(_) => continue, // acks and noise between packets
+            }
+
+            let mut data = Vec::new();
+            loop {
+                match self.read_byte()? {
+                    None => return Ok(None),
+                    Some(b'#') => break,
+                    Some(byte) => data.push(byte),
+                }
+            }
+
+            let checksum = [self.read_byte()?, self.read_byte()?];
+            let checksum = match checksum {
+                [Some(high), Some(low)] => from_hex(&String::from_utf8_lossy(&[high, low])).map(|bytes| bytes[0]),
+                _ => return Ok(None),
+            };
+
+            if checksum != Some(data.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte))) && !self.no_ack {
+                self.connection.write_all(b"-")?;
+                continue;
+            }
+
+            if !self.no_ack {
+                self.connection.write_all(b"+")?;
+            }
+
+            // undo the escaping of binary data
+            let mut unescaped = Vec::with_capacity(data.len());
+            let mut bytes = data.into_iter();
+            while let Some(byte) = bytes.next() {
+                match byte {
+                    b'}' => unescaped.push(bytes.next().unwrap_or(0) ^ 0x20),
+                    _ => unescaped.push(byte),
+                }
+            }
+
+            return Ok(Some(String::from_utf8_lossy(&unescaped).into_owned()));
+        }
+    }
+
+    // sends a packet
+    fn send_packet(&mut self, data: &str) -> io::Result<()> {
+        let mut escaped = Vec::with_capacity(data.len());
+        for byte in data.bytes() {
+            match byte {
+                b'$' | b'#' | b'}' | b'*' => escaped.extend([b'}', byte ^ 0x20]),
+                _ => escaped.push(byte),
+            }
+        }
+
+        let checksum = escaped.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+
+        self.connection.write_all(b"$")?;
+        self.connection.write_all(&escaped)?;
+        self.connection.write_all(format!("#{checksum:02x}").as_bytes())?;
+        self.connection.flush()
+    }
+
+    // reads a register as big endian bytes
+    fn read_register(c8: &Chip8, reg: usize) -> Option<Vec<u8>> {
+        Some(match reg {
+            0..=15 => vec![*c8.registers.get_v_register(reg).ok()?],
+            REG_I => c8.registers.get_i_register().to_be_bytes().to_vec(),
+            REG_PC => c8.registers.get_pc_register().to_be_bytes().to_vec(),
+            REG_SP => vec![c8.stack.get_depth() as u8],
+            REG_DT => vec![*c8.timers.get_delay()],
+            REG_ST => vec![*c8.timers.get_sound()],
+            _ => return None,
+        })
+    }
+
+    // writes a register from big endian bytes, returns the number of bytes used. the stack depth can't be changed
+    // this way, so writes to SP are ignored
+    fn write_register(c8: &mut Chip8, reg: usize, bytes: &[u8]) -> Option<usize> {
+        let size = if reg == REG_I || reg == REG_PC { 2 } else { 1 };
+        let bytes = bytes.get(..size)?;
+
+        match reg {
+            0..=15 => *c8.registers.get_v_register_mut(reg).ok()? = bytes[0],
+            REG_I => *c8.registers.get_i_register_mut() = u16::from_be_bytes([bytes[0], bytes[1]]),
+            REG_PC => *c8.registers.get_pc_register_mut() = u16::from_be_bytes([bytes[0], bytes[1]]),
+            REG_SP => {},
+            REG_DT => *c8.timers.get_delay_mut() = bytes[0],
+            REG_ST => *c8.timers.get_sound_mut() = bytes[0],
+            _ => return None,
+        }
+
+        Some(size)
+    }
+
+    // turns why execution stopped into a stop reply
+    fn stop_reply(reason: &StopReason) -> String {
+        match reason {
+            StopReason::Exited => String::from("W00"),
+            StopReason::Fault(_) => format!("S{SIGILL:02x}"),
+            StopReason::Paused => format!("S{SIGINT:02x}"),
+            _ => format!("S{SIGTRAP:02x}"),
+        }
+    }
+
+    // handles a single packet, returning the reply (None if there is nothing to reply yet) and the new state
+    fn handle_packet(&mut self, c8: &mut Chip8, packet: &str) -> (Option<String>, GdbState) {
+        let (command, args) = packet.split_at(packet.chars().next().map_or(0, char::len_utf8));
+        let error = String::from("E01");
+
+        let reply = match command {
+            "?" => format!("S{SIGTRAP:02x}"),
+
+            "\x03" => format!("S{SIGINT:02x}"),
+
+            "g" => (0..REG_COUNT)
+                .filter_map(|reg| Self::read_register(c8, reg))
+                .map(|bytes| to_hex(&bytes))
+                .collect(),
+
+            "G" => {
+                let Some(bytes) = from_hex(args) else {
+                    return (Some(error), GdbState::Stopped);
+                };
+
+                let mut pos = 0;
+                for reg in 0..REG_COUNT {
+                    match Self::write_register(c8, reg, &bytes[pos.min(bytes.len())..]) {
+                        Some(size) => pos += size,
+                        None => break,
+                    }
+                }
+
+                String::from("OK")
+            },
+
+            "p" => parse_hex(args)
+                .and_then(|reg| Self::read_register(c8, reg))
+                .map_or(error, |bytes| to_hex(&bytes)),
+
+            "P" => {
+                let written = args.split_once('=')
+                    .and_then(|(reg, val)| Some((parse_hex(reg)?, from_hex(val)?)))
+                    .and_then(|(reg, bytes)| Self::write_register(c8, reg, &bytes));
+
+                written.map_or(error, |_| String::from("OK"))
+            },
+
+            "m" => {
+                let range = args.split_once(',').and_then(|(addr, len)| Some((parse_hex(addr)?, parse_hex(len)?)));
+
+                match range.and_then(|(addr, len)| Some((addr, addr.checked_add(len)?))) {
+                    Some((start, end)) if end <= c8.memory.get_size() => to_hex(&c8.memory.get_bytes()[start..end]),
+                    _ => error,
+                }
+            },
+
+            "M" => {
+                let write = args.split_once(':')
+                    .and_then(|(range, data)| Some((range.split_once(',')?, from_hex(data)?)))
+                    .and_then(|((addr, _), data)| Some((parse_hex(addr)?, data)));
+
+                match write.and_then(|(addr, data)| Some((addr, addr.checked_add(data.len())?, data))) {
+                    Some((start, end, data)) if end <= c8.memory.get_size() => {
+                        c8.memory.get_bytes_mut()[start..end].copy_from_slice(&data);
+                        String::from("OK")
+                    },
+                    _ => error,
+                }
+            },
+
+            // software and hardware breakpoints both just stop before the instruction at the address
+            "Z" | "z" => {
+                let mut fields = args.split(',');
+                let kind = fields.next();
+                let addr = fields.next().and_then(parse_hex).and_then(|addr| u16::try_from(addr).ok());
+
+                match (kind, addr) {
+                    (Some("0" | "1"), Some(addr)) if command == "Z" => {
+                        if !self.breakpoints.contains_key(&addr) {
+                            let id = self.debugger.add_breakpoint(Breakpoint { addr: Some(addr), condition: None });
+                            self.breakpoints.insert(addr, id);
+                        }
+
+                        String::from("OK")
+                    },
+                    (Some("0" | "1"), Some(addr)) => {
+                        if let Some(id) = self.breakpoints.remove(&addr) {
+                            self.debugger.delete(id);
+                        }
+
+                        String::from("OK")
+                    },
+                    (Some("0" | "1"), None) => error,
+                    _ => String::new(), // watchpoints aren't supported
+                }
+            },
+
+            "s" | "c" => {
+                if !args.is_empty() {
+                    // the PC is 16 bits, so a bigger address can't be resumed at
+                    let Some(addr) = parse_hex(args).and_then(|addr| u16::try_from(addr).ok()) else {
+                        return (Some(error), GdbState::Stopped);
+                    };
+
+                    *c8.registers.get_pc_register_mut() = addr;
+                }
+
+                if command == "c" {
+                    return (None, GdbState::Running);
+                }
+
+                let reason = self.debugger.step(c8);
+                Self::stop_reply(&reason)
+            },
+
+            "k" => return (None, GdbState::Detached),
+
+            "D" => return (Some(String::from("OK")), GdbState::Detached),
+
+            "H" | "T" => String::from("OK"),
+
+            "q" | "Q" => match packet {
+                _ if packet.starts_with("qSupported") => String::from("PacketSize=4000;qXfer:features:read+;QStartNoAckMode+"),
+                "QStartNoAckMode" => {
+                    // the OK itself is still acked
+                    self.send_packet("OK").ok();
+                    self.no_ack = true;
+                    return (None, GdbState::Stopped);
+                },
+                "qAttached" => String::from("1"),
+                "qC" => String::from("QC1"),
+                "qfThreadInfo" => String::from("m1"),
+                "qsThreadInfo" => String::from("l"),
+                _ if packet.starts_with("qXfer:features:read:target.xml:") => {
+                    let range = packet["qXfer:features:read:target.xml:".len()..]
+                        .split_once(',')
+                        .and_then(|(offset, len)| Some((parse_hex(offset)?, parse_hex(len)?)));
+
+                    match range.and_then(|(offset, len)| Some((offset, offset.checked_add(len)?))) {
+                        Some((offset, end)) => {
+                            let start = offset.min(TARGET_XML.len());
+                            let end = end.min(TARGET_XML.len());
+                            let marker = if end == TARGET_XML.len() { 'l' } else { 'm' };
+                            format!("{marker}{}", &TARGET_XML[start..end])
+                        },
+                        None => error,
+                    }
+                },
+                _ => String::new(),
+            },
+
+            _ => String::new(), // an empty reply means the packet isn't supported
+        };
+
+        (Some(reply), GdbState::Stopped)
+    }
+
+    // handles packets until the client continues or detaches (also returning Detached when the connection is closed)
+    pub fn serve(&mut self, c8: &mut Chip8) -> io::Result<GdbState> {
+        loop {
+            let Some(packet) = self.read_packet()? else {
+                return Ok(GdbState::Detached);
+            };
+
+            let (reply, state) = self.handle_packet(c8, &packet);
+            if let Some(reply) = reply {
+                self.send_packet(&reply)?;
+            }
+
+            if state != GdbState::Stopped {
+                return Ok(state);
+            }
+        }
+    }
+
+    // runs up to limit instructions after a continue, sending the stop reply and returning true if execution stopped
+    pub fn run(&mut self, c8: &mut Chip8, limit: usize) -> io::Result<bool> {
+        match self.debugger.resume(c8, limit) {
+            Some(reason) => {
+                self.send_packet(&Self::stop_reply(&reason))?;
+                Ok(true)
+            },
+            None => Ok(false),
+        }
+    }
+
+    // stops a running program because the client sent an interrupt
+    pub fn interrupt(&mut self) -> io::Result<()> {
+        self.send_packet(&Self::stop_reply(&StopReason::Paused))
+    }
+}
@@ -20,13 +20,28 @@ impl Chip8Input {
         &mut self.keys_status
     }
 
+    // gets the status of every key as a bit mask (bit 0 = key 0)
+    pub fn get_keys_mask(&self) -> u16 {
+        self.keys_status
+            .iter()
+            .enumerate()
+            .fold(0, |keys, (i, &pressed)| keys | ((pressed as u16) << i))
+    }
+
+    // sets the status of every key from a bit mask (bit 0 = key 0)
+    pub fn set_keys_mask(&mut self, keys: u16) {
+        for (i, pressed) in self.keys_status.iter_mut().enumerate() {
+            *pressed = keys & (1 << i) != 0;
+        }
+    }
+
     // gets the first currently pressed key (returns the position in the keys_status array)
     pub fn get_current_key(&self) -> Option<usize> {
         self.keys_status
             .iter()
             .enumerate()
-            .filter(|(i, x)| **x == true)
-            .map(|(i, x)| i)
+            .filter(|(_, &x)| x)
+            .map(|(i, _)| i)
             .next()
     }
 }
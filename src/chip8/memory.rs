@@ -1,3 +1,5 @@
+use super::error::Chip8ErrorReason;
+
 static FONT_DATA: [u8; 80] = [
     0xF0, 0x90, 0x90, 0x90, 0xF0,		// 0
 	0x20, 0x60, 0x20, 0x20, 0x70,		// 1
@@ -17,46 +19,81 @@ static FONT_DATA: [u8; 80] = [
 	0xF0, 0x80, 0xF0, 0x80, 0x80		// F
 ];
 
+// SUPER-CHIP large font, 10 bytes per character (A-F are the XO-CHIP additions)
+static BIG_FONT_DATA: [u8; 160] = [
+    0xFF, 0xFF, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF,		// 0
+    0x18, 0x78, 0x78, 0x18, 0x18, 0x18, 0x18, 0x18, 0xFF, 0xFF,		// 1
+    0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF,		// 2
+    0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF,		// 3
+    0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0x03, 0x03,		// 4
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF,		// 5
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF,		// 6
+    0xFF, 0xFF, 0x03, 0x03, 0x06, 0x0C, 0x18, 0x18, 0x18, 0x18,		// 7
+    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF,		// 8
+    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF,		// 9
+    0x7E, 0xFF, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3,		// A
+    0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC,		// B
+    0x3C, 0xFF, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0xFF, 0x3C,		// C
+    0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC,		// D
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF,		// E
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xC0, 0xC0		// F
+];
+
+// where the large font starts in memory, right after the regular font
+pub const BIG_FONT_ADDR: usize = 0x50;
+
+// the size of the XO-CHIP address space, classic programs can only use the first 4 KiB of it
+pub const MEMORY_SIZE: usize = 0x10000;
+
 #[derive(Debug)]
 pub struct Chip8Memory {
-    mem: [u8; 4096],
-    stack_ptr: u16,
+    mem: Vec<u8>,
 }
 
 impl Chip8Memory {
     // creates a new blank instance of memory (all 0s)
     pub fn new() -> Chip8Memory {
         Chip8Memory {
-            mem: [0; 4096],
-            stack_ptr: 0x0EFF,
+            mem: vec![0; MEMORY_SIZE],
         }
     }
 
-    // gets a specific memory address immutably
-    pub fn get_memory_at(&self, addr: usize) -> &u8 {
-        assert!(addr < 4096);
+    // gets the size of memory in bytes
+    pub fn get_size(&self) -> usize {
+        self.mem.len()
+    }
 
-        &self.mem[addr]
+    // gets all of memory immutably
+    pub fn get_bytes(&self) -> &[u8] {
+        &self.mem
+    }
+
+    // gets all of memory mutably
+    pub fn get_bytes_mut(&mut self) -> &mut [u8] {
+        &mut self.mem
+    }
+
+    // gets a specific memory address immutably
+    pub fn get_memory_at(&self, addr: usize) -> Result<&u8, Chip8ErrorReason> {
+        self.mem.get(addr).ok_or(Chip8ErrorReason::MemoryOutOfBounds { addr })
     }
 
     // gets a specific memory address immutably, but as a 16-bit number (big-endian)
-    pub fn get_memory_at_u16(&self, addr: usize) -> u16 {
-        let higher = self.get_memory_at(addr).clone() as u16;
-        let lower = self.get_memory_at(addr + 1).clone() as u16;
+    pub fn get_memory_at_u16(&self, addr: usize) -> Result<u16, Chip8ErrorReason> {
+        let higher = *self.get_memory_at(addr)? as u16;
+        let lower = *self.get_memory_at(addr + 1)? as u16;
 
         // convert to 16bit using big endian
-        (higher << 8) + lower
+        Ok((higher << 8) + lower)
     }
 
     // gets a specific memory address mutably
-    pub fn get_memory_at_mut(&mut self, addr: usize) -> &mut u8 {
-        assert!(addr < 4096);
-
-        &mut self.mem[addr]
+    pub fn get_memory_at_mut(&mut self, addr: usize) -> Result<&mut u8, Chip8ErrorReason> {
+        self.mem.get_mut(addr).ok_or(Chip8ErrorReason::MemoryOutOfBounds { addr })
     }
 
-    // loads the program into memory starting at 0x200
-    pub fn load_program_into_mem(&mut self, program: &Vec<u16>) {
+    // loads the program into memory starting at 0x200, anything that does not fit is dropped
+    pub fn load_program_into_mem(&mut self, program: &[u16]) {
         program
             .iter()
             .map(|opcode| (((opcode & 0xFF00) >> 8) as u8, (opcode & 0x00FF) as u8)) // split into higher and lower bytes
@@ -65,49 +102,26 @@ impl Chip8Memory {
                 // program starts at addr 0x200, each opcode takes up 2 bytes
                 let (high_addr, low_addr) = (0x200 + 2 * i, 0x201 + 2 * i);
 
-                *self.get_memory_at_mut(high_addr) = higher;
-                *self.get_memory_at_mut(low_addr) = lower;
+                if let Ok(byte) = self.get_memory_at_mut(high_addr) {
+                    *byte = higher;
+                }
+
+                if let Ok(byte) = self.get_memory_at_mut(low_addr) {
+                    *byte = lower;
+                }
             });
     }
 
-    // loads the font data into memory
+    // loads the font data (and the large font data after it) into memory
     pub fn load_fonts_into_mem(&mut self) {
         FONT_DATA
             .iter()
             .zip(self.mem.iter_mut())
             .for_each(|(&byte, memory_location)| *memory_location = byte);
-    }
-
-    // pushes an 8-bit value to the stack
-    pub fn push_to_stack_u8(&mut self, val: u8) {
-        *self.get_memory_at_mut(self.stack_ptr as usize) = val;
-        self.stack_ptr -= 1;
-    }
-
-    // pushes a 16-bit value to the stack (higher byte first, lower byte second)
-    pub fn push_to_stack_u16(&mut self, val: u16) {
-        // separate bytes
-        let higher = ((val & 0xFF00) >> 8) as u8;
-        let lower = (val & 0x00FF) as u8;
-
-        // push to stack
-        self.push_to_stack_u8(higher);
-        self.push_to_stack_u8(lower);
-    }
-
-    // pops an 8-bit value from the stack
-    pub fn pop_from_stack_u8(&mut self) -> u8 {
-        self.stack_ptr += 1;
-        self.get_memory_at(self.stack_ptr as usize).clone()
-    }
 
-    // pops a 16-bit value from the satck (lower byte first, higher byte second)
-    pub fn pop_from_stack_u16(&mut self) -> u16 {
-        // pop the bytes and convert to 16-bit for proceeding arithmetic
-        let lower = self.pop_from_stack_u8() as u16;
-        let higher = self.pop_from_stack_u8() as u16;
-
-        // combine the bytes
-        (higher << 8) + lower
+        BIG_FONT_DATA
+            .iter()
+            .zip(self.mem[BIG_FONT_ADDR..].iter_mut())
+            .for_each(|(&byte, memory_location)| *memory_location = byte);
     }
 }
@@ -0,0 +1,292 @@
+use std::fmt;
+use super::Chip8;
+use super::error::{Chip8FaultPolicies, FaultPolicy};
+use super::quirks::Quirks;
+use super::savestate::{crc32, read_quirks, read_timing, unwrap_payload, wrap_payload, write_quirks, write_timing, SaveStateError, StateReader, StateWriter};
+use super::timing::TimingModel;
+
+// movie file layout: the same wrapping as save states, with its own magic and version
+const MAGIC: &[u8; 4] = b"C8MV";
+pub const MOVIE_VERSION: u16 = 3;
+
+// version 1 movies didn't store the timing or the fault policies, so they can't be replayed reliably
+const OLDEST_MOVIE_VERSION: u16 = 2;
+
+// hashes a program the way movies identify it (CRC-32 of its bytes)
+pub fn rom_hash(program: &[u16]) -> u32 {
+    let bytes = program
+        .iter()
+        .flat_map(|word| word.to_be_bytes())
+        .collect::<Vec<_>>();
+
+    crc32(&bytes)
+}
+
+// a change to the keys, happening right before the instruction with the given count is executed
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MovieEvent {
+    pub frame: u64,
+    pub instruction: u64,
+    pub keys: u16, // bit mask of the pressed keys (bit 0 = key 0)
+}
+
+// a recording of every key change from power on, along with everything else needed to replay the run exactly
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chip8Movie {
+    pub rom_hash: u32,
+    pub seed: u64,
+    pub quirks: Quirks,
+    pub timing: TimingModel,
+    pub instructions_per_frame: usize,
+    pub fault_policies: Chip8FaultPolicies,
+    pub events: Vec<MovieEvent>,
+}
+
+impl Chip8Movie {
+    // creates an empty movie for a machine that is about to run a program
+    pub fn new(c8: &Chip8, rom_hash: u32) -> Chip8Movie {
+        Chip8Movie {
+            rom_hash,
+            seed: c8.random.get_seed(),
+            quirks: c8.quirks,
+            timing: c8.timing,
+            instructions_per_frame: c8.instructions_per_frame,
+            fault_policies: c8.fault_policies,
+            events: Vec::new(),
+        }
+    }
+
+    // sets up a freshly powered on machine the same way as the recorded one (the program has to be loaded separately)
+    pub fn configure(&self, c8: &mut Chip8) {
+        c8.quirks = self.quirks;
+        c8.timing = self.timing;
+        c8.instructions_per_frame = self.instructions_per_frame;
+        c8.fault_policies = self.fault_policies;
+        c8.random.reseed(self.seed);
+    }
+
+    // serializes the movie
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut writer = StateWriter::new();
+
+        writer.write_u32(self.rom_hash);
+        writer.write_u64(self.seed);
+        write_quirks(&mut writer, &self.quirks);
+        write_timing(&mut writer, self.timing, self.instructions_per_frame);
+        write_fault_policies(&mut writer, &self.fault_policies);
+
+        writer.write_u32(self.events.len() as u32);
+        for event in &self.events {
+            writer.write_u64(event.frame);
+            writer.write_u64(event.instruction);
+            writer.write_u16(event.keys);
+        }
+
+        wrap_payload(MAGIC, MOVIE_VERSION, &writer.into_bytes())
+    }
+
+    // parses a movie made by to_bytes
+    pub fn from_bytes(data: &[u8]) -> Result<Chip8Movie, SaveStateError> {
+        let (version, payload) = unwrap_payload(MAGIC, MOVIE_VERSION, data)?;
+        if version < OLDEST_MOVIE_VERSION {
+            return Err(SaveStateError::UnsupportedVersion(version));
+        }
+
+        let mut reader = StateReader::new(payload);
+
+        let rom_hash = reader.read_u32()?;
+        let seed = reader.read_u64()?;
+
+        // version 2 movies also stored the random mode, the VIP-like one was removed so those can't be replayed
+        if version < 3 && reader.read_bool()? {
+            return Err(SaveStateError::Invalid("the VIP-like random mode is no longer supported"));
+        }
+
+        let quirks = read_quirks(&mut reader)?;
+        let (timing, instructions_per_frame) = read_timing(&mut reader)?;
+        let fault_policies = read_fault_policies(&mut reader)?;
+
+        if instructions_per_frame == 0 {
+            return Err(SaveStateError::Invalid("no instructions per frame"));
+        }
+
+        let event_count = reader.read_u32()? as usize;
+        let events = (0..event_count)
+            .map(|_| Ok(MovieEvent {
+                frame: reader.read_u64()?,
+                instruction: reader.read_u64()?,
+                keys: reader.read_u16()?,
+            }))
+            .collect::<Result<Vec<_>, SaveStateError>>()?;
+
+        if events.windows(2).any(|pair| pair[1].instruction < pair[0].instruction || pair[1].frame < pair[0].frame) {
+            return Err(SaveStateError::Invalid("events out of order"));
+        }
+
+        if !reader.is_empty() {
+            return Err(SaveStateError::Invalid("trailing data"));
+        }
+
+        Ok(Chip8Movie { rom_hash, seed, quirks, timing, instructions_per_frame, fault_policies, events })
+    }
+}
+
+// the number a fault policy is stored as
+fn fault_policy_to_u8(policy: FaultPolicy) -> u8 {
+    match policy {
+        FaultPolicy::Halt => 0,
+        FaultPolicy::Skip => 1,
+        FaultPolicy::Wrap => 2,
+    }
+}
+
+// writes the policy for every fault kind
+fn write_fault_policies(writer: &mut StateWriter, policies: &Chip8FaultPolicies) {
+    for policy in [
+        policies.machine_code,
+        policies.unknown_instruction,
+        policies.immutable_target,
+        policies.memory_out_of_bounds,
+        policies.invalid_register,
+        policies.invalid_key,
+        policies.invalid_sprite,
+        policies.stack,
+    ] {
+        writer.write_u8(fault_policy_to_u8(policy));
+    }
+}
+
+// reads fault policies written by write_fault_policies
+fn read_fault_policies(reader: &mut StateReader) -> Result<Chip8FaultPolicies, SaveStateError> {
+    let mut read_policy = || match reader.read_u8()? {
+        0 => Ok(FaultPolicy::Halt),
+        1 => Ok(FaultPolicy::Skip),
+        2 => Ok(FaultPolicy::Wrap),
+        _ => Err(SaveStateError::Invalid("fault policy")),
+    };
+
+    Ok(Chip8FaultPolicies {
+        machine_code: read_policy()?,
+        unknown_instruction: read_policy()?,
+        immutable_target: read_policy()?,
+        memory_out_of_bounds: read_policy()?,
+        invalid_register: read_policy()?,
+        invalid_key: read_policy()?,
+        invalid_sprite: read_policy()?,
+        stack: read_policy()?,
+    })
+}
+
+// a movie that no longer matches the machine playing it back: a key change was due at a different point than where
+// it was recorded, so the run has diverged from the recording
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MovieDesync {
+    pub event: MovieEvent,
+    pub frame: u64, // where the machine was when the change was due
+    pub instruction: u64,
+}
+
+impl fmt::Display for MovieDesync {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "movie out of sync: a key change recorded at frame {} (instruction {}) was played at frame {} (instruction {})",
+            self.event.frame, self.event.instruction, self.frame, self.instruction,
+        )
+    }
+}
+
+impl std::error::Error for MovieDesync {}
+
+// records key changes into a movie
+#[derive(Debug)]
+pub struct Chip8MovieRecorder {
+    movie: Chip8Movie,
+    last_keys: u16,
+}
+
+impl Chip8MovieRecorder {
+    // starts recording a machine that is about to run a program
+    pub fn new(c8: &Chip8, rom_hash: u32) -> Chip8MovieRecorder {
+        Chip8MovieRecorder {
+            movie: Chip8Movie::new(c8, rom_hash),
+            last_keys: 0,
+        }
+    }
+
+    // should be called whenever the keys might have changed (at the latest before the next instruction is executed),
+    // returns true if a change was recorded
+    pub fn record(&mut self, c8: &Chip8) -> bool {
+        let keys = c8.input.get_keys_mask();
+
+        if keys == self.last_keys {
+            return false;
+        }
+
+        self.movie.events.push(MovieEvent {
+            frame: c8.get_frame_count(),
+            instruction: c8.get_instruction_count(),
+            keys,
+        });
+        self.last_keys = keys;
+
+        true
+    }
+
+    // gets the movie recorded so far
+    pub fn get_movie(&self) -> &Chip8Movie {
+        &self.movie
+    }
+
+    // stops recording, returning the movie
+    pub fn finish(self) -> Chip8Movie {
+        self.movie
+    }
+}
+
+// plays a movie back by setting the keys at the recorded points
+#[derive(Debug)]
+pub struct Chip8MoviePlayer {
+    movie: Chip8Movie,
+    next_event: usize,
+}
+
+impl Chip8MoviePlayer {
+    // starts playing a movie back, configuring the machine like the recorded one
+    pub fn new(movie: Chip8Movie, c8: &mut Chip8) -> Chip8MoviePlayer {
+        movie.configure(c8);
+        c8.input.set_keys_mask(0);
+
+        Chip8MoviePlayer { movie, next_event: 0 }
+    }
+
+    // gets the movie being played
+    pub fn get_movie(&self) -> &Chip8Movie {
+        &self.movie
+    }
+
+    // returns true once every event has been played
+    pub fn is_finished(&self) -> bool {
+        self.next_event >= self.movie.events.len()
+    }
+
+    // must be called before every instruction, sets the keys if they changed at this point in the recording. fails if
+    // the change is due at another frame or instruction than where it was recorded, leaving the keys as they were
+    pub fn apply(&mut self, c8: &mut Chip8) -> Result<(), MovieDesync> {
+        while let Some(&event) = self.movie.events.get(self.next_event) {
+            let (frame, instruction) = (c8.get_frame_count(), c8.get_instruction_count());
+            if event.instruction > instruction {
+                break;
+            }
+
+            if event.instruction != instruction || event.frame != frame {
+                return Err(MovieDesync { event, frame, instruction });
+            }
+
+            c8.input.set_keys_mask(event.keys);
+            self.next_event += 1;
+        }
+
+        Ok(())
+    }
+}
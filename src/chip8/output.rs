@@ -1,4 +1,11 @@
-use super::{sprites::Chip8Sprite, Chip8};
+use super::sprites::Chip8Sprite;
+
+// the largest display size (SUPER-CHIP hi-res mode), the lo-res display uses the top left 64x32 pixels
+pub const MAX_DISPLAY_WIDTH: usize = 128;
+pub const MAX_DISPLAY_HEIGHT: usize = 64;
+
+// the number of XO-CHIP bitplanes, each pixel's color is made up of one bit from every plane
+pub const PLANE_COUNT: usize = 2;
 
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Chip8Pixel {
@@ -8,86 +15,222 @@ pub enum Chip8Pixel {
 
 #[derive(Debug)]
 pub struct Chip8Output {
-    pub display: [[Chip8Pixel; 64]; 32],
+    pub planes: [[[Chip8Pixel; MAX_DISPLAY_WIDTH]; MAX_DISPLAY_HEIGHT]; PLANE_COUNT],
+    hires: bool,
+    selected_planes: u8, // bit mask of the planes that drawing, clearing and scrolling apply to
 }
 
 impl Chip8Output {
     // creates a new Chip8Output instance
     pub fn new() -> Chip8Output {
         Chip8Output {
-            display: [[Chip8Pixel::Black; 64]; 32],
+            planes: [[[Chip8Pixel::Black; MAX_DISPLAY_WIDTH]; MAX_DISPLAY_HEIGHT]; PLANE_COUNT],
+            hires: false,
+            selected_planes: 1,
         }
     }
 
-    // clears display (all set to black)
+    // gets the width of the display in the current resolution
+    pub fn get_width(&self) -> usize {
+        if self.hires { 128 } else { 64 }
+    }
+
+    // gets the height of the display in the current resolution
+    pub fn get_height(&self) -> usize {
+        if self.hires { 64 } else { 32 }
+    }
+
+    // returns true if the display is in hi-res (128x64) mode
+    pub fn is_hires(&self) -> bool {
+        self.hires
+    }
+
+    // switches between lo-res and hi-res mode, which also clears the display (all planes)
+    pub fn set_hires(&mut self, hires: bool) {
+        self.hires = hires;
+
+        for plane in &mut self.planes {
+            for pixel_row_mut in plane {
+                for pixel_mut in pixel_row_mut {
+                    *pixel_mut = Chip8Pixel::Black;
+                }
+            }
+        }
+    }
+
+    // gets the bit mask of the selected planes
+    pub fn get_selected_planes(&self) -> u8 {
+        self.selected_planes
+    }
+
+    // selects the planes that drawing, clearing and scrolling apply to (bit 0 = first plane, bit 1 = second plane)
+    pub fn set_selected_planes(&mut self, planes: u8) {
+        self.selected_planes = planes & ((1 << PLANE_COUNT) - 1) as u8;
+    }
+
+    // gets the indices of the selected planes
+    pub fn selected_plane_indices(&self) -> Vec<usize> {
+        (0..PLANE_COUNT)
+            .filter(|plane| self.selected_planes & (1 << plane) != 0)
+            .collect()
+    }
+
+    // clears the selected planes of the display (all set to black)
     pub fn clear_display(&mut self) {
-        for pixel_row_mut in &mut self.display {
-            for pixel_mut in pixel_row_mut {
-                *pixel_mut = Chip8Pixel::Black;
+        for plane in self.selected_plane_indices() {
+            for pixel_row_mut in &mut self.planes[plane] {
+                for pixel_mut in pixel_row_mut {
+                    *pixel_mut = Chip8Pixel::Black;
+                }
             }
         }
     }
 
-    // toggles a pixel on the display, returns true if the pixel was flipped from white to black
-    pub fn toggle_pixel(&mut self, mut x: usize, mut y: usize) -> bool {
-        x %= 64;
-        y %= 32;
+    // toggles a pixel on a plane, returns true if the pixel was flipped from white to black
+    pub fn toggle_pixel(&mut self, plane: usize, mut x: usize, mut y: usize) -> bool {
+        x %= self.get_width();
+        y %= self.get_height();
 
         // store original pixel value
-        let orig_pixel = self.display[y][x];
+        let orig_pixel = self.planes[plane][y][x];
 
         // flip the pixel
-        self.display[y][x] = if orig_pixel == Chip8Pixel::White { Chip8Pixel::Black } else { Chip8Pixel::White };
+        self.planes[plane][y][x] = if orig_pixel == Chip8Pixel::White { Chip8Pixel::Black } else { Chip8Pixel::White };
 
         // if it was originally white, then return true
         orig_pixel == Chip8Pixel::White
     }
 
-    // takes in a Chip8Sprite instance and draws it to the screen at the appropriate place, returns true if any pixels were flipped from white to black
-    pub fn draw_sprite_on_display(&mut self, x: usize, y: usize, sprite: Chip8Sprite) -> bool {
-        let mut flipped_from_white = false;
+    // takes in a Chip8Sprite instance and draws it to a plane at the appropriate place, returns the number of rows where pixels were flipped from white to black.
+    // the starting position always wraps around the screen, the rest of the sprite is either clipped at the edges or wrapped around
+    pub fn draw_sprite_on_plane(&mut self, plane: usize, x: usize, y: usize, sprite: Chip8Sprite, clip: bool) -> usize {
+        let (width, height) = (self.get_width(), self.get_height());
+        let (x, y) = (x % width, y % height);
+        let mut collided_rows = 0;
 
         for (i, pixel_row) in sprite.pixels.iter().enumerate() {
+            let mut flipped_from_white = false;
+
             for (j, &pixel) in pixel_row.iter().enumerate() {
+                if clip && (x + j >= width || y + i >= height) {
+                    continue;
+                }
+
                 if pixel == Chip8Pixel::White {
-                    flipped_from_white |= self.toggle_pixel(x + j, y + i);
+                    flipped_from_white |= self.toggle_pixel(plane, x + j, y + i);
                 }
             }
+
+            if flipped_from_white {
+                collided_rows += 1;
+            }
         }
 
-        flipped_from_white
+        collided_rows
     }
 
-    // returns the display immutably
-    pub fn get_display(&self) -> &[[Chip8Pixel; 64]; 32] {
-        &self.display
+    // scrolls the selected planes down by a number of pixels, the rows scrolled in are black
+    pub fn scroll_down(&mut self, rows: usize) {
+        let height = self.get_height();
+
+        for plane in self.selected_plane_indices() {
+            let display = &mut self.planes[plane];
+
+            for y in (0..height).rev() {
+                display[y] = if y >= rows { display[y - rows] } else { [Chip8Pixel::Black; MAX_DISPLAY_WIDTH] };
+            }
+        }
     }
 
-    // turns a display of Chip8Pixel enums into an array of integers (0 = Black, 1 = White)
+    // scrolls the selected planes up by a number of pixels, the rows scrolled in are black
+    pub fn scroll_up(&mut self, rows: usize) {
+        let height = self.get_height();
+
+        for plane in self.selected_plane_indices() {
+            let display = &mut self.planes[plane];
+
+            for y in 0..height {
+                display[y] = if y + rows < height { display[y + rows] } else { [Chip8Pixel::Black; MAX_DISPLAY_WIDTH] };
+            }
+        }
+    }
+
+    // scrolls the selected planes right by a number of pixels, the columns scrolled in are black
+    pub fn scroll_right(&mut self, columns: usize) {
+        let (width, height) = (self.get_width(), self.get_height());
+
+        for plane in self.selected_plane_indices() {
+            let display = &mut self.planes[plane];
+
+            for row in display.iter_mut().take(height) {
+                for x in (0..width).rev() {
+                    row[x] = if x >= columns { row[x - columns] } else { Chip8Pixel::Black };
+                }
+            }
+        }
+    }
+
+    // scrolls the selected planes left by a number of pixels, the columns scrolled in are black
+    pub fn scroll_left(&mut self, columns: usize) {
+        let (width, height) = (self.get_width(), self.get_height());
+
+        for plane in self.selected_plane_indices() {
+            let display = &mut self.planes[plane];
+
+            for row in display.iter_mut().take(height) {
+                for x in 0..width {
+                    row[x] = if x + columns < width { row[x + columns] } else { Chip8Pixel::Black };
+                }
+            }
+        }
+    }
+
+    // gets the color of a pixel, made up of one bit per plane (so 0 = Black and 1 = White when only the first plane is used)
+    pub fn get_pixel_color(&self, x: usize, y: usize) -> u8 {
+        self.planes
+            .iter()
+            .enumerate()
+            .filter(|(_, plane)| plane[y][x] == Chip8Pixel::White)
+            .fold(0, |color, (i, _)| color | (1 << i))
+    }
+
+    // turns the planes of Chip8Pixel enums into an array of pixel colors (see get_pixel_color), in the current resolution
     pub fn get_display_as_ints(&self) -> Vec<Vec<u8>> {
-        self.display.iter()
-            .map(|row| {
-                row.iter()
-                    .map(|&pixel| match pixel {
-                        Chip8Pixel::Black => 0,
-                        Chip8Pixel::White => 1,
-                    })         
-                    .collect::<Vec<_>>()       
+        (0..self.get_height())
+            .map(|y| {
+                (0..self.get_width())
+                    .map(|x| self.get_pixel_color(x, y))
+                    .collect::<Vec<_>>()
             }).collect::<Vec<_>>()
     }
 
-    // turns a display of Chip8Pixel into a string representing the display
+    // gets a hash of the display in the current resolution (64-bit FNV-1a over the size and pixel colors), which stays the
+    // same across versions and platforms so it can be compared against stored values
+    pub fn get_display_hash(&self) -> u64 {
+        let size = [self.get_width() as u8, self.get_height() as u8];
+
+        size.into_iter()
+            .chain(self.get_display_as_ints().into_iter().flatten())
+            .fold(0xCBF2_9CE4_8422_2325, |hash, byte| (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01B3))
+    }
+
+    // gets the two characters a pixel of a color is shown as in text
+    pub fn pixel_as_str(color: u8) -> &'static str {
+        match color {
+            0 => "▒▒",
+            1 => "▓▓",
+            2 => "░░",
+            _ => "██",
+        }
+    }
+
+    // turns the display into a string representing the display
     pub fn get_display_as_str(&self) -> String {
         let mut s = String::new();
-        
-        for pixel_row in &self.display {
-            for &pixel in pixel_row {
-                let char = match pixel {
-                    Chip8Pixel::White => "▓▓",
-                    Chip8Pixel::Black => "▒▒",
-                };
-
-                s.push_str(char);
+
+        for pixel_row in self.get_display_as_ints() {
+            for color in pixel_row {
+                s.push_str(Chip8Output::pixel_as_str(color));
             }
 
             s.push('\n');
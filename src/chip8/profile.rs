@@ -0,0 +1,189 @@
+use std::collections::{BTreeMap, HashMap};
+use super::Chip8;
+use super::cpu::{CPUInstruction, InstructionClass};
+use super::disasm::PROGRAM_START;
+
+// how many hot spots the report lists
+const REPORT_HOT_SPOTS: usize = 20;
+
+// instruction counts of a subroutine
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct SubroutineCounts {
+    pub inclusive: u64, // executed in the subroutine or anything it called
+    pub exclusive: u64, // executed in the subroutine itself
+}
+
+// counts where instructions are executed. call record before every instruction
+#[derive(Debug, Clone)]
+pub struct Chip8Profiler {
+    address_counts: BTreeMap<u16, u64>,
+    subroutines: BTreeMap<u16, SubroutineCounts>,
+    stacks: HashMap<Vec<u16>, u64>, // instruction counts by call path (entry addresses, outermost first)
+    classes: BTreeMap<InstructionClass, u64>,
+    draws_per_frame: Vec<u32>, // indexed by frame count, up to the last frame an instruction was recorded in
+    call_path: Vec<u16>, // the entry addresses of the subroutines currently being executed, outermost first (the first
+                         // recorded PC stands for the program itself)
+    labels: BTreeMap<usize, String>,
+    instructions: u64,
+}
+
+impl Chip8Profiler {
+    pub fn new() -> Chip8Profiler {
+        Chip8Profiler {
+            address_counts: BTreeMap::new(),
+            subroutines: BTreeMap::new(),
+            stacks: HashMap::new(),
+            classes: BTreeMap::new(),
+            draws_per_frame: Vec::new(),
+            call_path: Vec::new(),
+            labels: BTreeMap::new(),
+            instructions: 0,
+        }
+    }
+
+    // names subroutines in reports, e.g. with Disassembly::labels (unnamed ones are called sub_XXX)
+    pub fn set_labels(&mut self, labels: BTreeMap<usize, String>) {
+        self.labels = labels;
+    }
+
+    // gets the name of a subroutine
+    pub fn get_name(&self, addr: u16) -> String {
+        match self.labels.get(&(addr as usize)) {
+            Some(label) => label.clone(),
+            None if addr as usize == PROGRAM_START => String::from("main"),
+            None => format!("sub_{addr:03X}"),
+        }
+    }
+
+    // counts the instruction that is about to be executed. subroutines are tracked through the depth of the call
+    // stack: when it grows the PC is the start of a subroutine, when it shrinks subroutines returned
+    pub fn record(&mut self, c8: &Chip8) {
+        let pc = *c8.registers.get_pc_register();
+        let depth = c8.stack.get_depth() + 1;
+
+        self.call_path.truncate(depth);
+        while self.call_path.len() < depth {
+            self.call_path.push(pc);
+        }
+
+        self.instructions += 1;
+        *self.address_counts.entry(pc).or_default() += 1;
+        *self.stacks.entry(self.call_path.clone()).or_default() += 1;
+
+        // recursive subroutines only count once towards their inclusive count
+        for (i, &entry) in self.call_path.iter().enumerate() {
+            if !self.call_path[..i].contains(&entry) {
+                self.subroutines.entry(entry).or_default().inclusive += 1;
+            }
+        }
+        if let Some(&current) = self.call_path.last() {
+            self.subroutines.entry(current).or_default().exclusive += 1;
+        }
+
+        let instruction = c8.memory.get_memory_at_u16(pc as usize)
+            .map(Chip8::opcode_to_instruction)
+            .unwrap_or(CPUInstruction::Unknown { opcode: 0 });
+        *self.classes.entry(instruction.class()).or_default() += 1;
+
+        let frame = c8.get_frame_count() as usize;
+        if self.draws_per_frame.len() <= frame {
+            self.draws_per_frame.resize(frame + 1, 0);
+        }
+        if let CPUInstruction::Draw { .. } = instruction {
+            self.draws_per_frame[frame] += 1;
+        }
+    }
+
+    // gets the number of instructions counted
+    pub fn get_instruction_count(&self) -> u64 {
+        self.instructions
+    }
+
+    // gets how often the instruction at each address was executed
+    pub fn get_address_counts(&self) -> &BTreeMap<u16, u64> {
+        &self.address_counts
+    }
+
+    // gets the counts of every subroutine by entry address (including the program itself, see call_path)
+    pub fn get_subroutines(&self) -> &BTreeMap<u16, SubroutineCounts> {
+        &self.subroutines
+    }
+
+    // gets how many instructions of each class were executed
+    pub fn get_class_counts(&self) -> &BTreeMap<InstructionClass, u64> {
+        &self.classes
+    }
+
+    // gets the number of draw instructions in each frame
+    pub fn get_draws_per_frame(&self) -> &[u32] {
+        &self.draws_per_frame
+    }
+
+    // percentage of all instructions
+    fn percent(&self, count: u64) -> f64 {
+        100.0 * count as f64 / self.instructions.max(1) as f64
+    }
+
+    // writes a text report of the hot spots, subroutines, instruction classes and draws per frame
+    pub fn report(&self) -> String {
+        let mut out = format!("{} instructions over {} frames\n", self.instructions, self.draws_per_frame.len());
+
+        out.push_str("\nhot spots:\n");
+        let mut hot_spots = self.address_counts.iter().collect::<Vec<_>>();
+        hot_spots.sort_by_key(|&(addr, count)| (std::cmp::Reverse(*count), *addr));
+        for (addr, count) in hot_spots.into_iter().take(REPORT_HOT_SPOTS) {
+            out.push_str(&format!("  0x{addr:03X} {count:>12} {:>6.2}%\n", self.percent(*count)));
+        }
+
+        out.push_str("\nsubroutines (inclusive, exclusive):\n");
+        let mut subroutines = self.subroutines.iter().collect::<Vec<_>>();
+        subroutines.sort_by_key(|&(addr, counts)| (std::cmp::Reverse(counts.inclusive), *addr));
+        for (addr, counts) in subroutines {
+            out.push_str(&format!(
+                "  {:<20} {:>12} {:>6.2}% {:>12} {:>6.2}%\n",
+                self.get_name(*addr),
+                counts.inclusive,
+                self.percent(counts.inclusive),
+                counts.exclusive,
+                self.percent(counts.exclusive),
+            ));
+        }
+
+        out.push_str("\ninstruction classes:\n");
+        for (class, count) in &self.classes {
+            out.push_str(&format!("  {:<8} {count:>12} {:>6.2}%\n", class.name(), self.percent(*count)));
+        }
+
+        let draws = self.draws_per_frame.iter().map(|&draws| draws as u64).sum::<u64>();
+        let busiest = self.draws_per_frame.iter().enumerate().max_by_key(|&(frame, draws)| (*draws, std::cmp::Reverse(frame)));
+        out.push_str(&format!(
+            "\ndraws per frame: {:.2} on average, {} at most (frame {})\n",
+            draws as f64 / self.draws_per_frame.len().max(1) as f64,
+            busiest.map_or(0, |(_, draws)| *draws),
+            busiest.map_or(0, |(frame, _)| frame),
+        ));
+
+        out
+    }
+
+    // writes the instruction counts in the collapsed stack format flame graph tools read: one `main;sub_2A4;sub_300 COUNT`
+    // line per call path
+    pub fn collapsed_stacks(&self) -> String {
+        let mut lines = self.stacks
+            .iter()
+            .map(|(path, count)| {
+                let names = path.iter().map(|&addr| self.get_name(addr)).collect::<Vec<_>>();
+                format!("{} {count}\n", names.join(";"))
+            })
+            .collect::<Vec<_>>();
+
+        lines.sort();
+        lines.concat()
+    }
+}
+
+impl Default for Chip8Profiler {
+    fn default() -> Self {
+        Chip8Profiler::new()
+    }
+}
@@ -0,0 +1,94 @@
+// the behavior to use for each of the ambiguous CHIP-8 instructions
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Quirks {
+    pub shift_uses_vy: bool, // 8XY6/8XYE shift Vy into Vx instead of shifting Vx in place
+    pub load_store_increments_i: bool, // FX55/FX65 leave I pointing past the last register
+    pub vf_reset: bool, // 8XY1/8XY2/8XY3 reset VF to 0
+    pub jump_with_vx: bool, // BXNN jumps to XNN + Vx instead of NNN + V0
+    pub clip_sprites: bool, // sprites are cut off at the edges of the screen instead of wrapping around
+    pub display_wait: bool, // drawing waits for the vertical blank, so at most one sprite is drawn per frame
+    pub extended_memory: bool, // the full 64 KiB XO-CHIP address space is usable instead of only the first 4 KiB
+    pub stack_depth: usize, // the maximum number of nested subroutine calls
+    pub memory_mapped_stack: bool, // return addresses are kept in memory like on the COSMAC VIP, for programs that rely on it
+}
+
+impl Quirks {
+    // the original CHIP-8 interpreter on the COSMAC VIP
+    pub fn cosmac_vip() -> Quirks {
+        Quirks {
+            shift_uses_vy: true,
+            load_store_increments_i: true,
+            vf_reset: true,
+            jump_with_vx: false,
+            clip_sprites: true,
+            display_wait: true,
+            extended_memory: false,
+            stack_depth: 16,
+            memory_mapped_stack: false,
+        }
+    }
+
+    // SUPER-CHIP 1.1 on the HP48 calculators
+    pub fn super_chip() -> Quirks {
+        Quirks {
+            shift_uses_vy: false,
+            load_store_increments_i: false,
+            vf_reset: false,
+            jump_with_vx: true,
+            clip_sprites: true,
+            display_wait: false,
+            extended_memory: false,
+            stack_depth: 16,
+            memory_mapped_stack: false,
+        }
+    }
+
+    // modern interpreters such as Octo (and XO-CHIP)
+    pub fn modern() -> Quirks {
+        Quirks {
+            shift_uses_vy: true,
+            load_store_increments_i: true,
+            vf_reset: false,
+            jump_with_vx: false,
+            clip_sprites: false,
+            display_wait: false,
+            extended_memory: true,
+            stack_depth: 128,
+            memory_mapped_stack: false,
+        }
+    }
+
+    // gets a preset by name
+    pub fn from_preset_name(name: &str) -> Option<Quirks> {
+        match name {
+            "vip" | "cosmac-vip" | "chip8" => Some(Quirks::cosmac_vip()),
+            "schip" | "super-chip" => Some(Quirks::super_chip()),
+            "modern" | "octo" | "xo-chip" => Some(Quirks::modern()),
+            _ => None,
+        }
+    }
+
+    // sets a single quirk by name, returns false if the name is not a known quirk
+    pub fn set_by_name(&mut self, name: &str, enabled: bool) -> bool {
+        let quirk = match name {
+            "shift_uses_vy" => &mut self.shift_uses_vy,
+            "load_store_increments_i" => &mut self.load_store_increments_i,
+            "vf_reset" => &mut self.vf_reset,
+            "jump_with_vx" => &mut self.jump_with_vx,
+            "clip_sprites" => &mut self.clip_sprites,
+            "display_wait" => &mut self.display_wait,
+            "extended_memory" => &mut self.extended_memory,
+            "memory_mapped_stack" => &mut self.memory_mapped_stack,
+            _ => return false,
+        };
+
+        *quirk = enabled;
+        true
+    }
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Quirks::cosmac_vip()
+    }
+}
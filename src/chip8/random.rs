@@ -0,0 +1,62 @@
+// the random number generator used by CXNN (SplitMix64). it is owned by the machine and fully determined by its seed,
+// so two runs with the same seed and the same inputs produce the same numbers
+#[derive(Debug, Clone)]
+pub struct Chip8Random {
+    seed: u64,
+    state: u64,
+}
+
+impl Chip8Random {
+    // creates a generator with a given seed
+    pub fn new(seed: u64) -> Chip8Random {
+        Chip8Random { seed, state: seed }
+    }
+
+    // creates a generator with a seed taken from the system's random number generator
+    pub fn from_entropy() -> Chip8Random {
+        Chip8Random::new(rand::random())
+    }
+
+    // gets the seed the generator was created with (or last reseeded with)
+    pub fn get_seed(&self) -> u64 {
+        self.seed
+    }
+
+    // restarts the generator from a seed
+    pub fn reseed(&mut self, seed: u64) {
+        self.seed = seed;
+        self.state = seed;
+    }
+
+    // gets the part of the state that changes as numbers are generated (for save states)
+    pub fn get_state(&self) -> u64 {
+        self.state
+    }
+
+    // restores the generator from a seed and a state returned by get_state
+    pub fn set_state(&mut self, seed: u64, state: u64) {
+        self.seed = seed;
+        self.state = state;
+    }
+
+    // generates a random byte
+    pub fn next_byte(&mut self) -> u8 {
+        (splitmix64(&mut self.state) >> 56) as u8
+    }
+}
+
+impl Default for Chip8Random {
+    fn default() -> Self {
+        Chip8Random::from_entropy()
+    }
+}
+
+// advances a SplitMix64 state and returns the next number
+fn splitmix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
@@ -1,3 +1,5 @@
+use super::error::Chip8ErrorReason;
+
 #[derive(Debug)]
 pub struct Chip8Registers {
     v: [u8; 16],
@@ -12,17 +14,13 @@ impl Chip8Registers {
     }
 
     // for getting a V register immutably
-    pub fn get_v_register(&self, reg: usize) -> &u8 {
-        assert!(reg < 16);
-
-        &self.v[reg]
+    pub fn get_v_register(&self, reg: usize) -> Result<&u8, Chip8ErrorReason> {
+        self.v.get(reg).ok_or(Chip8ErrorReason::InvalidRegister { reg })
     }
 
     // for getting a V register mutably
-    pub fn get_v_register_mut(&mut self, reg: usize) -> &mut u8 {
-        assert!(reg < 16);
-
-        &mut self.v[reg]
+    pub fn get_v_register_mut(&mut self, reg: usize) -> Result<&mut u8, Chip8ErrorReason> {
+        self.v.get_mut(reg).ok_or(Chip8ErrorReason::InvalidRegister { reg })
     }
 
     // for getting the I regsiter immmutably
@@ -50,8 +48,13 @@ impl Chip8Registers {
         *self.get_pc_register_mut() = addr;
     }
 
-    // skips the next instruction by increasing pc by 2
+    // skips the next instruction by increasing pc by 2, wrapping around at the end of the 64K address space
     pub fn skip_next_instr(&mut self) {
-        *self.get_pc_register_mut() += 2;
+        self.pc = self.pc.wrapping_add(2);
+    }
+
+    // steps pc back by 2 so the current instruction runs again, wrapping around like skip_next_instr
+    pub fn repeat_instr(&mut self) {
+        self.pc = self.pc.wrapping_sub(2);
     }
 }
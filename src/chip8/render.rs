@@ -0,0 +1,218 @@
+use super::output::Chip8Output;
+
+// how the display is turned into text
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RenderMode {
+    Blocks, // two shaded characters per pixel (see Chip8Output::pixel_as_str), 128 columns for a lo-res display
+    HalfBlock, // ▀▄█, 1x2 pixels per character
+    Quadrant, // ▘▝▖▗ and friends, 2x2 pixels per character
+    Braille, // ⣿ and friends, 2x4 pixels per character
+}
+
+impl RenderMode {
+    // gets a render mode by its name ("blocks", "half", "quadrant" or "braille")
+    pub fn from_name(name: &str) -> Option<RenderMode> {
+        match name {
+            "blocks" | "full" => Some(RenderMode::Blocks),
+            "half" | "half-block" => Some(RenderMode::HalfBlock),
+            "quadrant" | "quad" => Some(RenderMode::Quadrant),
+            "braille" => Some(RenderMode::Braille),
+            _ => None,
+        }
+    }
+
+    // gets the number of pixels each character covers, as (width, height)
+    pub fn pixels_per_cell(&self) -> (usize, usize) {
+        match self {
+            RenderMode::Blocks => (1, 1),
+            RenderMode::HalfBlock => (1, 2),
+            RenderMode::Quadrant => (2, 2),
+            RenderMode::Braille => (2, 4),
+        }
+    }
+
+    // gets the number of terminal columns each cell takes up
+    pub fn columns_per_cell(&self) -> usize {
+        match self {
+            RenderMode::Blocks => 2,
+            _ => 1,
+        }
+    }
+}
+
+// the quadrant characters, indexed by the lit pixels (bit 0 = top left, 1 = top right, 2 = bottom left, 3 = bottom right)
+const QUADRANTS: [char; 16] = [' ', '▘', '▝', '▀', '▖', '▌', '▞', '▛', '▗', '▚', '▐', '▜', '▄', '▙', '▟', '█'];
+
+// the bit of the Braille dot for each pixel of a cell, by row and then column
+const BRAILLE_DOTS: [[u32; 2]; 4] = [[0x01, 0x08], [0x02, 0x10], [0x04, 0x20], [0x40, 0x80]];
+
+// a 24-bit color
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rgb(pub u8, pub u8, pub u8);
+
+impl Rgb {
+    // parses a color written as RRGGBB, with or without a leading #
+    pub fn from_hex(hex: &str) -> Option<Rgb> {
+        let hex = hex.strip_prefix('#').unwrap_or(hex);
+        if hex.len() != 6 {
+            return None;
+        }
+
+        let value = u32::from_str_radix(hex, 16).ok()?;
+        Some(Rgb((value >> 16) as u8, (value >> 8) as u8, value as u8))
+    }
+
+    // mixes in a fraction (0 to 1) of another color
+    fn mix(self, other: Rgb, amount: f32) -> Rgb {
+        let channel = |from: u8, to: u8| (from as f32 + (to as f32 - from as f32) * amount).round() as u8;
+        Rgb(channel(self.0, other.0), channel(self.1, other.1), channel(self.2, other.2))
+    }
+}
+
+// the colors of the pixels when rendering with 24-bit ANSI colors
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Palette {
+    pub colors: [Rgb; 4], // by pixel color, see Chip8Output::get_pixel_color
+}
+
+impl Palette {
+    // makes a palette from the background and foreground colors, with the XO-CHIP colors in between (like the web page,
+    // the second plane is the lighter shade)
+    pub fn new(foreground: Rgb, background: Rgb) -> Palette {
+        Palette { colors: [background, foreground, background.mix(foreground, 2.0 / 3.0), background.mix(foreground, 1.0 / 3.0)] }
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Palette {
+        Palette::new(Rgb(0xFF, 0xFF, 0xFF), Rgb(0, 0, 0))
+    }
+}
+
+// turns the display into text in one of the render modes, optionally with 24-bit ANSI colors
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Renderer {
+    pub mode: RenderMode,
+    pub palette: Option<Palette>, // None leaves the colors to the terminal
+}
+
+impl Renderer {
+    pub fn new(mode: RenderMode) -> Renderer {
+        Renderer { mode, palette: None }
+    }
+
+    // gets the number of text rows a display of a height takes up
+    pub fn rows(&self, height: usize) -> usize {
+        height.div_ceil(self.mode.pixels_per_cell().1)
+    }
+
+    // renders one cell, given the colors of its pixels by row (pixels outside the display are 0)
+    fn render_cell(&self, pixels: &[[u8; 2]; 4]) -> String {
+        let lit = |x: usize, y: usize| pixels[y][x] != 0;
+
+        let glyph = match self.mode {
+            RenderMode::Blocks => match self.palette {
+                Some(_) => String::from("██"),
+                None => String::from(Chip8Output::pixel_as_str(pixels[0][0])),
+            },
+
+            // with colors the top pixel is the foreground and the bottom one the background, which shows both exactly
+            RenderMode::HalfBlock => String::from(match (lit(0, 0), lit(0, 1)) {
+                _ if self.palette.is_some() => '▀',
+                (true, true) => '█',
+                (true, false) => '▀',
+                (false, true) => '▄',
+                (false, false) => ' ',
+            }),
+
+            RenderMode::Quadrant => {
+                let bits = (0..4).filter(|i| lit(i % 2, i / 2)).fold(0, |bits, i| bits | (1 << i));
+                String::from(QUADRANTS[bits])
+            },
+
+            RenderMode::Braille => {
+                let bits = (0..8).filter(|i| lit(i % 2, i / 2)).fold(0, |bits, i| bits | BRAILLE_DOTS[i / 2][i % 2]);
+                String::from(char::from_u32(0x2800 + bits).unwrap_or(' '))
+            },
+        };
+
+        let Some(palette) = self.palette else {
+            return glyph;
+        };
+
+        let (foreground, background) = match self.mode {
+            RenderMode::Blocks => (pixels[0][0], 0),
+            RenderMode::HalfBlock => (pixels[0][0], pixels[1][0]),
+
+            // a cell only has one foreground color, so mixed XO-CHIP colors go with the most common one
+            _ => {
+                let (width, height) = self.mode.pixels_per_cell();
+                let mut counts = [0; 4];
+                for row in &pixels[..height] {
+                    for &color in &row[..width] {
+                        counts[color as usize & 3] += 1;
+                    }
+                }
+
+                let foreground = (1..4).rev().max_by_key(|&color| counts[color]).filter(|&color| counts[color] > 0).unwrap_or(0);
+                (foreground as u8, 0)
+            },
+        };
+
+        let Rgb(fr, fg, fb) = palette.colors[foreground as usize & 3];
+        let Rgb(br, bg, bb) = palette.colors[background as usize & 3];
+        format!("\x1B[38;2;{fr};{fg};{fb};48;2;{br};{bg};{bb}m{glyph}")
+    }
+
+    // renders a display (see Chip8Output::get_display_as_ints) as rows of cells, each cell being the text of one
+    // character position (including its color escape sequence)
+    pub fn render_cells(&self, display: &[Vec<u8>]) -> Vec<Vec<String>> {
+        let (cell_width, cell_height) = self.mode.pixels_per_cell();
+        let width = display.first().map_or(0, Vec::len);
+        let pixel = |x: usize, y: usize| display.get(y).and_then(|row| row.get(x)).copied().unwrap_or(0);
+
+        (0..self.rows(display.len()))
+            .map(|row| {
+                (0..width.div_ceil(cell_width))
+                    .map(|column| {
+                        let mut pixels = [[0; 2]; 4];
+                        for (dy, pixel_row) in pixels.iter_mut().enumerate().take(cell_height) {
+                            for (dx, color) in pixel_row.iter_mut().enumerate().take(cell_width) {
+                                *color = pixel(column * cell_width + dx, row * cell_height + dy);
+                            }
+                        }
+
+                        self.render_cell(&pixels)
+                    }).collect()
+            }).collect()
+    }
+
+    // renders a display as text, one line per row of cells
+    pub fn render(&self, display: &[Vec<u8>]) -> String {
+        let mut s = String::new();
+
+        for row in self.render_cells(display) {
+            s.extend(row);
+
+            if self.palette.is_some() {
+                s.push_str("\x1B[0m");
+            }
+            s.push('\n');
+        }
+
+        s
+    }
+}
+
+impl Default for Renderer {
+    fn default() -> Renderer {
+        Renderer::new(RenderMode::Blocks)
+    }
+}
+
+impl Chip8Output {
+    // renders the display as text, see Renderer
+    pub fn render(&self, renderer: &Renderer) -> String {
+        renderer.render(&self.get_display_as_ints())
+    }
+}
@@ -0,0 +1,169 @@
+use std::collections::VecDeque;
+use super::Chip8;
+
+// records snapshots of the machine every few frames so it can be stepped backwards. only the newest snapshot is kept
+// whole, every older one is stored as a compressed delta that turns the snapshot after it back into itself
+#[derive(Debug)]
+pub struct Chip8Rewind {
+    deltas: VecDeque<Vec<u8>>, // oldest first
+    latest: Option<Vec<u8>>,
+    capacity: usize, // the most snapshots that are kept
+    interval: usize, // the number of frames between snapshots
+    frames_since_snapshot: usize,
+}
+
+impl Chip8Rewind {
+    // creates a rewind buffer that goes back a given number of seconds (at 60 frames per second), taking a snapshot every interval frames
+    pub fn new(seconds: f64, interval: usize) -> Chip8Rewind {
+        let interval = interval.max(1);
+        let capacity = ((seconds * 60.0) as usize / interval).max(1);
+
+        Chip8Rewind {
+            deltas: VecDeque::with_capacity(capacity),
+            latest: None,
+            capacity,
+            interval,
+            frames_since_snapshot: 0,
+        }
+    }
+
+    // gets the number of snapshots that can currently be rewound to
+    pub fn get_snapshot_count(&self) -> usize {
+        self.deltas.len() + self.latest.is_some() as usize
+    }
+
+    // gets the number of bytes used by the snapshots
+    pub fn get_memory_usage(&self) -> usize {
+        self.deltas.iter().map(Vec::len).sum::<usize>() + self.latest.as_ref().map_or(0, Vec::len)
+    }
+
+    // throws away all snapshots
+    pub fn clear(&mut self) {
+        self.deltas.clear();
+        self.latest = None;
+        self.frames_since_snapshot = 0;
+    }
+
+    // should be called once per frame, takes a snapshot every interval frames
+    pub fn record_frame(&mut self, c8: &Chip8) {
+        if self.frames_since_snapshot.is_multiple_of(self.interval) {
+            self.push_snapshot(c8.save_state());
+        }
+
+        self.frames_since_snapshot += 1;
+    }
+
+    // adds a snapshot, dropping the oldest one if the buffer is full
+    fn push_snapshot(&mut self, snapshot: Vec<u8>) {
+        if let Some(previous) = self.latest.take() {
+            self.deltas.push_back(compress_delta(&snapshot, &previous));
+
+            if self.deltas.len() >= self.capacity {
+                self.deltas.pop_front();
+            }
+        }
+
+        self.latest = Some(snapshot);
+    }
+
+    // restores the newest snapshot and drops it, so the next call goes further back. the oldest snapshot is never dropped,
+    // so holding rewind stops there. returns false if there was nothing to rewind to
+    pub fn rewind_step(&mut self, c8: &mut Chip8) -> bool {
+        let Some(latest) = self.latest.take() else {
+            return false;
+        };
+
+        // snapshots are only ever produced by save_state, so they always load
+        let loaded = c8.load_state(&latest).is_ok();
+
+        self.latest = match self.deltas.pop_back() {
+            Some(delta) => Some(apply_delta(&latest, &delta)),
+            None => Some(latest),
+        };
+
+        // start counting towards the next snapshot from the restored frame
+        self.frames_since_snapshot = 1;
+
+        loaded
+    }
+}
+
+// appends a number in LEB128 form
+fn write_varint(out: &mut Vec<u8>, mut val: usize) {
+    while val >= 0x80 {
+        out.push((val as u8 & 0x7F) | 0x80);
+        val >>= 7;
+    }
+
+    out.push(val as u8);
+}
+
+// reads a number in LEB128 form
+fn read_varint(data: &[u8], pos: &mut usize) -> usize {
+    let mut val = 0;
+    let mut shift = 0;
+
+    while let Some(&byte) = data.get(*pos) {
+        *pos += 1;
+        val |= ((byte & 0x7F) as usize) << shift;
+        shift += 7;
+
+        if byte & 0x80 == 0 {
+            break;
+        }
+    }
+
+    val
+}
+
+// makes a delta that turns `from` into `to`: the length of `to`, followed by the XOR of the two (the shorter one padded with zeros)
+// as alternating runs of unchanged bytes (just a count) and changed bytes (a count and the XORed bytes)
+fn compress_delta(from: &[u8], to: &[u8]) -> Vec<u8> {
+    let len = from.len().max(to.len());
+    let xor = (0..len)
+        .map(|i| from.get(i).unwrap_or(&0) ^ to.get(i).unwrap_or(&0))
+        .collect::<Vec<_>>();
+
+    let mut out = Vec::new();
+    write_varint(&mut out, to.len());
+
+    let mut i = 0;
+    while i < len {
+        let unchanged = xor[i..].iter().take_while(|&&byte| byte == 0).count();
+        i += unchanged;
+
+        let changed = xor[i..].iter().take_while(|&&byte| byte != 0).count();
+
+        write_varint(&mut out, unchanged);
+        write_varint(&mut out, changed);
+        out.extend_from_slice(&xor[i..i + changed]);
+        i += changed;
+    }
+
+    out
+}
+
+// applies a delta made by compress_delta
+fn apply_delta(from: &[u8], delta: &[u8]) -> Vec<u8> {
+    let mut pos = 0;
+    let to_len = read_varint(delta, &mut pos);
+
+    let mut out = from.to_vec();
+    out.resize(out.len().max(to_len), 0);
+
+    let mut i = 0;
+    while pos < delta.len() {
+        i += read_varint(delta, &mut pos);
+        let changed = read_varint(delta, &mut pos);
+
+        for &byte in &delta[pos..pos + changed] {
+            out[i] ^= byte;
+            i += 1;
+        }
+
+        pos += changed;
+    }
+
+    out.truncate(to_len);
+    out
+}
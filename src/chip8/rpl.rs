@@ -0,0 +1,73 @@
+use std::fmt::Debug;
+use std::fs;
+use std::path::PathBuf;
+
+// somewhere to keep the SUPER-CHIP RPL user flags (FX75/FX85), so they can outlive the emulator
+pub trait RplStorage: Debug + Send {
+    // loads the flags from storage
+    fn load(&mut self) -> [u8; 16];
+
+    // saves the flags to storage
+    fn save(&mut self, flags: &[u8; 16]);
+}
+
+// keeps the flags in memory only, so they are lost when the emulator is dropped
+#[derive(Debug)]
+pub struct MemoryRplStorage {
+    flags: [u8; 16],
+}
+
+impl MemoryRplStorage {
+    pub fn new() -> MemoryRplStorage {
+        MemoryRplStorage { flags: [0; 16] }
+    }
+}
+
+impl Default for MemoryRplStorage {
+    fn default() -> Self {
+        MemoryRplStorage::new()
+    }
+}
+
+impl RplStorage for MemoryRplStorage {
+    fn load(&mut self) -> [u8; 16] {
+        self.flags
+    }
+
+    fn save(&mut self, flags: &[u8; 16]) {
+        self.flags = *flags;
+    }
+}
+
+// keeps the flags in a 16-byte file, missing or short files read as zeros
+#[derive(Debug)]
+pub struct FileRplStorage {
+    path: PathBuf,
+}
+
+impl FileRplStorage {
+    pub fn new(path: impl Into<PathBuf>) -> FileRplStorage {
+        FileRplStorage { path: path.into() }
+    }
+}
+
+impl RplStorage for FileRplStorage {
+    fn load(&mut self) -> [u8; 16] {
+        let mut flags = [0; 16];
+
+        if let Ok(contents) = fs::read(&self.path) {
+            flags
+                .iter_mut()
+                .zip(contents)
+                .for_each(|(flag, byte)| *flag = byte);
+        }
+
+        flags
+    }
+
+    fn save(&mut self, flags: &[u8; 16]) {
+        if let Err(e) = fs::write(&self.path, flags) {
+            log::warn!("could not save RPL flags to {}: {e}", self.path.display());
+        }
+    }
+}
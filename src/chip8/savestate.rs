@@ -0,0 +1,433 @@
+use std::fmt;
+use super::Chip8;
+use super::output::{Chip8Pixel, MAX_DISPLAY_HEIGHT, MAX_DISPLAY_WIDTH};
+use super::quirks::Quirks;
+use super::timing::TimingModel;
+
+// save state layout: magic, version (u16), payload length (u32), payload, CRC-32 of the payload. all numbers are little-endian
+const MAGIC: &[u8; 4] = b"C8SS";
+pub const SAVE_STATE_VERSION: u16 = 4;
+
+// the reasons a save state (or anything else stored the same way, like movies) can fail to load
+#[derive(Debug, Clone, PartialEq)]
+pub enum SaveStateError {
+    BadMagic,
+    UnsupportedVersion(u16),
+    ChecksumMismatch,
+    Truncated,
+    Invalid(&'static str),
+}
+
+impl fmt::Display for SaveStateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SaveStateError::BadMagic => write!(f, "not a CHIP-8 save state or movie (or the wrong one of the two)"),
+            SaveStateError::UnsupportedVersion(version) => write!(f, "file format version {version} is not supported"),
+            SaveStateError::ChecksumMismatch => write!(f, "file is corrupted (checksum mismatch)"),
+            SaveStateError::Truncated => write!(f, "file is truncated"),
+            SaveStateError::Invalid(what) => write!(f, "file is invalid: {what}"),
+        }
+    }
+}
+
+impl std::error::Error for SaveStateError {}
+
+// computes the CRC-32 (IEEE) of some data
+pub(crate) fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+
+    for &byte in data {
+        crc ^= byte as u32;
+
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+        }
+    }
+
+    !crc
+}
+
+// appends little-endian values to a byte buffer
+pub(crate) struct StateWriter {
+    buf: Vec<u8>,
+}
+
+impl StateWriter {
+    pub fn new() -> StateWriter {
+        StateWriter { buf: Vec::new() }
+    }
+
+    pub fn write_u8(&mut self, val: u8) {
+        self.buf.push(val);
+    }
+
+    pub fn write_bool(&mut self, val: bool) {
+        self.write_u8(val as u8);
+    }
+
+    pub fn write_u16(&mut self, val: u16) {
+        self.buf.extend_from_slice(&val.to_le_bytes());
+    }
+
+    pub fn write_u32(&mut self, val: u32) {
+        self.buf.extend_from_slice(&val.to_le_bytes());
+    }
+
+    pub fn write_u64(&mut self, val: u64) {
+        self.buf.extend_from_slice(&val.to_le_bytes());
+    }
+
+    // writes a length-prefixed byte string
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        self.write_u32(bytes.len() as u32);
+        self.buf.extend_from_slice(bytes);
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.buf
+    }
+}
+
+// reads little-endian values from a byte buffer, failing with Truncated if it runs out
+pub(crate) struct StateReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> StateReader<'a> {
+    pub fn new(data: &'a [u8]) -> StateReader<'a> {
+        StateReader { data, pos: 0 }
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], SaveStateError> {
+        let bytes = self.data
+            .get(self.pos..self.pos + len)
+            .ok_or(SaveStateError::Truncated)?;
+
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8, SaveStateError> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn read_bool(&mut self) -> Result<bool, SaveStateError> {
+        Ok(self.read_u8()? != 0)
+    }
+
+    pub fn read_u16(&mut self) -> Result<u16, SaveStateError> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    pub fn read_u32(&mut self) -> Result<u32, SaveStateError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    pub fn read_u64(&mut self) -> Result<u64, SaveStateError> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    // reads a length-prefixed byte string
+    pub fn read_bytes(&mut self) -> Result<&'a [u8], SaveStateError> {
+        let len = self.read_u32()? as usize;
+        self.take(len)
+    }
+
+    // returns true if everything has been read
+    pub fn is_empty(&self) -> bool {
+        self.pos == self.data.len()
+    }
+}
+
+// wraps a payload with the magic, version, length and checksum
+pub(crate) fn wrap_payload(magic: &[u8; 4], version: u16, payload: &[u8]) -> Vec<u8> {
+    let mut writer = StateWriter::new();
+
+    magic.iter().for_each(|&byte| writer.write_u8(byte));
+    writer.write_u16(version);
+    writer.write_bytes(payload);
+    writer.write_u32(crc32(payload));
+
+    writer.into_bytes()
+}
+
+// checks the magic, length and checksum of wrapped data, returning its version and payload
+pub(crate) fn unwrap_payload<'a>(magic: &[u8; 4], newest_version: u16, data: &'a [u8]) -> Result<(u16, &'a [u8]), SaveStateError> {
+    if data.len() < 4 || &data[..4] != magic {
+        return Err(SaveStateError::BadMagic);
+    }
+
+    let mut reader = StateReader::new(&data[4..]);
+    let version = reader.read_u16()?;
+
+    if version == 0 || version > newest_version {
+        return Err(SaveStateError::UnsupportedVersion(version));
+    }
+
+    let payload = reader.read_bytes()?;
+    let checksum = reader.read_u32()?;
+
+    if checksum != crc32(payload) {
+        return Err(SaveStateError::ChecksumMismatch);
+    }
+
+    Ok((version, payload))
+}
+
+// writes every quirk
+pub(crate) fn write_quirks(writer: &mut StateWriter, quirks: &Quirks) {
+    writer.write_bool(quirks.shift_uses_vy);
+    writer.write_bool(quirks.load_store_increments_i);
+    writer.write_bool(quirks.vf_reset);
+    writer.write_bool(quirks.jump_with_vx);
+    writer.write_bool(quirks.clip_sprites);
+    writer.write_bool(quirks.display_wait);
+    writer.write_bool(quirks.extended_memory);
+    writer.write_u32(quirks.stack_depth as u32);
+    writer.write_bool(quirks.memory_mapped_stack);
+}
+
+// reads quirks written by write_quirks
+pub(crate) fn read_quirks(reader: &mut StateReader) -> Result<Quirks, SaveStateError> {
+    Ok(Quirks {
+        shift_uses_vy: reader.read_bool()?,
+        load_store_increments_i: reader.read_bool()?,
+        vf_reset: reader.read_bool()?,
+        jump_with_vx: reader.read_bool()?,
+        clip_sprites: reader.read_bool()?,
+        display_wait: reader.read_bool()?,
+        extended_memory: reader.read_bool()?,
+        stack_depth: reader.read_u32()? as usize,
+        memory_mapped_stack: reader.read_bool()?,
+    })
+}
+
+// writes a timing model and the instructions per frame it runs with
+pub(crate) fn write_timing(writer: &mut StateWriter, timing: TimingModel, instructions_per_frame: usize) {
+    writer.write_u8(match timing {
+        TimingModel::Instructions => 0,
+        TimingModel::CosmacVip => 1,
+    });
+    writer.write_u32(instructions_per_frame as u32);
+}
+
+// reads a timing model and instructions per frame written by write_timing
+pub(crate) fn read_timing(reader: &mut StateReader) -> Result<(TimingModel, usize), SaveStateError> {
+    let timing = match reader.read_u8()? {
+        0 => TimingModel::Instructions,
+        1 => TimingModel::CosmacVip,
+        _ => return Err(SaveStateError::Invalid("timing model")),
+    };
+
+    Ok((timing, reader.read_u32()? as usize))
+}
+
+impl super::Chip8 {
+    // serializes the whole machine state (everything but the fault policies and RPL flag storage, which belong to the host)
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut writer = StateWriter::new();
+
+        // memory
+        writer.write_bytes(self.memory.get_bytes());
+
+        // registers
+        for reg in 0..16 {
+            writer.write_u8(*self.registers.get_v_register(reg).unwrap());
+        }
+        writer.write_u16(*self.registers.get_i_register());
+        writer.write_u16(*self.registers.get_pc_register());
+
+        // call stack
+        let frames = self.get_call_stack();
+        writer.write_u16(frames.len() as u16);
+        frames.iter().for_each(|&frame| writer.write_u16(frame));
+
+        // timers
+        writer.write_u8(*self.timers.get_delay());
+        writer.write_u8(*self.timers.get_sound());
+
+        // display, one bit per pixel
+        writer.write_bool(self.output.is_hires());
+        writer.write_u8(self.output.get_selected_planes());
+        writer.write_u8(self.output.planes.len() as u8);
+        for plane in &self.output.planes {
+            let bits = plane
+                .iter()
+                .flat_map(|row| row.iter())
+                .map(|&pixel| pixel == Chip8Pixel::White)
+                .collect::<Vec<_>>();
+
+            let packed = bits
+                .chunks(8)
+                .map(|chunk| chunk.iter().enumerate().fold(0u8, |byte, (i, &bit)| byte | ((bit as u8) << i)))
+                .collect::<Vec<_>>();
+
+            writer.write_bytes(&packed);
+        }
+
+        // input
+        writer.write_u16(self.input.get_keys_mask());
+
+        // audio
+        match self.audio.get_pattern() {
+            Some(pattern) => {
+                writer.write_bool(true);
+                pattern.iter().for_each(|&byte| writer.write_u8(byte));
+            },
+            None => writer.write_bool(false),
+        }
+        writer.write_u8(*self.audio.get_pitch());
+
+        // quirks
+        write_quirks(&mut writer, &self.quirks);
+
+        // vertical blank flag
+        writer.write_bool(self.vblank);
+
+        // random number generator (since version 2)
+        writer.write_u64(self.random.get_seed());
+        writer.write_u64(self.random.get_state());
+
+        // timing and counters (since version 3)
+        write_timing(&mut writer, self.timing, self.instructions_per_frame);
+        writer.write_u64(self.instruction_count);
+        writer.write_u64(self.frame_count);
+        writer.write_u64(self.frame_start_instruction);
+        writer.write_u64(self.cycle_count);
+        writer.write_u64(self.frame_cycles);
+
+        wrap_payload(MAGIC, SAVE_STATE_VERSION, &writer.into_bytes())
+    }
+
+    // restores the machine state from a save state. nothing is changed if the save state can't be loaded.
+    // version 1 save states don't include the random number generator, and versions 1 and 2 don't include the timing
+    // model and the instruction, frame and cycle counters, so the current ones are kept
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), SaveStateError> {
+        let (version, payload) = unwrap_payload(MAGIC, SAVE_STATE_VERSION, data)?;
+        let mut reader = StateReader::new(payload);
+        let mut loaded = Chip8::new();
+        loaded.random = self.random.clone();
+        loaded.timing = self.timing;
+        loaded.instructions_per_frame = self.instructions_per_frame;
+        loaded.instruction_count = self.instruction_count;
+        loaded.frame_count = self.frame_count;
+        loaded.frame_start_instruction = self.frame_start_instruction;
+        loaded.cycle_count = self.cycle_count;
+        loaded.frame_cycles = self.frame_cycles;
+
+        // memory
+        let memory = reader.read_bytes()?;
+        if memory.len() != loaded.memory.get_size() {
+            return Err(SaveStateError::Invalid("memory size"));
+        }
+        loaded.memory.get_bytes_mut().copy_from_slice(memory);
+
+        // registers
+        for reg in 0..16 {
+            *loaded.registers.get_v_register_mut(reg).unwrap() = reader.read_u8()?;
+        }
+        *loaded.registers.get_i_register_mut() = reader.read_u16()?;
+        *loaded.registers.get_pc_register_mut() = reader.read_u16()?;
+
+        // call stack (restored after the quirks, since they decide where it lives)
+        let frame_count = reader.read_u16()? as usize;
+        let frames = (0..frame_count)
+            .map(|_| reader.read_u16())
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // timers
+        *loaded.timers.get_delay_mut() = reader.read_u8()?;
+        *loaded.timers.get_sound_mut() = reader.read_u8()?;
+
+        // display
+        loaded.output.set_hires(reader.read_bool()?);
+        loaded.output.set_selected_planes(reader.read_u8()?);
+        if reader.read_u8()? as usize != loaded.output.planes.len() {
+            return Err(SaveStateError::Invalid("plane count"));
+        }
+        for plane in &mut loaded.output.planes {
+            let packed = reader.read_bytes()?;
+            if packed.len() != MAX_DISPLAY_WIDTH * MAX_DISPLAY_HEIGHT / 8 {
+                return Err(SaveStateError::Invalid("display size"));
+            }
+
+            for (i, pixel) in plane.iter_mut().flat_map(|row| row.iter_mut()).enumerate() {
+                let bit = packed[i / 8] & (1 << (i % 8)) != 0;
+                *pixel = if bit { Chip8Pixel::White } else { Chip8Pixel::Black };
+            }
+        }
+
+        // input
+        loaded.input.set_keys_mask(reader.read_u16()?);
+
+        // audio
+        if reader.read_bool()? {
+            let mut pattern = [0; 16];
+            for byte in &mut pattern {
+                *byte = reader.read_u8()?;
+            }
+            *loaded.audio.get_pattern_mut() = Some(pattern);
+        }
+        *loaded.audio.get_pitch_mut() = reader.read_u8()?;
+
+        // quirks
+        loaded.quirks = read_quirks(&mut reader)?;
+
+        loaded.stack.set_frames(&frames, loaded.quirks.memory_mapped_stack);
+
+        // vertical blank flag
+        loaded.vblank = reader.read_bool()?;
+
+        // random number generator. versions 2 and 3 also stored the VIP-like random mode and its counter, which were
+        // removed, so states using that mode can't be continued
+        if version >= 2 {
+            let vip_like = version < 4 && reader.read_bool()?;
+            let seed = reader.read_u64()?;
+            let state = reader.read_u64()?;
+            if version < 4 {
+                reader.read_u16()?;
+            }
+
+            if vip_like {
+                return Err(SaveStateError::Invalid("the VIP-like random mode is no longer supported"));
+            }
+            loaded.random.set_state(seed, state);
+        }
+
+        // timing and counters
+        if version >= 3 {
+            (loaded.timing, loaded.instructions_per_frame) = read_timing(&mut reader)?;
+            loaded.instruction_count = reader.read_u64()?;
+            loaded.frame_count = reader.read_u64()?;
+            loaded.frame_start_instruction = reader.read_u64()?;
+            loaded.cycle_count = reader.read_u64()?;
+            loaded.frame_cycles = reader.read_u64()?;
+        }
+
+        if !reader.is_empty() {
+            return Err(SaveStateError::Invalid("trailing data"));
+        }
+
+        // everything loaded, so swap in the new state (keeping the host's fault policies and RPL flag storage)
+        self.memory = loaded.memory;
+        self.registers = loaded.registers;
+        self.stack = loaded.stack;
+        self.timers = loaded.timers;
+        self.output = loaded.output;
+        self.input = loaded.input;
+        self.audio = loaded.audio;
+        self.quirks = loaded.quirks;
+        self.vblank = loaded.vblank;
+        self.random = loaded.random;
+        self.timing = loaded.timing;
+        self.instructions_per_frame = loaded.instructions_per_frame;
+        self.instruction_count = loaded.instruction_count;
+        self.frame_count = loaded.frame_count;
+        self.frame_start_instruction = loaded.frame_start_instruction;
+        self.cycle_count = loaded.cycle_count;
+        self.frame_cycles = loaded.frame_cycles;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,202 @@
+use std::time::Duration;
+use super::Chip8;
+use super::error::{Chip8Error, StepOutcome};
+
+// the number of frames per second, the timers tick once per frame
+pub const FRAMES_PER_SECOND: u32 = 60;
+
+// how long a frame lasts
+pub const FRAME_DURATION: Duration = Duration::from_nanos(1_000_000_000 / FRAMES_PER_SECOND as u64);
+
+// the number of instructions run_frame executes by default, about 600 a second
+pub const DEFAULT_INSTRUCTIONS_PER_FRAME: usize = 10;
+
+// the most frames run_for catches up on at once, so a host that was suspended for a while (e.g. a background browser
+// tab) doesn't freeze while the emulator runs minutes of frames
+const MAX_CATCH_UP_FRAMES: u32 = 6;
+
+// what happened while running frames
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct FrameReport {
+    pub frames: u32, // the number of frames that were run
+    pub instructions: usize,
+    pub display_dirty: bool, // whether the display changed, i.e. needs to be drawn again
+    pub sound: bool, // whether the sound timer is running at the end
+    pub waiting_for_key: bool, // whether the program is waiting for a key press at the end
+    pub exited: bool, // whether the program exited (00FD), which stops the frame early
+}
+
+impl FrameReport {
+    // adds up the report of a later frame
+    fn merge(&mut self, later: FrameReport) {
+        self.frames += later.frames;
+        self.instructions += later.instructions;
+        self.display_dirty |= later.display_dirty;
+        self.sound = later.sound;
+        self.waiting_for_key = later.waiting_for_key;
+        self.exited |= later.exited;
+    }
+}
+
+// where run_with_clock gets the time from
+pub trait Chip8Clock {
+    // gets the time that passed since the last call (nothing on the first call)
+    fn elapsed(&mut self) -> Duration;
+}
+
+// a clock that follows the system's monotonic clock (std::time::Instant isn't available on the web, see ManualClock)
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Debug, Clone, Default)]
+pub struct SystemClock {
+    last: Option<std::time::Instant>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl SystemClock {
+    pub fn new() -> SystemClock {
+        SystemClock { last: None }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Chip8Clock for SystemClock {
+    fn elapsed(&mut self) -> Duration {
+        let now = std::time::Instant::now();
+        let elapsed = self.last.map_or(Duration::ZERO, |last| now - last);
+        self.last = Some(now);
+
+        elapsed
+    }
+}
+
+// a clock that is told the time, e.g. the timestamps of requestAnimationFrame on the web
+#[derive(Debug, Clone, Default)]
+pub struct ManualClock {
+    now: Option<Duration>,
+    last: Option<Duration>,
+}
+
+impl ManualClock {
+    pub fn new() -> ManualClock {
+        ManualClock { now: None, last: None }
+    }
+
+    // sets the current time, measured from any fixed point
+    pub fn set_time(&mut self, now: Duration) {
+        self.now = Some(now);
+    }
+
+    // moves the current time forward
+    pub fn advance(&mut self, duration: Duration) {
+        self.now = Some(self.now.unwrap_or_default() + duration);
+    }
+}
+
+impl Chip8Clock for ManualClock {
+    fn elapsed(&mut self) -> Duration {
+        let elapsed = match (self.last, self.now) {
+            (Some(last), Some(now)) => now.saturating_sub(last),
+            _ => Duration::ZERO,
+        };
+        self.last = self.now;
+
+        elapsed
+    }
+}
+
+impl super::Chip8 {
+    // runs a frame: instructions_per_frame instructions (or with the VIP timing model, everything until the vertical
+    // blank interrupt), then the timers tick once. the keys are read from the input source first, and afterwards the
+    // display is presented on the video sink if it changed (the audio sink hears about the buzzer as it happens)
+    pub fn run_frame(&mut self) -> Result<FrameReport, Chip8Error> {
+        self.run_frame_with(|_| true)
+    }
+
+    // runs a frame, calling a function before every instruction (e.g. to apply a movie). if it returns false, the frame
+    // stops right there without the vertical blank
+    pub fn run_frame_with(&mut self, mut before_instruction: impl FnMut(&mut Chip8) -> bool) -> Result<FrameReport, Chip8Error> {
+        self.poll_input();
+
+        let display_hash = self.output.get_display_hash();
+        let hires = self.output.is_hires();
+        let frame = self.frame_count;
+        let mut report = FrameReport::default();
+        let mut last_outcome = StepOutcome::Executed;
+        let mut finished = true;
+
+        while !self.is_frame_complete(frame, report.instructions, self.instructions_per_frame) {
+            if !before_instruction(self) {
+                finished = false;
+                break;
+            }
+
+            report.instructions += 1;
+            last_outcome = self.execute_next_instruction()?;
+
+            if last_outcome == StepOutcome::Exited {
+                report.exited = true;
+                finished = false;
+                break;
+            }
+        }
+
+        if finished {
+            self.end_frame();
+            report.frames = 1;
+        }
+
+        report.display_dirty = self.output.get_display_hash() != display_hash || self.output.is_hires() != hires;
+        report.sound = *self.timers.get_sound() > 0;
+        report.waiting_for_key = last_outcome == StepOutcome::WaitingForKey;
+
+        if report.display_dirty {
+            self.present();
+        }
+
+        Ok(report)
+    }
+
+    // runs as many frames as fit in the time that passed, keeping the rest of a frame for the next call
+    pub fn run_for(&mut self, duration: Duration) -> Result<FrameReport, Chip8Error> {
+        self.run_for_with(duration, |_| true)
+    }
+
+    // runs as many frames as fit in the time that passed, calling a function before every instruction (see
+    // run_frame_with)
+    pub fn run_for_with(&mut self, duration: Duration, before_instruction: impl FnMut(&mut Chip8) -> bool) -> Result<FrameReport, Chip8Error> {
+        self.run_for_with_hooks(duration, before_instruction, |_| {})
+    }
+
+    // like run_for_with, but also calls a function after every frame that ran to its end (e.g. to record it for
+    // rewinding), since catching up can run several frames in one call
+    pub fn run_for_with_hooks(
+        &mut self,
+        duration: Duration,
+        mut before_instruction: impl FnMut(&mut Chip8) -> bool,
+        mut after_frame: impl FnMut(&Chip8),
+    ) -> Result<FrameReport, Chip8Error> {
+        self.frame_time = (self.frame_time + duration).min(FRAME_DURATION * MAX_CATCH_UP_FRAMES);
+        let mut report = FrameReport { sound: *self.timers.get_sound() > 0, ..FrameReport::default() };
+
+        while self.frame_time >= FRAME_DURATION {
+            self.frame_time -= FRAME_DURATION;
+
+            let frame = self.run_frame_with(&mut before_instruction)?;
+            let stopped = frame.frames == 0;
+            report.merge(frame);
+
+            if stopped {
+                break;
+            }
+
+            after_frame(self);
+        }
+
+        Ok(report)
+    }
+
+    // runs the frames for the time a clock says has passed since the last call
+    pub fn run_with_clock(&mut self, clock: &mut dyn Chip8Clock) -> Result<FrameReport, Chip8Error> {
+        self.run_for(clock.elapsed())
+    }
+}
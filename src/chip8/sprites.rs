@@ -1,44 +1,55 @@
+use super::error::Chip8ErrorReason;
 use super::memory::Chip8Memory;
 use super::output::Chip8Pixel;
 
 #[derive(Debug)]
 pub struct Chip8Sprite {
-    pub pixels: Vec<[Chip8Pixel; 8]>, // a sprite is made up of up to 15 rows of 8 pixels
+    pub pixels: Vec<Vec<Chip8Pixel>>, // a sprite is made up of up to 15 rows of 8 pixels, or 16 rows of 16 pixels for SUPER-CHIP large sprites
 }
 
 impl Chip8Sprite {
-    pub fn new(memory: &Chip8Memory, starting_addr: usize, sprite_height: usize) -> Chip8Sprite {
-        assert!(sprite_height < 16);
+    pub fn new(memory: &Chip8Memory, starting_addr: usize, sprite_height: usize) -> Result<Chip8Sprite, Chip8ErrorReason> {
+        if sprite_height >= 16 {
+            return Err(Chip8ErrorReason::InvalidSpriteHeight { height: sprite_height });
+        }
+
+        Chip8Sprite::new_with_width(memory, starting_addr, sprite_height, 1)
+    }
 
+    // creates a 16x16 SUPER-CHIP sprite, made up of 2 bytes per row
+    pub fn new_large(memory: &Chip8Memory, starting_addr: usize) -> Result<Chip8Sprite, Chip8ErrorReason> {
+        Chip8Sprite::new_with_width(memory, starting_addr, 16, 2)
+    }
+
+    // creates a sprite with a given number of bytes per row
+    fn new_with_width(memory: &Chip8Memory, starting_addr: usize, sprite_height: usize, row_bytes: usize) -> Result<Chip8Sprite, Chip8ErrorReason> {
         // create new blank instance
         let mut inst = Chip8Sprite {
             pixels: Vec::with_capacity(sprite_height),
         };
 
         // read the bytes that make up the sprite from memory
-        let sprite_bytes = (0..sprite_height)
+        let sprite_bytes = (0..sprite_height * row_bytes)
             .map(|x| starting_addr + x)
-            .map(|addr| memory.get_memory_at(addr).clone());
+            .map(|addr| memory.get_memory_at(addr).copied())
+            .collect::<Result<Vec<_>, _>>()?;
 
-        // iterate over the sprite bytes and convert them to pixel arrays (a bit of 1 -> White, 0 -> Black), and then push the bit array to the sprite instance
-        for byte in sprite_bytes {
+        // iterate over the sprite rows and convert them to pixel arrays (a bit of 1 -> White, 0 -> Black), and then push the pixel array to the sprite instance
+        for row in sprite_bytes.chunks(row_bytes) {
             // convert to bit array iterator
-            let bit_arr = (0..8)
-                .map(|bit| (byte & (0x80 >> bit)) > 0);
-
-            // blank mutable pixel array
-            let mut pixel_array = [Chip8Pixel::Black; 8];
+            let bit_arr = row
+                .iter()
+                .flat_map(|byte| (0..8).map(move |bit| (byte & (0x80 >> bit)) > 0));
 
-            // go through the bit array and assign the appropriate values to pixel_array
-            bit_arr
+            // go through the bit array and convert it into pixels
+            let pixel_array = bit_arr
                 .map(|bit| if bit { Chip8Pixel::White } else { Chip8Pixel::Black })
-                .enumerate()
-                .for_each(|(i, pixel)| pixel_array[i] = pixel);
+                .collect::<Vec<_>>();
 
             // push the pixel array to the sprite instance
             inst.pixels.push(pixel_array);
         }
 
-        inst
+        Ok(inst)
     }
-}
\ No newline at end of file
+}
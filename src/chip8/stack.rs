@@ -0,0 +1,89 @@
+use super::error::Chip8ErrorReason;
+use super::memory::Chip8Memory;
+
+// where the return addresses are kept when the stack is memory mapped, like the COSMAC VIP interpreter's stack area
+pub const MAPPED_STACK_ADDR: usize = 0x0EA0;
+
+// the subroutine call stack. normally the return addresses are kept separately from memory, but they can also be
+// kept in memory (starting at MAPPED_STACK_ADDR) for programs that rely on reading or changing them
+#[derive(Debug)]
+pub struct Chip8Stack {
+    frames: Vec<u16>, // return addresses, oldest first (unused while the stack is memory mapped)
+    depth: usize,
+}
+
+impl Chip8Stack {
+    // creates a new empty stack
+    pub fn new() -> Chip8Stack {
+        Chip8Stack { frames: Vec::new(), depth: 0 }
+    }
+
+    // gets the number of return addresses on the stack
+    pub fn get_depth(&self) -> usize {
+        self.depth
+    }
+
+    // pushes a return address, failing if the stack already holds max_depth addresses
+    pub fn push(&mut self, memory: &mut Chip8Memory, addr: u16, max_depth: usize, memory_mapped: bool) -> Result<(), Chip8ErrorReason> {
+        if self.depth >= max_depth {
+            return Err(Chip8ErrorReason::StackOverflow { depth: self.depth });
+        }
+
+        if memory_mapped {
+            let frame_addr = MAPPED_STACK_ADDR + 2 * self.depth;
+
+            *memory.get_memory_at_mut(frame_addr)? = ((addr & 0xFF00) >> 8) as u8;
+            *memory.get_memory_at_mut(frame_addr + 1)? = (addr & 0x00FF) as u8;
+        } else {
+            self.frames.push(addr);
+        }
+
+        self.depth += 1;
+        Ok(())
+    }
+
+    // pops the most recent return address, failing if the stack is empty
+    pub fn pop(&mut self, memory: &Chip8Memory, memory_mapped: bool) -> Result<u16, Chip8ErrorReason> {
+        if self.depth == 0 {
+            return Err(Chip8ErrorReason::StackUnderflow);
+        }
+
+        let addr = if memory_mapped {
+            memory.get_memory_at_u16(MAPPED_STACK_ADDR + 2 * (self.depth - 1))?
+        } else {
+            self.frames.pop().ok_or(Chip8ErrorReason::StackUnderflow)?
+        };
+
+        self.depth -= 1;
+        Ok(addr)
+    }
+
+    // gets the return addresses on the stack, oldest first
+    pub fn get_frames(&self, memory: &Chip8Memory, memory_mapped: bool) -> Vec<u16> {
+        if memory_mapped {
+            (0..self.depth)
+                .map(|i| memory.get_memory_at_u16(MAPPED_STACK_ADDR + 2 * i).unwrap_or(0))
+                .collect()
+        } else {
+            self.frames.clone()
+        }
+    }
+
+    // restores the stack from a list of return addresses, oldest first (when memory mapped, they are expected to already be in memory)
+    pub fn set_frames(&mut self, frames: &[u16], memory_mapped: bool) {
+        self.depth = frames.len();
+        self.frames = if memory_mapped { Vec::new() } else { frames.to_vec() };
+    }
+
+    // empties the stack
+    pub fn clear(&mut self) {
+        self.frames.clear();
+        self.depth = 0;
+    }
+}
+
+impl Default for Chip8Stack {
+    fn default() -> Self {
+        Chip8Stack::new()
+    }
+}
@@ -0,0 +1,229 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use super::Chip8;
+use super::frontend::AudioSink;
+use super::scheduler::FRAMES_PER_SECOND;
+
+// the sample rate used unless another one is asked for
+pub const DEFAULT_SAMPLE_RATE: u32 = 44100;
+
+// the pitch of the buzzer unless another one is asked for, in Hz
+pub const DEFAULT_FREQUENCY: f64 = 440.0;
+
+// how long the tone takes to fade in and out when the buzzer turns on or off, short enough not to be heard as a fade
+// but long enough to stop the speaker from clicking
+const FADE_SECONDS: f64 = 0.002;
+
+// the shape of the buzzer's tone
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Waveform {
+    Square, // like the COSMAC VIP's buzzer
+    Sine, // a softer tone
+}
+
+impl Waveform {
+    // gets a waveform by its name ("square" or "sine")
+    pub fn from_name(name: &str) -> Option<Waveform> {
+        match name {
+            "square" => Some(Waveform::Square),
+            "sine" => Some(Waveform::Sine),
+            _ => None,
+        }
+    }
+}
+
+// turns the buzzer into PCM samples (mono, -1.0 to 1.0), one frame at a time. as an audio sink it is told where in
+// the frame the buzzer turns on and off, so the tone starts and stops at the right sample rather than at the next
+// frame. while an XO-CHIP program has loaded an audio pattern, the pattern is played instead of the tone
+#[derive(Debug)]
+pub struct Chip8Synth {
+    pub waveform: Waveform,
+    pub frequency: f64, // in Hz
+    pub volume: f32, // the peak amplitude, 0.0 to 1.0
+    pub buffer_limit: Option<usize>, // the most samples kept for fill_buffer, the oldest are dropped. None keeps them all
+    sample_rate: u32,
+    gate: bool, // whether the buzzer is on at the end of the frame so far
+    edges: Vec<(f64, bool)>, // where in the current frame (0.0 to 1.0) the buzzer turned on or off
+    pattern: Option<([u8; 16], f64)>, // the XO-CHIP audio pattern and its playback rate in bits per second
+    phase: f64, // how far through a period of the tone (or the 128 bits of the pattern) the next sample is, 0.0 to 1.0
+    level: f64, // the current volume of the fade, 0.0 to 1.0
+    frame_samples: u32, // the samples of the frames so far that weren't made yet, times FRAMES_PER_SECOND
+    samples: VecDeque<f32>,
+}
+
+impl Chip8Synth {
+    pub fn new(sample_rate: u32) -> Chip8Synth {
+        Chip8Synth {
+            waveform: Waveform::Square,
+            frequency: DEFAULT_FREQUENCY,
+            volume: 0.25,
+            buffer_limit: None,
+            sample_rate,
+            gate: false,
+            edges: Vec::new(),
+            pattern: None,
+            phase: 0.0,
+            level: 0.0,
+            frame_samples: 0,
+            samples: VecDeque::new(),
+        }
+    }
+
+    // gets the number of samples per second
+    pub fn get_sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    // changes the number of samples per second, dropping the samples that were made at the old rate
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.sample_rate = sample_rate;
+        self.frame_samples = 0;
+        self.samples.clear();
+    }
+
+    // gets the number of samples waiting to be taken
+    pub fn get_buffered(&self) -> usize {
+        self.samples.len()
+    }
+
+    // turns the buzzer on or off at a point in the current frame (0.0 = its start, 1.0 = its end)
+    pub fn set_gate(&mut self, on: bool, position: f64) {
+        self.edges.push((position.clamp(0.0, 1.0), on));
+    }
+
+    // plays an XO-CHIP audio pattern (see Chip8Audio) instead of the tone, or the tone again with None
+    pub fn set_pattern(&mut self, pattern: Option<([u8; 16], f64)>) {
+        self.pattern = pattern;
+    }
+
+    // gets the next sample of the tone or pattern, at full volume
+    fn next_wave(&mut self) -> f64 {
+        let (sample, period) = match self.pattern {
+            Some((pattern, rate)) => {
+                let bit = (self.phase * 128.0) as usize % 128;
+                let on = pattern[bit / 8] & (0x80 >> (bit % 8)) != 0;
+
+                (if on { 1.0 } else { -1.0 }, rate / 128.0)
+            },
+            None => match self.waveform {
+                Waveform::Square => (if self.phase < 0.5 { 1.0 } else { -1.0 }, self.frequency),
+                Waveform::Sine => ((self.phase * std::f64::consts::TAU).sin(), self.frequency),
+            },
+        };
+
+        self.phase = (self.phase + period / self.sample_rate as f64).fract();
+        sample
+    }
+
+    // makes the samples of the frame that just ended, with the buzzer turning on and off where set_gate said it did
+    pub fn synthesize_frame(&mut self) {
+        self.frame_samples += self.sample_rate;
+        let count = (self.frame_samples / FRAMES_PER_SECOND) as usize;
+        self.frame_samples %= FRAMES_PER_SECOND;
+
+        let fade_step = 1.0 / (FADE_SECONDS * self.sample_rate as f64).max(1.0);
+        let mut edges = std::mem::take(&mut self.edges).into_iter().peekable();
+
+        for i in 0..count {
+            let position = i as f64 / count as f64;
+            while let Some((_, on)) = edges.next_if(|(at, _)| *at <= position) {
+                self.gate = on;
+            }
+
+            // fade towards the gate instead of jumping, which would click
+            let target = if self.gate { 1.0 } else { 0.0 };
+            self.level = if self.level < target { (self.level + fade_step).min(target) } else { (self.level - fade_step).max(target) };
+
+            let sample = self.next_wave() * self.level * self.volume as f64;
+            self.samples.push_back(sample as f32);
+        }
+
+        // edges at the very end of the frame
+        for (_, on) in edges {
+            self.gate = on;
+        }
+
+        if let Some(limit) = self.buffer_limit {
+            while self.samples.len() > limit {
+                self.samples.pop_front();
+            }
+        }
+    }
+
+    // moves the oldest samples into a buffer, filling the rest with silence if there aren't enough. returns the number
+    // of samples that were made by the synth
+    pub fn fill_buffer(&mut self, buffer: &mut [f32]) -> usize {
+        let count = buffer.len().min(self.samples.len());
+
+        for (out, sample) in buffer.iter_mut().zip(self.samples.drain(..count)) {
+            *out = sample;
+        }
+        buffer[count..].fill(0.0);
+
+        count
+    }
+
+    // takes every sample made so far
+    pub fn take_samples(&mut self) -> Vec<f32> {
+        self.samples.drain(..).collect()
+    }
+}
+
+impl Default for Chip8Synth {
+    fn default() -> Self {
+        Chip8Synth::new(DEFAULT_SAMPLE_RATE)
+    }
+}
+
+// the XO-CHIP audio pattern a machine is playing, if it loaded one
+fn machine_pattern(c8: &Chip8) -> Option<([u8; 16], f64)> {
+    c8.audio.get_pattern().as_ref().map(|pattern| (*pattern, c8.audio.get_playback_rate()))
+}
+
+impl AudioSink for Chip8Synth {
+    fn set_buzzer(&mut self, on: bool, c8: &Chip8) {
+        self.set_pattern(machine_pattern(c8));
+        self.set_gate(on, c8.get_frame_position());
+    }
+
+    fn end_frame(&mut self, c8: &Chip8) {
+        self.set_pattern(machine_pattern(c8));
+        self.synthesize_frame();
+    }
+}
+
+// a synth shared with whoever takes the samples, e.g. an audio callback on another thread
+impl AudioSink for Arc<Mutex<Chip8Synth>> {
+    fn set_buzzer(&mut self, on: bool, c8: &Chip8) {
+        self.lock().unwrap_or_else(|e| e.into_inner()).set_buzzer(on, c8);
+    }
+
+    fn end_frame(&mut self, c8: &Chip8) {
+        self.lock().unwrap_or_else(|e| e.into_inner()).end_frame(c8);
+    }
+}
+
+// encodes samples as a mono 16-bit PCM WAV file
+pub fn to_wav(samples: &[f32], sample_rate: u32) -> Vec<u8> {
+    let data_len = samples.len() as u32 * 2;
+    let mut wav = Vec::with_capacity(44 + data_len as usize);
+
+    wav.extend_from_slice(b"RIFF");
+    wav.extend_from_slice(&(36 + data_len).to_le_bytes());
+    wav.extend_from_slice(b"WAVEfmt ");
+    wav.extend_from_slice(&16u32.to_le_bytes()); // the size of the format chunk
+    wav.extend_from_slice(&1u16.to_le_bytes()); // PCM
+    wav.extend_from_slice(&1u16.to_le_bytes()); // mono
+    wav.extend_from_slice(&sample_rate.to_le_bytes());
+    wav.extend_from_slice(&(sample_rate * 2).to_le_bytes()); // bytes per second
+    wav.extend_from_slice(&2u16.to_le_bytes()); // bytes per sample
+    wav.extend_from_slice(&16u16.to_le_bytes()); // bits per sample
+    wav.extend_from_slice(b"data");
+    wav.extend_from_slice(&data_len.to_le_bytes());
+
+    for sample in samples {
+        wav.extend_from_slice(&((sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16).to_le_bytes());
+    }
+
+    wav
+}
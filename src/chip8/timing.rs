@@ -0,0 +1,167 @@
+use super::cpu::{ALUOperations, CPUInstrTarget, CPUInstruction};
+use super::error::StepOutcome;
+
+// the COSMAC VIP runs its 1802 at 1.7609 MHz with 8 clocks per machine cycle, so a 60 Hz frame is this many cycles
+pub const VIP_CYCLES_PER_FRAME: u64 = 3668;
+
+// the cycles of each frame taken by the vertical blank interrupt routine and the display DMA, which leaves the rest for
+// the interpreter
+pub const VIP_INTERRUPT_CYCLES: u64 = 1832;
+
+// the cycles the interpreter spends fetching and decoding every instruction
+const FETCH_CYCLES: u64 = 40;
+
+// the extra cycles of a skip instruction that skips
+const SKIP_TAKEN_CYCLES: u64 = 4;
+
+// how long sprites take to draw: a fixed cost, plus a cost per row that grows with the number of bits each row of the
+// sprite has to be shifted by when it isn't byte aligned
+const DRAW_CYCLES: u64 = 26;
+const DRAW_ROW_CYCLES: u64 = 34;
+const DRAW_ROW_SHIFT_CYCLES: u64 = 8;
+
+// how time passes in the emulator
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TimingModel {
+    Instructions, // the host runs some number of instructions between vertical blanks (see run_frame)
+    CosmacVip, // every instruction costs its COSMAC VIP machine cycles, and the vertical blank interrupt happens on its own
+}
+
+impl TimingModel {
+    // gets a timing model by its name ("instructions" or "vip")
+    pub fn from_name(name: &str) -> Option<TimingModel> {
+        match name {
+            "instructions" | "fixed" => Some(TimingModel::Instructions),
+            "vip" | "cosmac-vip" => Some(TimingModel::CosmacVip),
+            _ => None,
+        }
+    }
+}
+
+impl super::Chip8 {
+    // reads a V register without applying the fault policy, for instructions that are about to be executed (0 if the
+    // register doesn't exist, the instruction is going to fault anyway)
+    fn peek_target(&self, target: &CPUInstrTarget) -> u64 {
+        match target {
+            CPUInstrTarget::VRegister(reg) => self.registers.get_v_register(*reg).map_or(0, |val| *val as u64),
+            CPUInstrTarget::Constant(val) => *val as u64,
+            _ => 0,
+        }
+    }
+
+    // gets the approximate number of COSMAC VIP machine cycles the instruction at the PC is going to take, not counting
+    // the extra cycles of a skip that skips. instructions the VIP doesn't have cost as much as a jump
+    pub fn instruction_cycles(&self, instruction: &CPUInstruction) -> u64 {
+        let execute = match instruction {
+            CPUInstruction::ClearDisplay => 3078,
+            CPUInstruction::Return => 10,
+            CPUInstruction::Jump { .. } => 12,
+            CPUInstruction::CallSubroutine { .. } => 26,
+            CPUInstruction::CompareEq { left: CPUInstrTarget::IsKeyInVRegPressed(_), .. } => 14,
+            CPUInstruction::CompareEq { right: CPUInstrTarget::VRegister(_), .. } => 14,
+            CPUInstruction::CompareEq { .. } => 10,
+            CPUInstruction::Assignment { to: CPUInstrTarget::VRegister(_), from: CPUInstrTarget::Constant(_) } => 6,
+            CPUInstruction::Assignment { from: CPUInstrTarget::RandomNum(_), .. } => 36,
+            CPUInstruction::Assignment { from: CPUInstrTarget::CurrentKeyPressed, .. } => 18,
+            CPUInstruction::Assignment { to: CPUInstrTarget::IRegister, from: CPUInstrTarget::SpriteAddress(_) } => 16,
+            CPUInstruction::Assignment { to: CPUInstrTarget::IRegister, .. } => 12,
+            CPUInstruction::Assignment { .. } => 10,
+            CPUInstruction::ALUOperation { op: ALUOperations::Add { update_vf: false }, left: CPUInstrTarget::IRegister, .. } => 16,
+            CPUInstruction::ALUOperation { right: CPUInstrTarget::Constant(_), .. } => 10,
+            CPUInstruction::ALUOperation { .. } => 44,
+            CPUInstruction::SpecialJump { .. } => 22,
+
+            // every row is shifted into place bit by bit, and a 16x16 sprite has two bytes per row
+            CPUInstruction::Draw { x_reg, height_px, .. } => {
+                let shift = self.peek_target(x_reg) % 8;
+                let rows = match self.peek_target(height_px) {
+                    0 => 32,
+                    height => height,
+                };
+
+                DRAW_CYCLES + rows * (DRAW_ROW_CYCLES + DRAW_ROW_SHIFT_CYCLES * shift)
+            },
+
+            // the VIP counts each digit down by repeated subtraction
+            CPUInstruction::Bcd { x_reg } => {
+                let val = self.peek_target(x_reg);
+                80 + 16 * (val / 100 + val / 10 % 10 + val % 10)
+            },
+
+            CPUInstruction::RegisterDump { x } | CPUInstruction::RegisterLoad { x } => 14 + 14 * (self.peek_target(x) + 1),
+            CPUInstruction::RegisterRangeDump { x, y } | CPUInstruction::RegisterRangeLoad { x, y } => {
+                14 + 14 * (self.peek_target(x).abs_diff(self.peek_target(y)) + 1)
+            },
+
+            _ => 12,
+        };
+
+        FETCH_CYCLES + execute
+    }
+
+    // gets the number of COSMAC VIP machine cycles since power on. with the VIP timing model this includes the vertical
+    // blank interrupts and the time spent waiting for them, otherwise it only adds up the instructions
+    pub fn get_cycle_count(&self) -> u64 {
+        self.cycle_count
+    }
+
+    // gets the number of cycles the interpreter has left before the next vertical blank interrupt
+    pub fn get_cycles_until_vblank(&self) -> u64 {
+        (VIP_CYCLES_PER_FRAME - VIP_INTERRUPT_CYCLES).saturating_sub(self.frame_cycles)
+    }
+
+    // gets how far the current frame has got, from 0.0 right after a vertical blank to 1.0 right before the next one:
+    // the share of the frame's instructions that were executed, or with the VIP timing model the share of its cycles
+    // that were used (the interrupt comes first)
+    pub fn get_frame_position(&self) -> f64 {
+        let position = match self.timing {
+            TimingModel::Instructions => {
+                let executed = self.instruction_count.saturating_sub(self.frame_start_instruction);
+                executed as f64 / self.instructions_per_frame.max(1) as f64
+            },
+            TimingModel::CosmacVip => (VIP_INTERRUPT_CYCLES + self.frame_cycles) as f64 / VIP_CYCLES_PER_FRAME as f64,
+        };
+
+        position.min(1.0)
+    }
+
+    // charges the cycles of an executed instruction. with the VIP timing model, the vertical blank interrupt happens when
+    // the frame runs out of cycles, and an instruction waiting for it gives up the rest of the frame
+    pub(super) fn charge_cycles(&mut self, cycles: u64, skipped: bool, outcome: &StepOutcome) {
+        let cycles = if skipped { cycles + SKIP_TAKEN_CYCLES } else { cycles };
+
+        if self.timing != TimingModel::CosmacVip {
+            self.cycle_count += cycles;
+            return;
+        }
+
+        let cycles = match outcome {
+            StepOutcome::WaitingForVBlank => self.get_cycles_until_vblank().max(cycles),
+            _ => cycles,
+        };
+        self.cycle_count += cycles;
+        self.frame_cycles += cycles;
+
+        while self.get_cycles_until_vblank() == 0 {
+            self.frame_cycles -= VIP_CYCLES_PER_FRAME - VIP_INTERRUPT_CYCLES;
+            self.cycle_count += VIP_INTERRUPT_CYCLES;
+            self.timer_tick();
+        }
+    }
+
+    // returns true once a frame that started at frame `frame` has run `executed` instructions: the given number of
+    // instructions, or with the VIP timing model everything until the vertical blank interrupt
+    pub(super) fn is_frame_complete(&self, frame: u64, executed: usize, instructions_per_frame: usize) -> bool {
+        match self.timing {
+            TimingModel::Instructions => executed >= instructions_per_frame,
+            TimingModel::CosmacVip => self.frame_count != frame,
+        }
+    }
+
+    // ends a frame with the vertical blank, unless the VIP timing model's interrupt already took care of it
+    pub(super) fn end_frame(&mut self) {
+        if self.timing == TimingModel::Instructions {
+            self.timer_tick();
+        }
+    }
+}
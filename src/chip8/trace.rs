@@ -0,0 +1,289 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::Write;
+use std::ops::RangeInclusive;
+use crate::json::Json;
+use super::Chip8;
+use super::cpu::{CPUInstruction, InstructionClass};
+
+// how trace entries are written
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TraceFormat {
+    Text, // one compact line per instruction
+    JsonLines, // one JSON object per line
+}
+
+impl TraceFormat {
+    // gets a format by its name ("text" or "json")
+    pub fn from_name(name: &str) -> Option<TraceFormat> {
+        match name {
+            "text" => Some(TraceFormat::Text),
+            "json" | "jsonl" => Some(TraceFormat::JsonLines),
+            _ => None,
+        }
+    }
+}
+
+// which instructions get traced, everything by default
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TraceFilter {
+    pub addresses: Vec<RangeInclusive<u16>>, // only instructions at these addresses (any address if empty)
+    pub classes: Vec<InstructionClass>, // only instructions of these classes (any class if empty)
+}
+
+impl TraceFilter {
+    // returns true if an instruction at an address should be traced
+    pub fn matches(&self, pc: u16, class: InstructionClass) -> bool {
+        (self.addresses.is_empty() || self.addresses.iter().any(|range| range.contains(&pc)))
+            && (self.classes.is_empty() || self.classes.contains(&class))
+    }
+}
+
+// the machine state a trace entry compares against, captured right before an instruction executes
+#[derive(Debug, Clone)]
+pub struct TraceSnapshot {
+    v: [u8; 16],
+    writes: Vec<usize>, // the addresses the instruction is going to write
+}
+
+impl TraceSnapshot {
+    pub fn capture(c8: &Chip8, instruction: &CPUInstruction) -> TraceSnapshot {
+        TraceSnapshot {
+            v: std::array::from_fn(|reg| *c8.registers.get_v_register(reg).unwrap()),
+            writes: c8.memory_accesses(instruction).writes.collect(),
+        }
+    }
+}
+
+// everything that is known about one executed instruction
+#[derive(Debug, Clone)]
+pub struct TraceEntry {
+    pub index: u64, // the instruction count before the instruction executed
+    pub frame: u64,
+    pub pc: u16,
+    pub opcode: u16,
+    pub instruction: CPUInstruction,
+    pub register_changes: Vec<(usize, u8, u8)>, // V register, old value, new value
+    pub i: u16, // the values after the instruction executed
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+    pub memory_writes: Vec<(usize, u8)>, // address, new value
+}
+
+impl TraceEntry {
+    // builds an entry by comparing the machine against the snapshot taken before the instruction
+    pub fn new(c8: &Chip8, pc: u16, opcode: u16, instruction: CPUInstruction, before: &TraceSnapshot) -> TraceEntry {
+        let register_changes = (0..16)
+            .map(|reg| (reg, before.v[reg], *c8.registers.get_v_register(reg).unwrap()))
+            .filter(|(_, old, new)| old != new)
+            .collect();
+
+        let memory_writes = before.writes
+            .iter()
+            .filter_map(|&addr| c8.memory.get_memory_at(addr).ok().map(|val| (addr, *val)))
+            .collect();
+
+        TraceEntry {
+            index: c8.get_instruction_count(),
+            frame: c8.get_frame_count(),
+            pc,
+            opcode,
+            instruction,
+            register_changes,
+            i: *c8.registers.get_i_register(),
+            delay_timer: *c8.timers.get_delay(),
+            sound_timer: *c8.timers.get_sound(),
+            memory_writes,
+        }
+    }
+
+    // converts the entry to JSON
+    pub fn to_json(&self) -> Json {
+        let changes = self.register_changes
+            .iter()
+            .map(|&(reg, old, new)| (format!("v{reg:x}"), Json::from(vec![Json::from(old), Json::from(new)])));
+
+        let writes = self.memory_writes
+            .iter()
+            .map(|&(addr, val)| Json::from(vec![Json::from(addr), Json::from(val)]))
+            .collect::<Vec<_>>();
+
+        Json::object([
+            ("index", Json::from(self.index)),
+            ("frame", Json::from(self.frame)),
+            ("pc", Json::from(self.pc)),
+            ("opcode", Json::from(self.opcode)),
+            ("instruction", Json::from(format!("{:?}", self.instruction))),
+            ("class", Json::from(self.instruction.class().name())),
+            ("changes", Json::object(changes)),
+            ("i", Json::from(self.i)),
+            ("dt", Json::from(self.delay_timer)),
+            ("st", Json::from(self.sound_timer)),
+            ("writes", Json::from(writes)),
+        ])
+    }
+
+    // formats the entry in a format
+    pub fn format(&self, format: TraceFormat) -> String {
+        match format {
+            TraceFormat::Text => self.to_string(),
+            TraceFormat::JsonLines => self.to_json().to_string(),
+        }
+    }
+}
+
+// the compact text format: `INDEX PC: OPCODE INSTRUCTION | CHANGES | I=.. DT=.. ST=.. | WRITES`
+impl fmt::Display for TraceEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:08} {:03X}: {:04X} {:?} |", self.index, self.pc, self.opcode, self.instruction)?;
+
+        for (reg, old, new) in &self.register_changes {
+            write!(f, " V{reg:X}:{old:02X}->{new:02X}")?;
+        }
+
+        write!(f, " | I={:03X} DT={:02X} ST={:02X}", self.i, self.delay_timer, self.sound_timer)?;
+
+        if !self.memory_writes.is_empty() {
+            write!(f, " |")?;
+            for (addr, val) in &self.memory_writes {
+                write!(f, " [{addr:03X}]={val:02X}")?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// records every executed instruction that passes a filter, see Chip8::set_tracer. entries are written to a writer
+// (or kept in a ring buffer of the last few, written out by flush), and also logged at the trace level
+pub struct Chip8Tracer {
+    filter: TraceFilter,
+    format: TraceFormat,
+    writer: Option<Box<dyn Write + Send>>,
+    ring: Option<(VecDeque<TraceEntry>, usize)>, // the kept entries and how many to keep
+}
+
+impl Chip8Tracer {
+    // creates a tracer that only logs, until a writer or ring buffer is set up
+    pub fn new(format: TraceFormat) -> Chip8Tracer {
+        Chip8Tracer {
+            filter: TraceFilter::default(),
+            format,
+            writer: None,
+            ring: None,
+        }
+    }
+
+    pub fn get_filter(&self) -> &TraceFilter {
+        &self.filter
+    }
+
+    pub fn get_filter_mut(&mut self) -> &mut TraceFilter {
+        &mut self.filter
+    }
+
+    pub fn set_format(&mut self, format: TraceFormat) {
+        self.format = format;
+    }
+
+    // sets where entries are written
+    pub fn set_writer(&mut self, writer: Box<dyn Write + Send>) {
+        self.writer = Some(writer);
+    }
+
+    // keeps only the last entries in memory instead of writing every entry (None to write every entry again)
+    pub fn set_ring_capacity(&mut self, capacity: Option<usize>) {
+        self.ring = capacity.map(|capacity| (VecDeque::with_capacity(capacity), capacity));
+    }
+
+    // gets the entries in the ring buffer, oldest first
+    pub fn get_entries(&self) -> impl Iterator<Item = &TraceEntry> {
+        self.ring.iter().flat_map(|(entries, _)| entries.iter())
+    }
+
+    // writes a line to the writer, dropping the writer if it fails so a full disk doesn't stop the emulator
+    fn write_line(&mut self, line: &str) {
+        if let Some(writer) = self.writer.as_mut() {
+            if let Err(e) = writeln!(writer, "{line}") {
+                log::warn!("could not write trace: {e}");
+                self.writer = None;
+            }
+        }
+    }
+
+    // records an entry if it passes the filter
+    pub fn record(&mut self, entry: TraceEntry) {
+        if !self.filter.matches(entry.pc, entry.instruction.class()) {
+            return;
+        }
+
+        if log::log_enabled!(log::Level::Trace) {
+            log::trace!("{entry}");
+        }
+
+        match self.ring.as_mut() {
+            Some((entries, capacity)) => {
+                if entries.len() >= *capacity {
+                    entries.pop_front();
+                }
+                if *capacity > 0 {
+                    entries.push_back(entry);
+                }
+            },
+            None => {
+                let line = entry.format(self.format);
+                self.write_line(&line);
+            },
+        }
+    }
+
+    // writes out (and clears) the ring buffer, then flushes the writer
+    pub fn flush(&mut self) {
+        let entries = self.ring
+            .as_mut()
+            .map(|(entries, _)| entries.drain(..).collect::<Vec<_>>())
+            .unwrap_or_default();
+
+        for entry in entries {
+            let line = entry.format(self.format);
+            self.write_line(&line);
+        }
+
+        if let Some(writer) = self.writer.as_mut() {
+            if let Err(e) = writer.flush() {
+                log::warn!("could not write trace: {e}");
+            }
+        }
+    }
+}
+
+impl fmt::Debug for Chip8Tracer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Chip8Tracer")
+            .field("filter", &self.filter)
+            .field("format", &self.format)
+            .field("writer", &self.writer.is_some())
+            .field("ring", &self.ring.as_ref().map(|(entries, capacity)| (entries.len(), capacity)))
+            .finish()
+    }
+}
+
+impl super::Chip8 {
+    // starts tracing every executed instruction (None stops it, nothing is traced or captured without a tracer)
+    pub fn set_tracer(&mut self, tracer: Option<Chip8Tracer>) {
+        self.tracer = tracer.map(Box::new);
+    }
+
+    pub fn get_tracer(&self) -> Option<&Chip8Tracer> {
+        self.tracer.as_deref()
+    }
+
+    pub fn get_tracer_mut(&mut self) -> Option<&mut Chip8Tracer> {
+        self.tracer.as_deref_mut()
+    }
+
+    // stops tracing, returning the tracer
+    pub fn take_tracer(&mut self) -> Option<Chip8Tracer> {
+        self.tracer.take().map(|tracer| *tracer)
+    }
+}
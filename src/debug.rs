@@ -0,0 +1,120 @@
+use chip8_rs::chip8;
+use chip8::debugger::{Chip8Debugger, CommandOutcome, StopReason};
+use chip8::quirks::Quirks;
+use chip8::random::Chip8Random;
+use chip8::scheduler::DEFAULT_INSTRUCTIONS_PER_FRAME;
+use std::io::{self, Write};
+use std::process;
+use std::sync::mpsc;
+use std::thread;
+use std::time;
+
+use crate::{apply_quirk_setting, load_rom, usage_error};
+
+// prints the prompt and waits for a command, exiting when stdin is closed
+fn prompt(commands: &mpsc::Receiver<String>) -> String {
+    print!("(chip8) ");
+    io::stdout().flush().ok();
+
+    commands.recv().unwrap_or_else(|_| process::exit(0))
+}
+
+// runs a ROM under an interactive debugger reading commands from stdin. never returns
+pub fn main(mut args: impl Iterator<Item = String>) -> ! {
+    let mut rom_file = None;
+    let mut quirks = Quirks::default();
+    let mut random = Chip8Random::default();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--quirks" => {
+                let preset = args.next().unwrap_or_else(|| usage_error("--quirks needs a preset name"));
+                quirks = Quirks::from_preset_name(&preset).unwrap_or_else(|| usage_error(&format!("unknown quirks preset: {preset}")));
+            },
+
+            "--quirk" => {
+                let setting = args.next().unwrap_or_else(|| usage_error("--quirk needs NAME=on|off"));
+                apply_quirk_setting(&mut quirks, &setting);
+            },
+
+            "--seed" => {
+                let seed = args.next().unwrap_or_else(|| usage_error("--seed needs a number"));
+                random.reseed(seed.parse().unwrap_or_else(|_| usage_error(&format!("invalid seed: {seed}"))));
+            },
+
+            _ if arg.starts_with("--") => usage_error(&format!("unknown argument: {arg}")),
+
+            _ if rom_file.is_none() => rom_file = Some(arg),
+
+            _ => usage_error(&format!("unexpected argument: {arg}")),
+        }
+    }
+
+    let rom_file = rom_file.unwrap_or_else(|| usage_error("debug needs a ROM file"));
+    let program = load_rom(&rom_file).unwrap_or_else(|e| {
+        eprintln!("{e}");
+        process::exit(1);
+    });
+
+    let mut c8 = chip8::Chip8::new_with_program(&program);
+    c8.quirks = quirks;
+    c8.random = random;
+
+    let mut debugger = Chip8Debugger::new(DEFAULT_INSTRUCTIONS_PER_FRAME);
+
+    // read commands on another thread, so a running program can be paused by entering a line
+    let (command_sender, commands) = mpsc::channel();
+    thread::spawn(move || {
+        for line in io::stdin().lines().map_while(Result::ok) {
+            if command_sender.send(line).is_err() {
+                break;
+            }
+        }
+    });
+
+    println!("debugging {rom_file}, type help for a list of commands");
+    println!("{}", chip8::debugger::disassemble_around(&c8, *c8.registers.get_pc_register() as usize, 0, 4));
+
+    let mut last_command = String::new();
+
+    loop {
+        // an empty line repeats the last command, so holding enter keeps stepping
+        let mut command = prompt(&commands);
+        if command.trim().is_empty() {
+            command = last_command.clone();
+        }
+        if command.trim().is_empty() {
+            continue;
+        }
+        last_command = command.clone();
+
+        match debugger.run_command(&mut c8, &command) {
+            CommandOutcome::Output(output) => println!("{output}"),
+
+            CommandOutcome::Quit => process::exit(0),
+
+            CommandOutcome::Continue => {
+                println!("running, press enter to pause");
+
+                // run at normal speed, showing the display, until something stops it or a line is entered
+                let reason = loop {
+                    if commands.try_recv().is_ok() {
+                        break None;
+                    }
+
+                    if let Some(reason) = debugger.resume(&mut c8, DEFAULT_INSTRUCTIONS_PER_FRAME) {
+                        break Some(reason);
+                    }
+
+                    print!("\x1B[2J\x1B[1;1H");
+                    c8.output.print_display();
+
+                    thread::sleep(time::Duration::from_millis(33));
+                };
+
+                let reason = reason.unwrap_or(StopReason::Paused);
+                println!("{}", debugger.describe_stop(&c8, &reason));
+            },
+        }
+    }
+}
@@ -0,0 +1,74 @@
+use chip8_rs::chip8;
+use chip8::gdb::{Chip8GdbStub, GdbState};
+use chip8::scheduler::DEFAULT_INSTRUCTIONS_PER_FRAME;
+use std::io::{self, Read};
+use std::net::{TcpListener, TcpStream};
+use std::process;
+use std::thread;
+use std::time;
+
+// returns true if the client sent an interrupt (0x03) without blocking
+fn interrupt_pending(stream: &mut TcpStream) -> io::Result<bool> {
+    let mut byte = [0];
+
+    stream.set_nonblocking(true)?;
+    let result = stream.read(&mut byte);
+    stream.set_nonblocking(false)?;
+
+    match result {
+        Ok(1) => Ok(byte[0] == 0x03),
+        Ok(_) => Err(io::Error::from(io::ErrorKind::UnexpectedEof)),
+        Err(e) if e.kind() == io::ErrorKind::WouldBlock => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+// waits for gdb to connect on a localhost port, then lets it control the machine, showing the display while it runs.
+// never returns
+pub fn main(mut c8: chip8::Chip8, port: u16) -> ! {
+    let listener = TcpListener::bind(("127.0.0.1", port)).unwrap_or_else(|e| {
+        eprintln!("could not listen on port {port}: {e}");
+        process::exit(1);
+    });
+
+    println!("waiting for gdb on 127.0.0.1:{port} (target remote :{port})");
+    let (stream, _) = listener.accept().unwrap_or_else(|e| {
+        eprintln!("could not accept a connection: {e}");
+        process::exit(1);
+    });
+    stream.set_nodelay(true).ok();
+
+    let mut stub = Chip8GdbStub::new(stream, DEFAULT_INSTRUCTIONS_PER_FRAME);
+
+    let result = (|| -> io::Result<()> {
+        loop {
+            if stub.serve(&mut c8)? == GdbState::Detached {
+                return Ok(());
+            }
+
+            // run at normal speed until something stops execution or gdb interrupts
+            loop {
+                if interrupt_pending(stub.get_connection_mut())? {
+                    stub.interrupt()?;
+                    break;
+                }
+
+                if stub.run(&mut c8, DEFAULT_INSTRUCTIONS_PER_FRAME)? {
+                    break;
+                }
+
+                print!("\x1B[2J\x1B[1;1H");
+                c8.output.print_display();
+
+                thread::sleep(time::Duration::from_millis(33));
+            }
+        }
+    })();
+
+    if let Err(e) = result {
+        eprintln!("gdb connection failed: {e}");
+        process::exit(1);
+    }
+
+    process::exit(0);
+}
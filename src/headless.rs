@@ -0,0 +1,439 @@
+use chip8_rs::chip8;
+use chip8::quirks::Quirks;
+use chip8::coverage::{Chip8Coverage, EXECUTED, READ, WRITTEN};
+use chip8::cpu::InstructionClass;
+use chip8::disasm::{Disassembly, DisasmSyntax, PROGRAM_START};
+use chip8::profile::Chip8Profiler;
+use chip8::random::Chip8Random;
+use chip8::scheduler::DEFAULT_INSTRUCTIONS_PER_FRAME;
+use chip8::synth::{self, Chip8Synth, Waveform};
+use chip8::timing::TimingModel;
+use chip8::trace::{Chip8Tracer, TraceFormat};
+use std::fs;
+use std::process;
+use std::sync::{Arc, Mutex};
+
+use crate::{apply_quirk_setting, load_rom, usage_error};
+
+// a key held down for a range of frames
+struct KeyPress {
+    key: usize,
+    start_frame: u64,
+    frames: u64,
+}
+
+impl KeyPress {
+    // parses KEY@FRAME or KEY@FRAME+FRAMES (the key in hex, the key is held for one frame if no length is given)
+    fn parse(press: &str) -> Option<KeyPress> {
+        let (key, timing) = press.split_once('@')?;
+        let (start_frame, frames) = timing.split_once('+').unwrap_or((timing, "1"));
+
+        Some(KeyPress {
+            key: usize::from_str_radix(key, 16).ok().filter(|&key| key < 16)?,
+            start_frame: start_frame.parse().ok()?,
+            frames: frames.parse().ok()?,
+        })
+    }
+
+    // returns true if the key is held down during a frame
+    fn is_held(&self, frame: u64) -> bool {
+        frame >= self.start_frame && frame - self.start_frame < self.frames
+    }
+}
+
+// turns the display into text, one line per row and one digit per pixel (its color, see Chip8Output::get_pixel_color)
+fn display_to_text(c8: &chip8::Chip8) -> String {
+    c8.output.get_display_as_ints()
+        .iter()
+        .map(|row| row.iter().map(|color| char::from(b'0' + color)).collect::<String>() + "\n")
+        .collect()
+}
+
+// what the headless subcommand was asked to do
+struct HeadlessArgs {
+    rom_file: String,
+    frame_limit: Option<u64>,
+    instruction_limit: Option<u64>,
+    presses: Vec<KeyPress>,
+    quirks: Quirks,
+    random: Chip8Random,
+    timing: TimingModel,
+    instructions_per_frame: usize,
+    out_file: Option<String>,
+    golden_file: Option<String>,
+    trace: Option<(String, Chip8Tracer)>, // the trace file and the tracer set up with the --trace-* options
+    profile_file: Option<String>, // - for stdout
+    collapsed_file: Option<String>,
+    coverage_file: Option<String>,
+    listing_file: Option<String>,
+    audio: Option<(String, Chip8Synth)>, // the WAV file and the synth set up with the audio options
+}
+
+impl HeadlessArgs {
+    // parses the arguments after `headless`, exiting with the usage if they are wrong
+    fn parse(mut args: impl Iterator<Item = String>) -> HeadlessArgs {
+        let mut rom_file = None;
+        let mut frame_limit = None;
+        let mut instruction_limit = None;
+        let mut presses = Vec::new();
+        let mut quirks = Quirks::default();
+        let mut random = Chip8Random::new(0);
+        let mut timing = TimingModel::Instructions;
+        let mut instructions_per_frame = DEFAULT_INSTRUCTIONS_PER_FRAME;
+        let mut out_file = None;
+        let mut golden_file = None;
+        let mut trace_file = None;
+        let mut profile_file = None;
+        let mut coverage_file = None;
+        let mut listing_file = None;
+        let mut collapsed_file = None;
+        let mut tracer = Chip8Tracer::new(TraceFormat::Text);
+        let mut audio_file = None;
+        let mut synth = Chip8Synth::default();
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--frames" => {
+                    let frames = args.next().unwrap_or_else(|| usage_error("--frames needs a number"));
+                    frame_limit = Some(frames.parse::<u64>().unwrap_or_else(|_| usage_error(&format!("invalid number of frames: {frames}"))));
+                },
+
+                "--instructions" => {
+                    let instructions = args.next().unwrap_or_else(|| usage_error("--instructions needs a number"));
+                    instruction_limit = Some(instructions.parse::<u64>().unwrap_or_else(|_| usage_error(&format!("invalid number of instructions: {instructions}"))));
+                },
+
+                "--press" => {
+                    let press = args.next().unwrap_or_else(|| usage_error("--press needs KEY@FRAME[+FRAMES]"));
+                    presses.push(KeyPress::parse(&press).unwrap_or_else(|| usage_error(&format!("invalid key press: {press} (expected KEY@FRAME[+FRAMES])"))));
+                },
+
+                "--quirks" => {
+                    let preset = args.next().unwrap_or_else(|| usage_error("--quirks needs a preset name"));
+                    quirks = Quirks::from_preset_name(&preset).unwrap_or_else(|| usage_error(&format!("unknown quirks preset: {preset}")));
+                },
+
+                "--quirk" => {
+                    let setting = args.next().unwrap_or_else(|| usage_error("--quirk needs NAME=on|off"));
+                    apply_quirk_setting(&mut quirks, &setting);
+                },
+
+                "--seed" => {
+                    let seed = args.next().unwrap_or_else(|| usage_error("--seed needs a number"));
+                    random.reseed(seed.parse().unwrap_or_else(|_| usage_error(&format!("invalid seed: {seed}"))));
+                },
+
+                "--timing" => {
+                    let name = args.next().unwrap_or_else(|| usage_error("--timing needs a timing model name"));
+                    timing = TimingModel::from_name(&name).unwrap_or_else(|| usage_error(&format!("unknown timing model: {name}")));
+                },
+
+                "--instructions-per-frame" => {
+                    let count = args.next().unwrap_or_else(|| usage_error("--instructions-per-frame needs a number"));
+                    instructions_per_frame = count.parse().unwrap_or_else(|_| usage_error(&format!("invalid number of instructions: {count}")));
+                },
+
+                "--out" => {
+                    out_file = Some(args.next().unwrap_or_else(|| usage_error("--out needs a file name")));
+                },
+
+                "--golden" => {
+                    golden_file = Some(args.next().unwrap_or_else(|| usage_error("--golden needs a file name")));
+                },
+
+                "--profile" => {
+                    profile_file = Some(args.next().unwrap_or_else(|| usage_error("--profile needs a file name (- for stdout)")));
+                },
+
+                "--profile-collapsed" => {
+                    collapsed_file = Some(args.next().unwrap_or_else(|| usage_error("--profile-collapsed needs a file name")));
+                },
+
+                "--coverage" => {
+                    coverage_file = Some(args.next().unwrap_or_else(|| usage_error("--coverage needs a file name")));
+                },
+
+                "--coverage-listing" => {
+                    listing_file = Some(args.next().unwrap_or_else(|| usage_error("--coverage-listing needs a file name")));
+                },
+
+                "--audio-out" => {
+                    audio_file = Some(args.next().unwrap_or_else(|| usage_error("--audio-out needs a file name")));
+                },
+
+                "--sample-rate" => {
+                    let rate = args.next().unwrap_or_else(|| usage_error("--sample-rate needs a number"));
+                    let rate = rate.parse().ok().filter(|&rate| rate > 0).unwrap_or_else(|| usage_error(&format!("invalid sample rate: {rate}")));
+                    synth.set_sample_rate(rate);
+                },
+
+                "--waveform" => {
+                    let name = args.next().unwrap_or_else(|| usage_error("--waveform needs square or sine"));
+                    synth.waveform = Waveform::from_name(&name).unwrap_or_else(|| usage_error(&format!("unknown waveform: {name}")));
+                },
+
+                "--tone" => {
+                    let frequency = args.next().unwrap_or_else(|| usage_error("--tone needs a frequency in Hz"));
+                    synth.frequency = frequency.parse().ok().filter(|&frequency| frequency > 0.0).unwrap_or_else(|| usage_error(&format!("invalid frequency: {frequency}")));
+                },
+
+                "--trace" => {
+                    trace_file = Some(args.next().unwrap_or_else(|| usage_error("--trace needs a file name")));
+                },
+
+                "--trace-format" => {
+                    let name = args.next().unwrap_or_else(|| usage_error("--trace-format needs text or json"));
+                    tracer.set_format(TraceFormat::from_name(&name).unwrap_or_else(|| usage_error(&format!("unknown trace format: {name}"))));
+                },
+
+                "--trace-range" => {
+                    let range = args.next().unwrap_or_else(|| usage_error("--trace-range needs START-END"));
+                    let parsed = range.split_once('-').and_then(|(start, end)| {
+                        Some(u16::from_str_radix(start.trim_start_matches("0x"), 16).ok()?..=u16::from_str_radix(end.trim_start_matches("0x"), 16).ok()?)
+                    });
+                    tracer.get_filter_mut().addresses.push(parsed.unwrap_or_else(|| usage_error(&format!("invalid address range: {range} (expected hex START-END)"))));
+                },
+
+                "--trace-class" => {
+                    let names = args.next().unwrap_or_else(|| usage_error("--trace-class needs class names"));
+                    for name in names.split(',') {
+                        let class = InstructionClass::from_name(name).unwrap_or_else(|| usage_error(&format!("unknown instruction class: {name}")));
+                        tracer.get_filter_mut().classes.push(class);
+                    }
+                },
+
+                "--trace-ring" => {
+                    let entries = args.next().unwrap_or_else(|| usage_error("--trace-ring needs a number"));
+                    tracer.set_ring_capacity(Some(entries.parse().unwrap_or_else(|_| usage_error(&format!("invalid number of entries: {entries}")))));
+                },
+
+                _ if arg.starts_with("--") => usage_error(&format!("unknown argument: {arg}")),
+
+                _ if rom_file.is_none() => rom_file = Some(arg),
+
+                _ => usage_error(&format!("unexpected argument: {arg}")),
+            }
+        }
+
+        // run for a second if no limit is given
+        if frame_limit.is_none() && instruction_limit.is_none() {
+            frame_limit = Some(60);
+        }
+
+        HeadlessArgs {
+            rom_file: rom_file.unwrap_or_else(|| usage_error("headless needs a ROM file")),
+            frame_limit,
+            instruction_limit,
+            presses,
+            quirks,
+            random,
+            timing,
+            instructions_per_frame,
+            out_file,
+            golden_file,
+            trace: trace_file.map(|file| (file, tracer)),
+            profile_file,
+            collapsed_file,
+            coverage_file,
+            listing_file,
+            audio: audio_file.map(|file| (file, synth)),
+        }
+    }
+}
+
+// writes an output file, exiting if it can't be written
+fn write_output(file: &str, contents: &[u8]) {
+    fs::write(file, contents).unwrap_or_else(|e| {
+        eprintln!("could not write {file}: {e}");
+        process::exit(1);
+    });
+}
+
+// starts tracing into the trace file
+fn start_trace(c8: &mut chip8::Chip8, trace_file: &str, mut tracer: Chip8Tracer) {
+    let file = fs::File::create(trace_file).unwrap_or_else(|e| {
+        eprintln!("could not create {trace_file}: {e}");
+        process::exit(1);
+    });
+
+    tracer.set_writer(Box::new(std::io::BufWriter::new(file)));
+    c8.set_tracer(Some(tracer));
+}
+
+// starts keeping every sample of the run, to be written out at the end
+fn start_audio(c8: &mut chip8::Chip8, synth: Chip8Synth) -> Arc<Mutex<Chip8Synth>> {
+    let synth = Arc::new(Mutex::new(synth));
+    c8.audio_sink = Some(Box::new(synth.clone()));
+    synth
+}
+
+// starts profiling if a profile is going to be written, naming the subroutines after the disassembly's labels
+fn start_profile(args: &HeadlessArgs, rom: &[u8]) -> Option<Chip8Profiler> {
+    (args.profile_file.is_some() || args.collapsed_file.is_some()).then(|| {
+        let mut profiler = Chip8Profiler::new();
+        profiler.set_labels(Disassembly::new(rom).labels);
+        profiler
+    })
+}
+
+// starts recording coverage if it is going to be written. coverage accumulates across runs in the coverage file
+fn start_coverage(args: &HeadlessArgs) -> Option<Chip8Coverage> {
+    (args.coverage_file.is_some() || args.listing_file.is_some()).then(|| {
+        let previous = args.coverage_file.as_ref().and_then(|file| fs::read_to_string(file).ok());
+
+        previous.map_or_else(Chip8Coverage::new, |text| Chip8Coverage::from_text(&text).unwrap_or_else(|e| {
+            eprintln!("could not read {}: {e}", args.coverage_file.as_deref().unwrap_or_default());
+            process::exit(1);
+        }))
+    })
+}
+
+// flushes the trace, if there is one
+fn finish_trace(c8: &mut chip8::Chip8) {
+    if let Some(tracer) = c8.get_tracer_mut() {
+        tracer.flush();
+    }
+}
+
+// writes the profile report and collapsed stacks
+fn finish_profile(profiler: &Chip8Profiler, args: &HeadlessArgs) {
+    let outputs = [(&args.profile_file, profiler.report()), (&args.collapsed_file, profiler.collapsed_stacks())];
+
+    for (file, contents) in outputs {
+        match file.as_deref() {
+            Some("-") => print!("{contents}"),
+            Some(file) => write_output(file, contents.as_bytes()),
+            None => {},
+        }
+    }
+}
+
+// prints a summary of the coverage of the ROM and writes the coverage file and annotated listing
+fn finish_coverage(coverage: &Chip8Coverage, args: &HeadlessArgs, rom: &[u8]) {
+    let rom_addrs = PROGRAM_START..PROGRAM_START + rom.len();
+
+    eprintln!(
+        "coverage: {} of {} ROM bytes executed, {} read, {} written",
+        coverage.count(rom_addrs.clone(), EXECUTED),
+        rom.len(),
+        coverage.count(rom_addrs.clone(), READ),
+        coverage.count(rom_addrs, WRITTEN),
+    );
+    for addr in coverage.get_self_modifying() {
+        eprintln!("warning: self-modifying code at 0x{addr:03X} (executed and written)");
+    }
+
+    if let Some(file) = &args.coverage_file {
+        write_output(file, coverage.to_text().as_bytes());
+    }
+    if let Some(file) = &args.listing_file {
+        let listing = Disassembly::new(rom).to_annotated_text(DisasmSyntax::Classic, |item| coverage.annotate(item));
+        write_output(file, listing.as_bytes());
+    }
+}
+
+// writes every sample of the run as a WAV file
+fn finish_audio(synth: &Mutex<Chip8Synth>, audio_file: &str) {
+    let mut synth = synth.lock().unwrap_or_else(|e| e.into_inner());
+    write_output(audio_file, &synth::to_wav(&synth.take_samples(), synth.get_sample_rate()));
+}
+
+// holds down the keys that are pressed during the current frame
+fn apply_presses(c8: &mut chip8::Chip8, presses: &[KeyPress]) {
+    let frame = c8.get_frame_count();
+
+    for press in presses {
+        c8.input.get_keys_status_mut()[press.key] = false;
+    }
+    for press in presses.iter().filter(|press| press.is_held(frame)) {
+        c8.input.get_keys_status_mut()[press.key] = true;
+    }
+}
+
+// runs a ROM without any output until a number of frames or instructions have been executed, then prints a hash of
+// the display, optionally writing it to a file or comparing it against a golden file. never returns
+pub fn main(args: impl Iterator<Item = String>) -> ! {
+    let mut args = HeadlessArgs::parse(args);
+    let program = load_rom(&args.rom_file).unwrap_or_else(|e| {
+        eprintln!("{e}");
+        process::exit(1);
+    });
+    let rom = fs::read(&args.rom_file).unwrap_or_default();
+
+    let mut c8 = chip8::Chip8::new_with_program(&program);
+    c8.quirks = args.quirks;
+    c8.random = args.random.clone();
+    c8.timing = args.timing;
+    c8.instructions_per_frame = args.instructions_per_frame;
+
+    if let Some((trace_file, tracer)) = args.trace.take() {
+        start_trace(&mut c8, &trace_file, tracer);
+    }
+    let audio = args.audio.take().map(|(audio_file, synth)| (audio_file, start_audio(&mut c8, synth)));
+    let mut profiler = start_profile(&args, &rom);
+    let mut coverage = start_coverage(&args);
+
+    while args.frame_limit.is_none_or(|limit| c8.get_frame_count() < limit) {
+        apply_presses(&mut c8, &args.presses);
+
+        let mut limit_reached = false;
+        let report = c8.run_frame_with(|c8| {
+            if args.instruction_limit.is_some_and(|limit| c8.get_instruction_count() >= limit) {
+                limit_reached = true;
+                return false;
+            }
+
+            if let Some(profiler) = profiler.as_mut() {
+                profiler.record(c8);
+            }
+
+            if let Some(coverage) = coverage.as_mut() {
+                coverage.record(c8);
+            }
+
+            true
+        });
+
+        match report {
+            Ok(report) if report.exited || limit_reached => break,
+            Ok(_) => {},
+            Err(e) => {
+                eprintln!("CHIP-8 halted: {e}");
+                finish_trace(&mut c8);
+                process::exit(1);
+            },
+        }
+    }
+
+    finish_trace(&mut c8);
+    if let Some(profiler) = &profiler {
+        finish_profile(profiler, &args);
+    }
+    if let Some(coverage) = &coverage {
+        finish_coverage(coverage, &args, &rom);
+    }
+    if let Some((audio_file, synth)) = &audio {
+        finish_audio(synth, audio_file);
+    }
+
+    let display = display_to_text(&c8);
+    println!("{:016x}", c8.output.get_display_hash());
+
+    if let Some(out_file) = &args.out_file {
+        write_output(out_file, display.as_bytes());
+    }
+
+    if let Some(golden_file) = &args.golden_file {
+        let golden = fs::read_to_string(golden_file).unwrap_or_else(|e| {
+            eprintln!("could not read {golden_file}: {e}");
+            process::exit(1);
+        });
+
+        if golden.trim_end() != display.trim_end() {
+            eprintln!("display does not match {golden_file} after {} frames and {} instructions", c8.get_frame_count(), c8.get_instruction_count());
+            process::exit(1);
+        }
+    }
+
+    process::exit(0);
+}
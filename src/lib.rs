@@ -2,15 +2,71 @@ extern crate console_error_panic_hook;
 extern crate wasm_log;
 use std::panic;
 use wasm_bindgen::prelude::*;
-use std::sync::Mutex;
+use std::sync::{Arc, Mutex, MutexGuard};
 use lazy_static::lazy_static;
-mod chip8;
+pub mod chip8;
+pub mod json;
 
 lazy_static! {
     static ref CHIP8_INSTANCE: Mutex<chip8::Chip8> = {
-        let c8 = chip8::Chip8::new();
+        let mut c8 = chip8::Chip8::new();
+        attach_frontend(&mut c8);
         Mutex::new(c8)
     };
+
+    static ref REWIND: Mutex<Option<chip8::rewind::Chip8Rewind>> = Mutex::new(None);
+
+    static ref MOVIE: Mutex<Movie> = Mutex::new(Movie::Idle);
+
+    static ref ROM_HASH: Mutex<u32> = Mutex::new(0);
+
+    static ref CLOCK: Mutex<chip8::scheduler::ManualClock> = Mutex::new(chip8::scheduler::ManualClock::new());
+
+    static ref KEYS: Mutex<Option<u16>> = Mutex::new(Some(0));
+
+    static ref SYNTH: Arc<Mutex<chip8::synth::Chip8Synth>> = {
+        let mut synth = chip8::synth::Chip8Synth::default();
+        synth.buffer_limit = Some(audio_buffer_limit(synth.get_sample_rate()));
+        Arc::new(Mutex::new(synth))
+    };
+}
+
+// the most samples kept for fill_audio_buffer, a tenth of a second. if the page stops taking them (e.g. before the
+// audio is started) the oldest are dropped, so the sound never lags behind the display by more than that
+fn audio_buffer_limit(sample_rate: u32) -> usize {
+    sample_rate as usize / 10
+}
+
+// what is being done with a movie
+enum Movie {
+    Idle,
+    Recording(chip8::movie::Chip8MovieRecorder),
+    Playing(chip8::movie::Chip8MoviePlayer),
+}
+
+// locks the chip8 instance, recovering it if a previous call panicked while holding the lock
+fn instance() -> MutexGuard<'static, chip8::Chip8> {
+    CHIP8_INSTANCE.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+// locks the rewind buffer (None if rewinding is disabled)
+fn rewind() -> MutexGuard<'static, Option<chip8::rewind::Chip8Rewind>> {
+    REWIND.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+// locks the movie being recorded or played
+fn movie() -> MutexGuard<'static, Movie> {
+    MOVIE.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+// locks the synth making the buzzer's samples
+fn synth() -> MutexGuard<'static, chip8::synth::Chip8Synth> {
+    SYNTH.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+// locks the keys the input source reports (None while a movie is playing)
+fn keys() -> MutexGuard<'static, Option<u16>> {
+    KEYS.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
 }
 
 #[wasm_bindgen]
@@ -18,6 +74,39 @@ extern {
     pub fn alert(s: &str);
 }
 
+#[wasm_bindgen(module = "/web/frontend.js")]
+extern {
+    fn present_display(pixels: &[u8], width: usize, height: usize);
+}
+
+// draws the display on the page's canvas
+#[derive(Debug)]
+struct WebVideo;
+
+impl chip8::frontend::VideoSink for WebVideo {
+    fn present(&mut self, c8: &chip8::Chip8) {
+        present_display(&c8.output.get_display_as_ints().concat(), c8.output.get_width(), c8.output.get_height());
+    }
+}
+
+// reads the keys last passed to update_keys_status (none while a movie is playing, the keys come from the movie then)
+#[derive(Debug)]
+struct WebInput;
+
+impl chip8::frontend::InputSource for WebInput {
+    fn poll_keys(&mut self) -> Option<u16> {
+        *keys()
+    }
+}
+
+// attaches the page's display, buzzer (the synth, whose samples the page takes with fill_audio_buffer) and keyboard to
+// the chip8 instance
+fn attach_frontend(c8: &mut chip8::Chip8) {
+    c8.video_sink = Some(Box::new(WebVideo));
+    c8.audio_sink = Some(Box::new(SYNTH.clone()));
+    c8.input_source = Some(Box::new(WebInput));
+}
+
 #[wasm_bindgen]
 pub fn init_debug() {
     panic::set_hook(Box::new(console_error_panic_hook::hook));
@@ -26,15 +115,26 @@ pub fn init_debug() {
 
 #[wasm_bindgen]
 pub fn reset_inst() {
-    let mut c8 = CHIP8_INSTANCE.lock().unwrap();
+    let mut c8 = instance();
 
     *c8 = chip8::Chip8::new();
     c8.memory.load_fonts_into_mem();
+    attach_frontend(&mut c8);
+
+    if let Some(rewind) = rewind().as_mut() {
+        rewind.clear();
+    }
+
+    *movie() = Movie::Idle;
+    *keys() = Some(0);
+
+    // the new instance starts with the buzzer off
+    synth().set_gate(false, 0.0);
 }
 
 #[wasm_bindgen]
 pub fn reset_pc() {
-    let mut c8 = CHIP8_INSTANCE.lock().unwrap();
+    let mut c8 = instance();
 
     *c8.registers.get_pc_register_mut() = 0x200;
 }
@@ -60,54 +160,357 @@ pub fn program_8_to_16(program: &[u8]) -> Vec<u16> {
 
 #[wasm_bindgen]
 pub fn load_program(program: &[u16]) {
-    let mut c8 = CHIP8_INSTANCE.lock().unwrap();
+    let mut c8 = instance();
+
+    c8.memory.load_program_into_mem(program);
+    *ROM_HASH.lock().unwrap_or_else(|poisoned| poisoned.into_inner()) = chip8::movie::rom_hash(program);
+}
+
+// what happened during run, see chip8::scheduler::FrameReport
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy)]
+pub struct FrameStatus {
+    pub frames: u32,
+    pub display_dirty: bool,
+    pub sound: bool,
+    pub waiting_for_key: bool,
+}
+
+// runs the frames for the time that passed since the last call, given the current time in milliseconds (e.g. the
+// timestamp of requestAnimationFrame). the first call only starts the clock
+#[wasm_bindgen]
+pub fn run(now_ms: f64) -> Result<FrameStatus, JsError> {
+    let mut c8 = instance();
+    let mut movie = movie();
+    let mut clock = CLOCK.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
 
-    let program = Vec::from(program);
+    clock.set_time(std::time::Duration::from_secs_f64(now_ms.max(0.0) / 1000.0));
+    let elapsed = chip8::scheduler::Chip8Clock::elapsed(&mut *clock);
+
+    let mut rewind = rewind();
+    let mut desync = None;
+    let report = c8.run_for_with_hooks(elapsed, |c8| {
+        match &mut *movie {
+            Movie::Playing(player) => {
+                if let Err(e) = player.apply(c8) {
+                    desync = Some(e);
+                    return false;
+                }
+            },
+            Movie::Recording(recorder) => {
+                recorder.record(c8);
+            },
+            Movie::Idle => {},
+        }
+        true
+    }, |c8| {
+        if let Some(rewind) = rewind.as_mut() {
+            rewind.record_frame(c8);
+        }
+    }).map_err(|e| JsError::new(&e.to_string()))?;
+
+    if let Some(e) = desync {
+        *movie = Movie::Idle;
+        return Err(JsError::new(&e.to_string()));
+    }
 
-    c8.memory.load_program_into_mem(&program);
+    Ok(FrameStatus {
+        frames: report.frames,
+        display_dirty: report.display_dirty,
+        sound: report.sound,
+        waiting_for_key: report.waiting_for_key,
+    })
 }
 
+// stops the clock, e.g. while paused or rewinding, so the time that passes in the meantime isn't caught up on
 #[wasm_bindgen]
-pub fn execute_instructions(instr_count: usize) {
-    let mut c8 = CHIP8_INSTANCE.lock().unwrap();
+pub fn pause_clock() {
+    *CLOCK.lock().unwrap_or_else(|poisoned| poisoned.into_inner()) = chip8::scheduler::ManualClock::new();
+}
+
+#[wasm_bindgen]
+pub fn set_instructions_per_frame(count: usize) {
+    let mut c8 = instance();
+
+    c8.instructions_per_frame = count;
+}
 
-    for _ in 0..instr_count {
-        c8.execute_next_instruction();
+#[wasm_bindgen]
+pub fn set_fault_policy(fault: &str, policy: &str) -> Result<(), JsError> {
+    let mut c8 = instance();
+
+    let policy = chip8::error::FaultPolicy::from_name(policy)
+        .ok_or_else(|| JsError::new(&format!("unknown fault policy: {policy}")))?;
+
+    if !c8.fault_policies.set_policy_by_name(fault, policy) {
+        return Err(JsError::new(&format!("unknown fault kind: {fault}")));
     }
+
+    Ok(())
 }
 
 #[wasm_bindgen]
 pub fn get_display_as_str() -> String {
-    let c8 = CHIP8_INSTANCE.lock().unwrap();
+    let c8 = instance();
 
     c8.output.get_display_as_str()
 }
 
 #[wasm_bindgen]
 pub fn get_display_as_ints() -> Vec<u8> {
-    let c8 = CHIP8_INSTANCE.lock().unwrap();
+    let c8 = instance();
 
     c8.output.get_display_as_ints().concat()
 }
 
+#[wasm_bindgen]
+pub fn get_display_width() -> usize {
+    let c8 = instance();
+
+    c8.output.get_width()
+}
+
+#[wasm_bindgen]
+pub fn get_display_height() -> usize {
+    let c8 = instance();
+
+    c8.output.get_height()
+}
+
+#[wasm_bindgen]
+pub fn get_audio_pattern() -> Vec<u8> {
+    let c8 = instance();
+
+    c8.audio.get_pattern().map(Vec::from).unwrap_or_default()
+}
+
+#[wasm_bindgen]
+pub fn get_audio_pitch() -> u8 {
+    let c8 = instance();
+
+    *c8.audio.get_pitch()
+}
+
+#[wasm_bindgen]
+pub fn get_audio_playback_rate() -> f64 {
+    let c8 = instance();
+
+    c8.audio.get_playback_rate()
+}
+
+// sets the sample rate of the buzzer's samples, which should be the audio context's
+#[wasm_bindgen]
+pub fn set_audio_sample_rate(sample_rate: u32) {
+    let mut synth = synth();
+
+    synth.set_sample_rate(sample_rate);
+    synth.buffer_limit = Some(audio_buffer_limit(sample_rate));
+}
+
+// sets the buzzer's tone ("square" or "sine") and its frequency in Hz
+#[wasm_bindgen]
+pub fn set_audio_tone(waveform: &str, frequency: f64) -> Result<(), JsError> {
+    let mut synth = synth();
+
+    synth.waveform = chip8::synth::Waveform::from_name(waveform)
+        .ok_or_else(|| JsError::new(&format!("unknown waveform: {waveform}")))?;
+    synth.frequency = frequency;
+
+    Ok(())
+}
+
+// gets the number of samples fill_audio_buffer has ready
+#[wasm_bindgen]
+pub fn get_audio_buffered() -> usize {
+    synth().get_buffered()
+}
+
+// moves the buzzer's samples made since the last call into a buffer (e.g. for an AudioWorklet), padding it with
+// silence if there aren't enough
+#[wasm_bindgen]
+pub fn fill_audio_buffer(buffer: &mut [f32]) {
+    synth().fill_buffer(buffer);
+}
+
+// sets the keys the input source reports from the next frame on
 #[wasm_bindgen]
 pub fn update_keys_status(keys_status: &[usize]) {
-    let mut c8 = CHIP8_INSTANCE.lock().unwrap();
+    // the keys come from the movie while one is playing
+    if let Movie::Playing(_) = &*movie() {
+        return;
+    }
+
+    let mask = keys_status
+        .iter()
+        .take(16)
+        .enumerate()
+        .fold(0, |mask, (i, &key)| if key == 1 { mask | (1 << i) } else { mask });
+
+    *keys() = Some(mask);
+}
+
+// starts recording a movie, which should be done right after the program is loaded
+#[wasm_bindgen]
+pub fn movie_start_recording() {
+    let c8 = instance();
+    let rom_hash = *ROM_HASH.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+
+    let mut recorder = chip8::movie::Chip8MovieRecorder::new(&c8, rom_hash);
+    recorder.record(&c8);
+
+    *movie() = Movie::Recording(recorder);
+}
+
+// stops recording, returning the movie
+#[wasm_bindgen]
+pub fn movie_stop_recording() -> Result<Vec<u8>, JsError> {
+    let mut movie = movie();
+
+    match std::mem::replace(&mut *movie, Movie::Idle) {
+        Movie::Recording(recorder) => Ok(recorder.finish().to_bytes()),
+        other => {
+            *movie = other;
+            Err(JsError::new("no movie is being recorded"))
+        },
+    }
+}
+
+// starts playing a movie back, which should be done right after the program it was recorded with is loaded
+#[wasm_bindgen]
+pub fn movie_play(data: &[u8]) -> Result<(), JsError> {
+    let mut c8 = instance();
 
-    let keys_status_c8 = c8.input.get_keys_status_mut();
+    let recording = chip8::movie::Chip8Movie::from_bytes(data).map_err(|e| JsError::new(&e.to_string()))?;
+    if recording.rom_hash != *ROM_HASH.lock().unwrap_or_else(|poisoned| poisoned.into_inner()) {
+        return Err(JsError::new("the movie was recorded with a different program"));
+    }
+
+    *movie() = Movie::Playing(chip8::movie::Chip8MoviePlayer::new(recording, &mut c8));
+    *keys() = None;
 
-    for (i, &key) in keys_status.iter().enumerate() {
-        let key_bool = if key == 1 { true } else { false };
+    Ok(())
+}
 
-        keys_status_c8[i] = key_bool;
+// returns true while a movie is playing and still has key changes left
+#[wasm_bindgen]
+pub fn movie_is_playing() -> bool {
+    matches!(&*movie(), Movie::Playing(player) if !player.is_finished())
+}
+
+// stops recording or playing a movie
+#[wasm_bindgen]
+pub fn movie_stop() {
+    *movie() = Movie::Idle;
+    *keys() = Some(0);
+}
+
+#[wasm_bindgen]
+pub fn set_quirks_preset(preset: &str) -> Result<(), JsError> {
+    let mut c8 = instance();
+
+    c8.quirks = chip8::quirks::Quirks::from_preset_name(preset)
+        .ok_or_else(|| JsError::new(&format!("unknown quirks preset: {preset}")))?;
+
+    Ok(())
+}
+
+#[wasm_bindgen]
+pub fn set_quirk(quirk: &str, enabled: bool) -> Result<(), JsError> {
+    let mut c8 = instance();
+
+    if !c8.quirks.set_by_name(quirk, enabled) {
+        return Err(JsError::new(&format!("unknown quirk: {quirk}")));
     }
+
+    Ok(())
+}
+
+#[wasm_bindgen]
+pub fn set_stack_depth(depth: usize) {
+    let mut c8 = instance();
+
+    c8.quirks.stack_depth = depth;
+}
+
+#[wasm_bindgen]
+pub fn set_random_seed(seed: u64) {
+    let mut c8 = instance();
+
+    c8.random.reseed(seed);
+}
+
+#[wasm_bindgen]
+pub fn get_random_seed() -> u64 {
+    let c8 = instance();
+
+    c8.random.get_seed()
+}
+
+#[wasm_bindgen]
+pub fn set_timing_model(model: &str) -> Result<(), JsError> {
+    let mut c8 = instance();
+
+    c8.timing = chip8::timing::TimingModel::from_name(model)
+        .ok_or_else(|| JsError::new(&format!("unknown timing model: {model}")))?;
+
+    Ok(())
+}
+
+#[wasm_bindgen]
+pub fn get_cycle_count() -> u64 {
+    let c8 = instance();
+
+    c8.get_cycle_count()
+}
+
+#[wasm_bindgen]
+pub fn get_call_stack() -> Vec<u16> {
+    let c8 = instance();
+
+    c8.get_call_stack()
 }
 
 #[wasm_bindgen]
-pub fn timer_tick_and_get_sound() -> bool {
-    let mut c8 = CHIP8_INSTANCE.lock().unwrap();
+pub fn save_state() -> Vec<u8> {
+    let c8 = instance();
 
-    c8.timers.timer_tick();
+    c8.save_state()
+}
+
+#[wasm_bindgen]
+pub fn load_state(state: &[u8]) -> Result<(), JsError> {
+    let mut c8 = instance();
 
-    *c8.timers.get_sound() > 0
-}
\ No newline at end of file
+    c8.load_state(state).map_err(|e| JsError::new(&e.to_string()))?;
+    c8.present();
+
+    Ok(())
+}
+
+#[wasm_bindgen]
+pub fn rewind_configure(seconds: f64, interval_frames: usize) {
+    *rewind() = if seconds > 0.0 {
+        Some(chip8::rewind::Chip8Rewind::new(seconds, interval_frames))
+    } else {
+        None
+    };
+}
+
+// shows the display, e.g. after the program is restarted
+#[wasm_bindgen]
+pub fn present() {
+    let mut c8 = instance();
+
+    c8.present();
+}
+
+// steps back to the previous snapshot and shows it, returns false if there was nothing to go back to
+#[wasm_bindgen]
+pub fn rewind_step() -> bool {
+    let mut c8 = instance();
+
+    let stepped = rewind().as_mut().is_some_and(|rewind| rewind.rewind_step(&mut c8));
+    c8.present();
+
+    stepped
+}
@@ -1,28 +1,582 @@
-mod chip8;
+use chip8_rs::chip8;
+use chip8::asm;
+use chip8::cfg::ControlFlowGraph;
+use chip8::dap::Chip8DapServer;
+use chip8::frontend::{TerminalBell, TerminalVideo};
+use chip8::render::{Palette, RenderMode, Renderer, Rgb};
+use chip8::disasm::{Disassembly, DisasmSyntax};
+use chip8::movie::{self, Chip8Movie, Chip8MoviePlayer, Chip8MovieRecorder};
+use chip8::quirks::Quirks;
+use chip8::random::Chip8Random;
+use chip8::rewind::Chip8Rewind;
+use chip8::rpl::FileRplStorage;
+use chip8::scheduler::{Chip8Clock, FrameReport, SystemClock, DEFAULT_INSTRUCTIONS_PER_FRAME, FRAME_DURATION};
+use chip8::timing::TimingModel;
+use std::env;
+use std::fs;
+use std::io;
+use std::net::TcpListener;
+use std::process;
 use std::thread;
-use std::time;
+use std::time::Duration;
+
+mod debug;
+mod gdb_server;
+mod headless;
+mod terminal;
+
+// prints an error about the command line arguments and exits
+fn usage_error(message: &str) -> ! {
+    eprintln!("{message}");
+    eprintln!("usage: chip8-rs [--quirks vip|schip|modern] [--quirk NAME=on|off]... [--stack-depth N] [--rpl-file FILE] [--load-state FILE] [--seed N] [--timing instructions|vip] [--instructions-per-frame N] [--rewind-seconds N] [--rewind-interval FRAMES] [--record FILE | --play FILE] [--key-hold MS] [--render blocks|half|quadrant|braille] [--color] [--fg RRGGBB] [--bg RRGGBB] [--gdb PORT] [ROM]");
+    eprintln!("       chip8-rs asm SOURCE [-o ROM] [--symbols FILE]");
+    eprintln!("       chip8-rs cfg ROM [--dot FILE] [--call-graph-dot FILE]");
+    eprintln!("       chip8-rs dap [--port PORT]");
+    eprintln!("       chip8-rs debug ROM [--quirks ...] [--quirk ...] [--seed N]");
+    eprintln!("       chip8-rs disasm ROM [--syntax classic|octo]");
+    eprintln!("       chip8-rs headless ROM [--frames N | --instructions N] [--press KEY@FRAME[+FRAMES]]... [--quirks ...] [--quirk ...] [--seed N] [--timing instructions|vip] [--instructions-per-frame N] [--out FILE] [--golden FILE] [--profile FILE|-] [--profile-collapsed FILE] [--coverage FILE] [--coverage-listing FILE] [--audio-out FILE [--sample-rate N] [--waveform square|sine] [--tone HZ]] [--trace FILE [--trace-format text|json] [--trace-range START-END]... [--trace-class CLASS,...] [--trace-ring N]]");
+    eprintln!("while running, the keypad is on 1234/QWER/ASDF/ZXCV. space pauses, escape or ctrl-c quits, ctrl-r resets, ctrl-s and ctrl-l save and load the machine state (the --load-state file, or ROM.state), and holding backspace rewinds");
+    process::exit(2);
+}
+
+// applies a NAME=on|off quirk setting from the command line
+fn apply_quirk_setting(quirks: &mut Quirks, setting: &str) {
+    let (name, value) = setting.split_once('=').unwrap_or_else(|| usage_error("--quirk needs NAME=on|off"));
+    let enabled = match value {
+        "on" | "true" | "1" => true,
+        "off" | "false" | "0" => false,
+        _ => usage_error(&format!("invalid quirk value: {value}")),
+    };
+
+    if !quirks.set_by_name(name, enabled) {
+        usage_error(&format!("unknown quirk: {name}"));
+    }
+}
+
+// reads a ROM file into a program
+fn load_rom(path: &str) -> Result<Vec<u16>, String> {
+    let rom = fs::read(path).map_err(|e| format!("could not read {path}: {e}"))?;
+
+    Ok(chip8_rs::program_8_to_16(&rom))
+}
+
+// loads a save state file into the chip8 instance
+fn load_state_file(c8: &mut chip8::Chip8, path: &str) -> Result<(), String> {
+    let state = fs::read(path).map_err(|e| format!("could not read {path}: {e}"))?;
+
+    c8.load_state(&state).map_err(|e| format!("could not load {path}: {e}"))
+}
+
+// writes the movie being recorded, if there is one. the movie is rewritten whenever it changes, so it is complete
+// whenever the emulator is stopped
+fn write_movie(recorder: &Option<(Chip8MovieRecorder, String)>, status: &mut String) {
+    if let Some((recorder, path)) = recorder {
+        if let Err(e) = fs::write(path, recorder.get_movie().to_bytes()) {
+            *status = format!("could not write {path}: {e}");
+        }
+    }
+}
+
+// disassembles a ROM to stdout. never returns
+fn disasm_main(mut args: impl Iterator<Item = String>) -> ! {
+    let mut rom_file = None;
+    let mut syntax = DisasmSyntax::Classic;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--syntax" => {
+                let name = args.next().unwrap_or_else(|| usage_error("--syntax needs a syntax name"));
+                syntax = DisasmSyntax::from_name(&name).unwrap_or_else(|| usage_error(&format!("unknown syntax: {name}")));
+            },
+
+            _ if arg.starts_with("--") => usage_error(&format!("unknown argument: {arg}")),
+
+            _ if rom_file.is_none() => rom_file = Some(arg),
+
+            _ => usage_error(&format!("unexpected argument: {arg}")),
+        }
+    }
+
+    let rom_file = rom_file.unwrap_or_else(|| usage_error("disasm needs a ROM file"));
+    let rom = fs::read(&rom_file).unwrap_or_else(|e| {
+        eprintln!("could not read {rom_file}: {e}");
+        process::exit(1);
+    });
+
+    print!("{}", Disassembly::new(&rom).to_text(syntax));
+    process::exit(0);
+}
+
+// prints the control flow report of a ROM, optionally writing its basic blocks and call graph as Graphviz DOT files.
+// never returns
+fn cfg_main(mut args: impl Iterator<Item = String>) -> ! {
+    let mut rom_file = None;
+    let mut dot_file = None;
+    let mut call_graph_file = None;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--dot" => dot_file = Some(args.next().unwrap_or_else(|| usage_error("--dot needs a file"))),
+            "--call-graph-dot" => call_graph_file = Some(args.next().unwrap_or_else(|| usage_error("--call-graph-dot needs a file"))),
+
+            _ if arg.starts_with("--") => usage_error(&format!("unknown argument: {arg}")),
+
+            _ if rom_file.is_none() => rom_file = Some(arg),
+
+            _ => usage_error(&format!("unexpected argument: {arg}")),
+        }
+    }
+
+    let rom_file = rom_file.unwrap_or_else(|| usage_error("cfg needs a ROM file"));
+    let rom = fs::read(&rom_file).unwrap_or_else(|e| {
+        eprintln!("could not read {rom_file}: {e}");
+        process::exit(1);
+    });
+
+    let graph = ControlFlowGraph::new(&rom);
+    for (path, text) in [(dot_file, graph.to_dot()), (call_graph_file, graph.call_graph_to_dot())] {
+        if let Some(path) = path {
+            fs::write(&path, text).unwrap_or_else(|e| {
+                eprintln!("could not write {path}: {e}");
+                process::exit(1);
+            });
+        }
+    }
+
+    print!("{}", graph.report());
+    process::exit(0);
+}
+
+// runs a Debug Adapter Protocol server over stdio, or over a localhost TCP connection with --port. never returns
+fn dap_main(mut args: impl Iterator<Item = String>) -> ! {
+    let mut port = None;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--port" => {
+                let text = args.next().unwrap_or_else(|| usage_error("--port needs a port"));
+                port = Some(text.parse::<u16>().unwrap_or_else(|_| usage_error(&format!("invalid port: {text}"))));
+            },
+
+            _ => usage_error(&format!("unexpected argument: {arg}")),
+        }
+    }
+
+    let result = match port {
+        Some(port) => {
+            let listener = TcpListener::bind(("127.0.0.1", port)).unwrap_or_else(|e| {
+                eprintln!("could not listen on port {port}: {e}");
+                process::exit(1);
+            });
+
+            eprintln!("waiting for a DAP client on 127.0.0.1:{port}");
+            listener.accept().and_then(|(stream, _)| {
+                let reader = stream.try_clone()?;
+                Chip8DapServer::new(stream, DEFAULT_INSTRUCTIONS_PER_FRAME).serve(reader)
+            })
+        },
+        None => Chip8DapServer::new(io::stdout(), DEFAULT_INSTRUCTIONS_PER_FRAME).serve(io::stdin()),
+    };
+
+    if let Err(e) = result {
+        eprintln!("DAP connection failed: {e}");
+        process::exit(1);
+    }
+
+    process::exit(0);
+}
+
+// assembles an Octo source file into a ROM (next to the source unless -o is given), optionally writing a symbol map. never returns
+fn asm_main(mut args: impl Iterator<Item = String>) -> ! {
+    let mut source_file = None;
+    let mut rom_file = None;
+    let mut symbols_file = None;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" => {
+                rom_file = Some(args.next().unwrap_or_else(|| usage_error("-o needs a file name")));
+            },
+
+            "--symbols" => {
+                symbols_file = Some(args.next().unwrap_or_else(|| usage_error("--symbols needs a file name")));
+            },
+
+            _ if arg.starts_with('-') => usage_error(&format!("unknown argument: {arg}")),
+
+            _ if source_file.is_none() => source_file = Some(arg),
+
+            _ => usage_error(&format!("unexpected argument: {arg}")),
+        }
+    }
+
+    let source_file = source_file.unwrap_or_else(|| usage_error("asm needs a source file"));
+    let rom_file = rom_file.unwrap_or_else(|| {
+        let stem = source_file.rsplit_once('.').map_or(source_file.as_str(), |(stem, _)| stem);
+        format!("{stem}.ch8")
+    });
+
+    let source = fs::read_to_string(&source_file).unwrap_or_else(|e| {
+        eprintln!("could not read {source_file}: {e}");
+        process::exit(1);
+    });
+
+    let assembled = asm::assemble(&source).unwrap_or_else(|e| {
+        eprintln!("{source_file}:{e}");
+        process::exit(1);
+    });
+
+    let mut outputs = vec![(rom_file, assembled.rom.clone())];
+    if let Some(symbols_file) = symbols_file {
+        outputs.push((symbols_file, assembled.symbol_map().into_bytes()));
+    }
+
+    for (path, contents) in outputs {
+        if let Err(e) = fs::write(&path, contents) {
+            eprintln!("could not write {path}: {e}");
+            process::exit(1);
+        }
+    }
+
+    process::exit(0);
+}
 
 fn main() {
-    let program = include_bytes!("program.ch8");
-    let mut program = Vec::from(program);
-    if program.len() % 2 == 1 { program.push(0); }
-    let program = program
-        .chunks(2)
-        .map(|x| ((x[0] as u16) << 8) + (x[1] as u16))
-        .collect::<Vec<_>>();
+    match env::args().nth(1).as_deref() {
+        Some("headless") => headless::main(env::args().skip(2)),
+        Some("disasm") => disasm_main(env::args().skip(2)),
+        Some("cfg") => cfg_main(env::args().skip(2)),
+        Some("asm") => asm_main(env::args().skip(2)),
+        Some("debug") => debug::main(env::args().skip(2)),
+        Some("dap") => dap_main(env::args().skip(2)),
+        _ => {},
+    }
+
+    // parse command line arguments
+    let mut quirks = Quirks::default();
+    let mut rpl_file = None;
+    let mut state_file = None;
+    let mut random = Chip8Random::from_entropy();
+    let mut timing = TimingModel::Instructions;
+    let mut instructions_per_frame = DEFAULT_INSTRUCTIONS_PER_FRAME;
+    let mut rewind_seconds = 10.0;
+    let mut rewind_interval = 1;
+    let mut record_file = None;
+    let mut play_file = None;
+    let mut gdb_port = None;
+    let mut key_hold = terminal::DEFAULT_KEY_HOLD;
+    let mut renderer = Renderer::default();
+    let mut foreground = None;
+    let mut background = None;
+    let mut color = false;
+    let mut rom_file = None;
+    let mut args = env::args().skip(1);
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--quirks" => {
+                let preset = args.next().unwrap_or_else(|| usage_error("--quirks needs a preset name"));
+                quirks = Quirks::from_preset_name(&preset).unwrap_or_else(|| usage_error(&format!("unknown quirks preset: {preset}")));
+            },
+
+            "--quirk" => {
+                let setting = args.next().unwrap_or_else(|| usage_error("--quirk needs NAME=on|off"));
+                apply_quirk_setting(&mut quirks, &setting);
+            },
+
+            "--stack-depth" => {
+                let depth = args.next().unwrap_or_else(|| usage_error("--stack-depth needs a number"));
+                quirks.stack_depth = depth.parse().unwrap_or_else(|_| usage_error(&format!("invalid stack depth: {depth}")));
+            },
+
+            "--rpl-file" => {
+                rpl_file = Some(args.next().unwrap_or_else(|| usage_error("--rpl-file needs a file name")));
+            },
+
+            "--load-state" => {
+                state_file = Some(args.next().unwrap_or_else(|| usage_error("--load-state needs a file name")));
+            },
+
+            "--seed" => {
+                let seed = args.next().unwrap_or_else(|| usage_error("--seed needs a number"));
+                let seed = seed.parse().unwrap_or_else(|_| usage_error(&format!("invalid seed: {seed}")));
+                random.reseed(seed);
+            },
+
+            "--timing" => {
+                let name = args.next().unwrap_or_else(|| usage_error("--timing needs a timing model name"));
+                timing = TimingModel::from_name(&name).unwrap_or_else(|| usage_error(&format!("unknown timing model: {name}")));
+            },
+
+            "--instructions-per-frame" => {
+                let count = args.next().unwrap_or_else(|| usage_error("--instructions-per-frame needs a number"));
+                instructions_per_frame = count.parse().unwrap_or_else(|_| usage_error(&format!("invalid number of instructions: {count}")));
+            },
+
+            "--rewind-seconds" => {
+                let seconds = args.next().unwrap_or_else(|| usage_error("--rewind-seconds needs a number"));
+                rewind_seconds = seconds.parse().unwrap_or_else(|_| usage_error(&format!("invalid number of seconds: {seconds}")));
+            },
+
+            "--rewind-interval" => {
+                let interval = args.next().unwrap_or_else(|| usage_error("--rewind-interval needs a number"));
+                rewind_interval = interval.parse().unwrap_or_else(|_| usage_error(&format!("invalid interval: {interval}")));
+            },
+
+            "--record" => {
+                record_file = Some(args.next().unwrap_or_else(|| usage_error("--record needs a file name")));
+            },
+
+            "--play" => {
+                play_file = Some(args.next().unwrap_or_else(|| usage_error("--play needs a file name")));
+            },
+
+            "--gdb" => {
+                let port = args.next().unwrap_or_else(|| usage_error("--gdb needs a port"));
+                gdb_port = Some(port.parse::<u16>().unwrap_or_else(|_| usage_error(&format!("invalid port: {port}"))));
+            },
+
+            "--key-hold" => {
+                let ms = args.next().unwrap_or_else(|| usage_error("--key-hold needs a number of milliseconds"));
+                key_hold = Duration::from_millis(ms.parse().unwrap_or_else(|_| usage_error(&format!("invalid key hold time: {ms}"))));
+            },
+
+            "--render" => {
+                let name = args.next().unwrap_or_else(|| usage_error("--render needs a render mode name"));
+                renderer.mode = RenderMode::from_name(&name).unwrap_or_else(|| usage_error(&format!("unknown render mode: {name}")));
+            },
+
+            "--color" => color = true,
+
+            "--fg" | "--bg" => {
+                let hex = args.next().unwrap_or_else(|| usage_error(&format!("{arg} needs a color (RRGGBB)")));
+                let rgb = Rgb::from_hex(&hex).unwrap_or_else(|| usage_error(&format!("invalid color: {hex}")));
+
+                if arg == "--fg" {
+                    foreground = Some(rgb);
+                } else {
+                    background = Some(rgb);
+                }
+            },
+
+            _ if !arg.starts_with("--") && rom_file.is_none() => rom_file = Some(arg),
+
+            _ => usage_error(&format!("unknown argument: {arg}")),
+        }
+    }
+
+    // without a ROM, the built-in demo program runs
+    let program = match &rom_file {
+        Some(rom_file) => load_rom(rom_file).unwrap_or_else(|e| {
+            eprintln!("{e}");
+            process::exit(1);
+        }),
+        None => chip8_rs::program_8_to_16(include_bytes!("program.ch8")),
+    };
 
     let mut c8 = chip8::Chip8::new_with_program(&program);
+    c8.quirks = quirks;
+    c8.random = random;
+    c8.timing = timing;
+    c8.instructions_per_frame = instructions_per_frame;
+    // picking a color turns colors on, the other one defaults to white on black
+    if color || foreground.is_some() || background.is_some() {
+        let default = Palette::default();
+        renderer.palette = Some(Palette::new(foreground.unwrap_or(default.colors[1]), background.unwrap_or(default.colors[0])));
+    }
+
+    c8.video_sink = Some(Box::new(TerminalVideo::with_renderer(renderer)));
+    c8.audio_sink = Some(Box::new(TerminalBell::new()));
+
+    if let Some(rpl_file) = rpl_file {
+        c8.rpl_storage = Box::new(FileRplStorage::new(rpl_file));
+    }
+
+    if record_file.is_some() && play_file.is_some() {
+        usage_error("--record and --play can't be used together");
+    }
+
+    if gdb_port.is_some() && (record_file.is_some() || play_file.is_some()) {
+        usage_error("movies can't be used with --gdb");
+    }
+
+    if (record_file.is_some() || play_file.is_some()) && state_file.is_some() {
+        usage_error("movies always start from power on, so they can't be used with --load-state");
+    }
+
+    // movies replay everything from power on, so play back before anything else can change the machine
+    let mut player = play_file.map(|path| {
+        let movie = fs::read(&path)
+            .map_err(|e| format!("could not read {path}: {e}"))
+            .and_then(|data| Chip8Movie::from_bytes(&data).map_err(|e| format!("could not load {path}: {e}")))
+            .unwrap_or_else(|e| {
+                eprintln!("{e}");
+                process::exit(1);
+            });
+
+        if movie.rom_hash != movie::rom_hash(&program) {
+            eprintln!("{path} was recorded with a different program");
+            process::exit(1);
+        }
+
+        Chip8MoviePlayer::new(movie, &mut c8)
+    });
 
-    println!("{c8:?}");
+    let mut recorder = record_file.map(|path| (Chip8MovieRecorder::new(&c8, movie::rom_hash(&program)), path));
+
+    if let Some(state_file) = &state_file {
+        if let Err(e) = load_state_file(&mut c8, state_file) {
+            eprintln!("{e}");
+            process::exit(1);
+        }
+    }
+
+    if let Some(port) = gdb_port {
+        gdb_server::main(c8, port);
+    }
+
+    // ctrl-s and ctrl-l use the state file the machine was started from, or one next to the ROM
+    let state_file = state_file.unwrap_or_else(|| format!("{}.state", rom_file.as_deref().unwrap_or("program.ch8")));
+    let power_on = c8.save_state();
+
+    let mut raw_mode = terminal::RawMode::enable().map_err(|e| log::warn!("could not put the terminal in raw mode: {e}")).ok();
+    let (keys, controls) = terminal::spawn_key_reader();
+
+    // keys come from the movie while it is playing
+    if player.is_none() {
+        c8.input_source = Some(Box::new(terminal::TerminalKeypad::new(keys, key_hold)));
+    }
+
+    // rewinding would make a movie go out of sync, so it's turned off while recording or playing one
+    let rewind_enabled = rewind_seconds > 0.0 && recorder.is_none() && player.is_none();
+    let mut rewind = rewind_enabled.then(|| Chip8Rewind::new(rewind_seconds, rewind_interval));
+    let mut status = String::from("space: pause, esc: quit, ctrl-r: reset, ctrl-s/ctrl-l: save/load, backspace: rewind");
+    let mut shown_status = None;
+    let mut paused = false;
+    let mut clock = SystemClock::new();
+
+    c8.present();
+    write_movie(&recorder, &mut status);
 
     loop {
-        for _ in 0..17 {
-            c8.execute_next_instruction();
+        let mut rewinding = false;
+
+        while let Ok(control) = controls.try_recv() {
+            match control {
+                terminal::Control::Quit => {
+                    if let Some(raw_mode) = raw_mode.as_mut() {
+                        raw_mode.restore();
+                    }
+                    process::exit(0);
+                },
+
+                terminal::Control::Pause => {
+                    paused = !paused;
+                    status = String::from(if paused { "paused" } else { "running" });
+                },
+
+                terminal::Control::Rewind => rewinding = true,
+
+                terminal::Control::Save => status = match fs::write(&state_file, c8.save_state()) {
+                    Ok(()) => format!("saved state to {state_file}"),
+                    Err(e) => format!("could not write {state_file}: {e}"),
+                },
+
+                // jumping to another state would make a movie go out of sync
+                terminal::Control::Reset | terminal::Control::Load if recorder.is_some() || player.is_some() => {
+                    status = String::from("can't reset or load a state while a movie is recording or playing");
+                },
+
+                terminal::Control::Reset => {
+                    c8.load_state(&power_on).unwrap_or_else(|_| unreachable!("the power on state was made by save_state"));
+                    c8.present();
+                    if let Some(rewind) = rewind.as_mut() {
+                        rewind.clear();
+                    }
+                    status = String::from("reset");
+                },
+
+                terminal::Control::Load => {
+                    status = match load_state_file(&mut c8, &state_file) {
+                        Ok(()) => format!("loaded state from {state_file}"),
+                        Err(e) => e,
+                    };
+                    c8.present();
+                },
+            }
+        }
+
+        if rewinding {
+            if let Some(rewind) = rewind.as_mut() {
+                rewind.rewind_step(&mut c8);
+                status = format!("rewinding ({} snapshots left)", rewind.get_snapshot_count());
+                c8.present();
+            }
         }
 
-        print!("\x1B[2J\x1B[1;1H");
-        c8.output.print_display();
+        // the time spent paused or rewinding isn't caught up on afterwards
+        let elapsed = clock.elapsed();
+        let report = if paused || rewinding {
+            Ok(FrameReport::default())
+        } else {
+            let mut movie_changed = false;
+            let mut desync = None;
+
+            let report = c8.run_for_with_hooks(elapsed, |c8| {
+                if let Some(player) = player.as_mut() {
+                    if let Err(e) = player.apply(c8) {
+                        desync = Some(e);
+                        return false;
+                    }
+                }
+                if let Some((recorder, _)) = recorder.as_mut() {
+                    movie_changed |= recorder.record(c8);
+                }
+                true
+            }, |c8| {
+                if let Some(rewind) = rewind.as_mut() {
+                    rewind.record_frame(c8);
+                }
+            });
+
+            if movie_changed {
+                write_movie(&recorder, &mut status);
+            }
+
+            if let Some(e) = desync {
+                if let Some(raw_mode) = raw_mode.as_mut() {
+                    raw_mode.restore();
+                }
+                eprintln!("{e}");
+                process::exit(1);
+            }
+
+            report
+        };
+
+        let report = report.unwrap_or_else(|e| {
+            if let Some(raw_mode) = raw_mode.as_mut() {
+                raw_mode.restore();
+            }
+            eprintln!("CHIP-8 halted: {e}");
+            process::exit(1);
+        });
+
+        if report.exited {
+            if let Some(raw_mode) = raw_mode.as_mut() {
+                raw_mode.restore();
+            }
+            process::exit(0);
+        }
+
+        // the status line goes under the display, so it moves when the resolution changes
+        let row = renderer.rows(c8.output.get_height()) + 1;
+        if shown_status.as_ref() != Some(&(row, status.clone())) {
+            print!("\x1B[{row};1H\x1B[2K{status}");
+            io::Write::flush(&mut io::stdout()).ok();
+            shown_status = Some((row, status.clone()));
+        }
 
-        thread::sleep(time::Duration::from_millis(33));
+        thread::sleep(FRAME_DURATION);
     }
 }
@@ -0,0 +1,170 @@
+use chip8_rs::chip8;
+use chip8::frontend::{self, InputSource};
+use std::io::{self, Read, Write};
+use std::panic;
+use std::process::{Command, Stdio};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// how long a key counts as held after the terminal last sent it. terminals only send key presses (repeated while the
+// key is held down), so a release is a key that stopped repeating
+pub const DEFAULT_KEY_HOLD: Duration = Duration::from_millis(200);
+
+// the keys that control the player rather than the CHIP-8 keypad
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Control {
+    Quit, // escape or ctrl-c
+    Pause, // space
+    Reset, // ctrl-r
+    Rewind, // backspace, held to keep going back
+    Save, // ctrl-s
+    Load, // ctrl-l
+}
+
+// runs a terminal command on the terminal stdin is connected to, returning what it printed
+fn stty(args: &[&str]) -> io::Result<String> {
+    let output = Command::new("stty").args(args).stdin(Stdio::inherit()).stderr(Stdio::null()).output()?;
+
+    if !output.status.success() {
+        return Err(io::Error::other(format!("stty {} failed (is stdin a terminal?)", args.join(" "))));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+// puts the terminal back the way it was: cooked mode, the cursor shown again and below the display
+fn restore(settings: &str) {
+    print!("\x1B[?25h\x1B[0m\r\n");
+    io::stdout().flush().ok();
+
+    if let Err(e) = stty(&[settings]) {
+        eprintln!("could not restore the terminal: {e}");
+    }
+}
+
+// puts the terminal in raw mode with the cursor hidden, so keys arrive as soon as they are pressed. the terminal is
+// restored when this is dropped, when the program panics, or with restore before exiting
+#[derive(Debug)]
+pub struct RawMode {
+    settings: Option<String>, // what stty -g printed before raw mode
+}
+
+impl RawMode {
+    pub fn enable() -> io::Result<RawMode> {
+        let settings = stty(&["-g"])?;
+        stty(&["raw", "-echo"])?;
+        print!("\x1B[?25l");
+        io::stdout().flush()?;
+
+        // a panic would otherwise leave the terminal in raw mode, mangling the message
+        let saved = settings.clone();
+        let default_hook = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            restore(&saved);
+            default_hook(info);
+        }));
+
+        Ok(RawMode { settings: Some(settings) })
+    }
+
+    // restores the terminal, e.g. before process::exit (which doesn't run destructors)
+    pub fn restore(&mut self) {
+        if let Some(settings) = self.settings.take() {
+            restore(&settings);
+        }
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        self.restore();
+    }
+}
+
+// reads keys from stdin on another thread, sending CHIP-8 keys to the keypad and everything else as controls. the
+// thread stops when stdin is closed or the controls are no longer read
+pub fn spawn_key_reader() -> (mpsc::Receiver<usize>, mpsc::Receiver<Control>) {
+    let (key_sender, keys) = mpsc::channel();
+    let (control_sender, controls) = mpsc::channel();
+
+    thread::spawn(move || {
+        let mut stdin = io::stdin().lock();
+        let mut buf = [0; 64];
+
+        while let Ok(len @ 1..) = stdin.read(&mut buf) {
+            let mut bytes = buf[..len].iter().copied().peekable();
+
+            while let Some(byte) = bytes.next() {
+                let control = match byte {
+                    // escape sequences (arrow keys etc.) arrive in one read, a lone escape is the escape key
+                    0x1B => match bytes.peek() {
+                        Some(b'[' | b'O') => {
+                            bytes.next();
+                            while bytes.next_if(|byte| !(0x40..=0x7E).contains(byte)).is_some() {}
+                            bytes.next();
+                            continue;
+                        },
+                        _ => Control::Quit,
+                    },
+
+                    0x03 => Control::Quit,
+                    b' ' => Control::Pause,
+                    0x12 => Control::Reset,
+                    0x7F | 0x08 => Control::Rewind,
+                    0x13 => Control::Save,
+                    0x0C => Control::Load,
+
+                    _ => {
+                        // nobody reads the keypad while a movie is playing
+                        if let Some(key) = frontend::keyboard_key(byte as char) {
+                            key_sender.send(key).ok();
+                        }
+                        continue;
+                    },
+                };
+
+                if control_sender.send(control).is_err() {
+                    return;
+                }
+            }
+        }
+    });
+
+    (keys, controls)
+}
+
+// the CHIP-8 keypad on a terminal keyboard: a key is pressed from when the terminal sends it until it hasn't been sent
+// for the hold time
+#[derive(Debug)]
+pub struct TerminalKeypad {
+    keys: mpsc::Receiver<usize>,
+    hold: Duration,
+    pressed_at: [Option<Instant>; 16], // when each key was last sent
+}
+
+impl TerminalKeypad {
+    pub fn new(keys: mpsc::Receiver<usize>, hold: Duration) -> TerminalKeypad {
+        TerminalKeypad { keys, hold, pressed_at: [None; 16] }
+    }
+}
+
+impl InputSource for TerminalKeypad {
+    fn poll_keys(&mut self) -> Option<u16> {
+        let now = Instant::now();
+
+        while let Ok(key) = self.keys.try_recv() {
+            self.pressed_at[key] = Some(now);
+        }
+
+        let mut mask = 0;
+        for (key, pressed_at) in self.pressed_at.iter_mut().enumerate() {
+            match pressed_at {
+                Some(at) if now - *at < self.hold => mask |= 1 << key,
+                _ => *pressed_at = None,
+            }
+        }
+
+        Some(mask)
+    }
+}
@@ -0,0 +1,133 @@
+use chip8_rs::chip8::{asm, Chip8};
+use chip8_rs::chip8::disasm::{Disassembly, DisasmSyntax};
+use chip8_rs::chip8::error::StepOutcome;
+use chip8_rs::program_8_to_16;
+
+// runs an assembled program until it exits, returning the machine
+fn run(source: &str) -> Chip8 {
+    let assembled = asm::assemble(source).unwrap_or_else(|e| panic!("{e}"));
+    let mut c8 = Chip8::new_with_program(&program_8_to_16(&assembled.rom));
+
+    for _ in 0..10_000 {
+        if c8.execute_next_instruction().unwrap() == StepOutcome::Exited {
+            return c8;
+        }
+    }
+
+    panic!("the program didn't exit");
+}
+
+// the values of v0 to vF
+fn registers(c8: &Chip8) -> Vec<u8> {
+    (0..16).map(|reg| *c8.registers.get_v_register(reg).unwrap()).collect()
+}
+
+#[test]
+fn ordering_comparisons() {
+    let c8 = run("
+        v0 := 5
+        v1 := 7
+        if v0 < v1 then v2 := 1
+        if v0 > v1 then v3 := 1
+        if v0 <= 5 then v4 := 1
+        if v0 >= 6 then v5 := 1
+        if v1 > 6 then v6 := 1
+        if v1 < 7 then v7 := 1
+        if v1 >= v1 then v8 := 1
+        if v0 > v1 begin
+            v9 := 1
+        else
+            v9 := 2
+        end
+        if v1 <= v0 begin
+            va := 1
+        end
+        loop
+            while vb < 10
+            vb += 1
+        again
+        exit
+    ");
+
+    assert_eq!(registers(&c8)[..12], [5, 7, 1, 0, 1, 0, 1, 0, 1, 2, 0, 10]);
+}
+
+#[test]
+fn comparisons_with_vf_are_rejected() {
+    let e = asm::assemble("if vf < v1 then v0 := 1").unwrap_err();
+    assert_eq!(e.line, 1);
+
+    assert!(asm::assemble("if v1 >= vf then v0 := 1").is_err());
+    assert!(asm::assemble("if vf == v1 then v0 := 1").is_ok());
+}
+
+#[test]
+fn round_trip_through_the_disassembler() {
+    let source = "
+        : main
+            hires
+            clear
+            i := sprite
+            v0 := 10
+            v1 := 0x20
+            loop
+                sprite v0 v1 8
+                v0 += 8
+                if v0 > 50 begin
+                    v1 += 8
+                    v0 := 10
+                end
+                while v1 <= 40
+                if v2 != v3 then v2 := key
+                v4 =- v5
+                v6 >>= v7
+                v8 <<= v9
+                va |= vb
+                vc &= vd
+                ve ^= v1
+                vf := random 0x7F
+                delay := v3
+                buzzer := v3
+                i += v4
+                bcd v5
+                save v3
+                load v3
+                if v0 key then draw-row
+            again
+            exit
+
+        : draw-row
+            i := hex v0
+            v3 := delay
+            if v3 >= v4 then return
+            jump0 main
+            return
+
+        : sprite
+            0xFF 0x81 0x81 0xFF
+    ";
+
+    let assembled = asm::assemble(source).unwrap_or_else(|e| panic!("{e}"));
+    let text = Disassembly::new(&assembled.rom).to_text(DisasmSyntax::Octo);
+    let reassembled = asm::assemble(&text).unwrap_or_else(|e| panic!("{e}\n{text}"));
+
+    assert_eq!(reassembled.rom, assembled.rom, "{text}");
+}
+
+#[test]
+fn bad_expressions_are_errors() {
+    for source in [":calc X { 1 << 99 }", ":calc X { 1 >> -1 }", "v0 := 1\n:calc X { 2 << 64 }"] {
+        let e = asm::assemble(source).unwrap_err();
+        assert!(e.message.contains("shift"), "{source}: {e}");
+        assert_eq!(e.line, source.lines().count(), "{source}");
+    }
+
+    assert_eq!(asm::assemble(":calc X { 1 << 4 } :byte { X >> 1 }").unwrap().rom, [8]);
+}
+
+#[test]
+fn byte_expressions_are_range_checked() {
+    assert!(asm::assemble(":byte { 300 }").is_err());
+    assert!(asm::assemble(":byte { -129 }").is_err());
+    assert_eq!(asm::assemble(":byte { 255 } :byte { -128 }").unwrap().rom, [0xFF, 0x80]);
+}
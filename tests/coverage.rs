@@ -0,0 +1,18 @@
+use chip8_rs::chip8::coverage::{Chip8Coverage, EXECUTED, READ, WRITTEN};
+
+#[test]
+fn coverage_files_round_trip() {
+    let coverage = Chip8Coverage::from_text("chip8-coverage 1\n200 x--\n201 x--\nFFFF -rw\n").unwrap();
+
+    assert_eq!(coverage.get_flags(0x200), EXECUTED);
+    assert_eq!(coverage.get_flags(0xFFFF), READ | WRITTEN);
+    assert_eq!(Chip8Coverage::from_text(&coverage.to_text()).unwrap(), coverage);
+}
+
+#[test]
+fn addresses_past_the_end_of_memory_are_rejected() {
+    for addr in ["10000", "FFFFFFFFFF", "FFFFFFFFFFFFFFFF"] {
+        let e = Chip8Coverage::from_text(&format!("chip8-coverage 1\n200 x--\n{addr} x--\n")).unwrap_err();
+        assert!(e.starts_with("line 3:"), "{addr}: {e}");
+    }
+}
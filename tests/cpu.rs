@@ -0,0 +1,41 @@
+use chip8_rs::chip8::Chip8;
+use chip8_rs::chip8::error::StepOutcome;
+
+// a machine with 64K of memory about to run an instruction in the last two bytes
+fn at_end_of_memory(opcode: u16) -> Chip8 {
+    let mut c8 = Chip8::new_with_program(&[0x1200]);
+    c8.quirks.extended_memory = true;
+
+    let [high, low] = opcode.to_be_bytes();
+    *c8.memory.get_memory_at_mut(0xFFFE).unwrap() = high;
+    *c8.memory.get_memory_at_mut(0xFFFF).unwrap() = low;
+    c8.registers.jump_to(0xFFFE);
+
+    c8
+}
+
+#[test]
+fn pc_wraps_past_the_end_of_memory() {
+    let mut c8 = at_end_of_memory(0x6012);
+
+    assert_eq!(c8.execute_next_instruction().unwrap(), StepOutcome::Executed);
+    assert_eq!(*c8.registers.get_pc_register(), 0x0000);
+}
+
+#[test]
+fn repeated_instructions_stay_at_the_end_of_memory() {
+    // waiting for a key and exiting both run the instruction again
+    for opcode in [0xF00A, 0x00FD] {
+        let mut c8 = at_end_of_memory(opcode);
+
+        c8.execute_next_instruction().ok();
+        assert_eq!(*c8.registers.get_pc_register(), 0xFFFE, "{opcode:04X}");
+    }
+
+    // drawing waits for the vertical blank with the display wait quirk
+    let mut c8 = at_end_of_memory(0xD001);
+    c8.quirks.display_wait = true;
+
+    assert_eq!(c8.execute_next_instruction().unwrap(), StepOutcome::WaitingForVBlank);
+    assert_eq!(*c8.registers.get_pc_register(), 0xFFFE);
+}
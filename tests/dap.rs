@@ -0,0 +1,308 @@
+use chip8_rs::chip8::dap::Chip8DapServer;
+use chip8_rs::json::Json;
+use std::collections::VecDeque;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use std::time::Duration;
+
+// how long to wait for the server before failing instead of hanging
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+// the program being debugged, the line numbers below refer to it
+const PROGRAM: &str = "\
+: main
+\tv0 := 1
+\tv1 := 2
+\tadd-one
+\tv2 := 3
+\texit
+
+: add-one
+\tv0 += 1
+\treturn
+";
+
+// the reading end of an in-memory pipe, ending when the writing end is dropped
+struct PipeReader {
+    receiver: Receiver<Vec<u8>>,
+    buffer: VecDeque<u8>,
+}
+
+impl Read for PipeReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.buffer.is_empty() {
+            match self.receiver.recv() {
+                Ok(data) => self.buffer.extend(data),
+                Err(_) => return Ok(0),
+            }
+        }
+
+        let len = buf.len().min(self.buffer.len());
+        for (out, byte) in buf.iter_mut().zip(self.buffer.drain(..len)) {
+            *out = byte;
+        }
+
+        Ok(len)
+    }
+}
+
+// the writing end of an in-memory pipe
+struct PipeWriter(Sender<Vec<u8>>);
+
+impl Write for PipeWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.send(buf.to_vec()).map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// a client talking to a server running on another thread
+struct Client {
+    requests: PipeWriter,
+    messages: Receiver<Vec<u8>>,
+    received: Vec<u8>,
+    seq: u64,
+    server: Option<thread::JoinHandle<io::Result<()>>>,
+}
+
+impl Client {
+    fn start() -> Client {
+        let (request_sender, request_receiver) = mpsc::channel();
+        let (message_sender, message_receiver) = mpsc::channel();
+
+        let server = thread::spawn(move || {
+            let mut server = Chip8DapServer::new(PipeWriter(message_sender), 10);
+            server.serve(PipeReader { receiver: request_receiver, buffer: VecDeque::new() })
+        });
+
+        Client {
+            requests: PipeWriter(request_sender),
+            messages: message_receiver,
+            received: Vec::new(),
+            seq: 1,
+            server: Some(server),
+        }
+    }
+
+    // sends a request, returning its sequence number
+    fn send(&mut self, command: &str, arguments: Json) -> u64 {
+        let seq = self.seq;
+        self.seq += 1;
+
+        let body = Json::object([
+            ("seq", Json::from(seq)),
+            ("type", Json::from("request")),
+            ("command", Json::from(command)),
+            ("arguments", arguments),
+        ]).to_string();
+
+        write!(self.requests, "Content-Length: {}\r\n\r\n{body}", body.len()).unwrap();
+        seq
+    }
+
+    // reads the next message the server sent
+    fn next_message(&mut self) -> Json {
+        loop {
+            if let Some(header_end) = self.received.windows(4).position(|window| window == b"\r\n\r\n") {
+                let header = std::str::from_utf8(&self.received[..header_end]).unwrap();
+                let length = header.strip_prefix("Content-Length: ").unwrap().parse::<usize>().unwrap();
+
+                let body_start = header_end + 4;
+                if self.received.len() >= body_start + length {
+                    let body = String::from_utf8(self.received[body_start..body_start + length].to_vec()).unwrap();
+                    self.received.drain(..body_start + length);
+                    return Json::parse(&body).unwrap();
+                }
+            }
+
+            let data = self.messages.recv_timeout(TIMEOUT).expect("the server didn't send anything");
+            self.received.extend(data);
+        }
+    }
+
+    // reads the response to a request, checking that it succeeded and returning its body
+    fn response(&mut self, seq: u64) -> Json {
+        let message = self.next_message();
+
+        assert_eq!(message.get("type").as_str(), Some("response"), "expected a response, got {message}");
+        assert_eq!(message.get("request_seq").as_u64(), Some(seq), "{message}");
+        assert_eq!(message.get("success").as_bool(), Some(true), "{message}");
+
+        message.get("body").clone()
+    }
+
+    // sends a request and reads its response body
+    fn request(&mut self, command: &str, arguments: Json) -> Json {
+        let seq = self.send(command, arguments);
+        self.response(seq)
+    }
+
+    // reads the next message, checking that it is an event and returning its body
+    fn event(&mut self, event: &str) -> Json {
+        let message = self.next_message();
+
+        assert_eq!(message.get("type").as_str(), Some("event"), "expected an event, got {message}");
+        assert_eq!(message.get("event").as_str(), Some(event), "{message}");
+
+        message.get("body").clone()
+    }
+
+    // gets the location of the current instruction as (address, line)
+    fn location(&mut self) -> (String, u64) {
+        let body = self.request("stackTrace", Json::object([("threadId", Json::from(1))]));
+        let frame = &body.get("stackFrames").as_array().unwrap()[0];
+
+        (frame.get("instructionPointerReference").as_str().unwrap().to_string(), frame.get("line").as_u64().unwrap())
+    }
+
+    // gets the value of a register as shown in the variables view
+    fn register(&mut self, name: &str) -> String {
+        let body = self.request("variables", Json::object([("variablesReference", Json::from(1))]));
+
+        body.get("variables")
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|variable| variable.get("name").as_str() == Some(name))
+            .and_then(|variable| variable.get("value").as_str())
+            .unwrap()
+            .to_string()
+    }
+
+    // ends the session, waiting for the server to finish
+    fn disconnect(mut self) {
+        self.request("disconnect", Json::Null);
+        self.server.take().unwrap().join().unwrap().unwrap();
+    }
+}
+
+// writes the program to a file of its own, since launch reads it from a path
+fn write_program(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("chip8-rs-{name}-{}.8o", std::process::id()));
+    fs::write(&path, PROGRAM).unwrap();
+    path
+}
+
+#[test]
+fn debug_session() {
+    let path = write_program("dap-session");
+    let path_str = path.to_str().unwrap();
+    let mut client = Client::start();
+
+    let capabilities = client.request("initialize", Json::object([("adapterID", Json::from("chip8"))]));
+    assert_eq!(capabilities.get("supportsConfigurationDoneRequest").as_bool(), Some(true));
+    assert_eq!(capabilities.get("supportsReadMemoryRequest").as_bool(), Some(true));
+
+    client.request("launch", Json::object([("program", Json::from(path_str))]));
+    client.event("initialized");
+
+    // one breakpoint on the call, and one on an empty line which can't be resolved
+    let breakpoints = client.request("setBreakpoints", Json::object([
+        ("source", Json::object([("path", Json::from(path_str))])),
+        ("breakpoints", Json::from(vec![
+            Json::object([("line", Json::from(4))]),
+            Json::object([("line", Json::from(11))]),
+        ])),
+    ]));
+    let breakpoints = breakpoints.get("breakpoints").as_array().unwrap();
+    assert_eq!(breakpoints.len(), 2);
+    assert_eq!(breakpoints[0].get("verified").as_bool(), Some(true));
+    assert_eq!(breakpoints[0].get("line").as_u64(), Some(4));
+    assert_eq!(breakpoints[1].get("verified").as_bool(), Some(false));
+    let breakpoint_id = breakpoints[0].get("id").clone();
+
+    client.request("configurationDone", Json::Null);
+    let stopped = client.event("stopped");
+    assert_eq!(stopped.get("reason").as_str(), Some("breakpoint"));
+    assert_eq!(stopped.get("hitBreakpointIds"), &Json::from(vec![breakpoint_id]));
+
+    let stack = client.request("stackTrace", Json::object([("threadId", Json::from(1))]));
+    assert_eq!(stack.get("totalFrames").as_u64(), Some(1));
+    let frame = &stack.get("stackFrames").as_array().unwrap()[0];
+    assert_eq!(frame.get("name").as_str(), Some("main+0x4"));
+    assert_eq!(frame.get("line").as_u64(), Some(4));
+    assert_eq!(frame.get("instructionPointerReference").as_str(), Some("0x204"));
+    assert_eq!(frame.get("source").get("path").as_str(), Some(path_str));
+
+    let scopes = client.request("scopes", Json::object([("frameId", Json::from(0))]));
+    let scope_names = scopes.get("scopes")
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|scope| scope.get("name").as_str().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(scope_names, ["Registers", "Timers", "Keypad"]);
+
+    assert_eq!(client.register("V0"), "0x01");
+    assert_eq!(client.register("V1"), "0x02");
+    assert_eq!(client.register("PC"), "0x204");
+
+    // v0 := 1 and v1 := 2 are 60 01 61 02
+    let memory = client.request("readMemory", Json::object([("memoryReference", Json::from("0x200")), ("count", Json::from(4))]));
+    assert_eq!(memory.get("address").as_str(), Some("0x200"));
+    assert_eq!(memory.get("data").as_str(), Some("YAFhAg=="));
+    assert_eq!(memory.get("unreadableBytes").as_u64(), Some(0));
+
+    // next runs the whole subroutine
+    client.request("next", Json::object([("threadId", Json::from(1))]));
+    assert_eq!(client.event("stopped").get("reason").as_str(), Some("step"));
+    assert_eq!(client.location(), (String::from("0x206"), 5));
+    assert_eq!(client.register("V0"), "0x02");
+
+    client.request("stepIn", Json::object([("threadId", Json::from(1))]));
+    assert_eq!(client.event("stopped").get("reason").as_str(), Some("step"));
+    assert_eq!(client.location(), (String::from("0x208"), 6));
+    assert_eq!(client.register("V2"), "0x03");
+
+    // the program runs into exit
+    let continued = client.request("continue", Json::object([("threadId", Json::from(1))]));
+    assert_eq!(continued.get("allThreadsContinued").as_bool(), Some(true));
+    assert_eq!(client.event("exited").get("exitCode").as_u64(), Some(0));
+    client.event("terminated");
+
+    client.disconnect();
+    fs::remove_file(path).ok();
+}
+
+#[test]
+fn step_into_subroutine() {
+    let path = write_program("dap-step");
+    let mut client = Client::start();
+
+    client.request("initialize", Json::object([("adapterID", Json::from("chip8"))]));
+    client.request("launch", Json::object([("program", Json::from(path.to_str().unwrap())), ("stopOnEntry", Json::from(true))]));
+    client.event("initialized");
+
+    client.request("configurationDone", Json::Null);
+    assert_eq!(client.event("stopped").get("reason").as_str(), Some("entry"));
+    assert_eq!(client.location(), (String::from("0x200"), 2));
+
+    for _ in 0..3 {
+        client.request("stepIn", Json::object([("threadId", Json::from(1))]));
+        client.event("stopped");
+    }
+
+    // inside the subroutine the call shows up as the caller's frame
+    let stack = client.request("stackTrace", Json::object([("threadId", Json::from(1))]));
+    let frames = stack.get("stackFrames").as_array().unwrap();
+    assert_eq!(frames.len(), 2);
+    assert_eq!(frames[0].get("name").as_str(), Some("add-one"));
+    assert_eq!(frames[0].get("line").as_u64(), Some(9));
+    assert_eq!(frames[1].get("name").as_str(), Some("main+0x4"));
+    assert_eq!(frames[1].get("line").as_u64(), Some(4));
+
+    // a count running past the end of memory is clamped
+    let memory = client.request("readMemory", Json::object([("memoryReference", Json::from("0xFFFE")), ("count", Json::from(u64::MAX))]));
+    assert_eq!(memory.get("address").as_str(), Some("0xFFFE"));
+    assert_eq!(memory.get("data").as_str(), Some("AAA="));
+
+    client.disconnect();
+    fs::remove_file(path).ok();
+}
@@ -0,0 +1,56 @@
+// plays the samples the emulator makes (see push_audio in frontend.js) on the audio thread
+
+// the most samples kept waiting, a tenth of a second at 48 kHz. if the emulator gets ahead of the audio clock the
+// oldest samples are dropped, so the sound doesn't drift behind the display
+const max_queued = 4800;
+
+class Chip8AudioProcessor extends AudioWorkletProcessor {
+    constructor() {
+        super();
+
+        this.chunks = []; // Float32Arrays from the emulator, oldest first
+        this.offset = 0; // how far into the first chunk has been played
+        this.queued = 0; // the samples waiting, counting from offset
+
+        this.port.onmessage = (e) => {
+            this.chunks.push(e.data);
+            this.queued += e.data.length;
+
+            while (this.queued - this.chunks[0].length + this.offset > max_queued) {
+                this.queued -= this.chunks[0].length - this.offset;
+                this.chunks.shift();
+                this.offset = 0;
+            }
+        };
+    }
+
+    // fills a block of output with the waiting samples, and silence once they run out
+    process(inputs, outputs) {
+        let output = outputs[0];
+        let channel = output[0];
+
+        for (let i = 0; i < channel.length; i++) {
+            if (this.chunks.length == 0) {
+                channel[i] = 0;
+                continue;
+            }
+
+            channel[i] = this.chunks[0][this.offset++];
+            this.queued--;
+
+            if (this.offset >= this.chunks[0].length) {
+                this.chunks.shift();
+                this.offset = 0;
+            }
+        }
+
+        // the same sound on every other channel
+        for (let c = 1; c < output.length; c++) {
+            output[c].set(channel);
+        }
+
+        return true;
+    }
+}
+
+registerProcessor("chip8-audio", Chip8AudioProcessor);
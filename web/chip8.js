@@ -1,49 +1,93 @@
 import init, * as c8 from "../pkg/chip8_rs.js";
+import { push_audio, start_audio } from "./frontend.js";
 
 let active = false;
+let program_bin = null; // the loaded program, kept so it can be restarted from power on for movies
+let rewinding = false; // true while the rewind key (backspace) is held
 
 init().then(() => {
     c8.init_debug();
-    
-    // handle instruction execution and display
-    setInterval(() => {
-        if (active) 
-            c8.execute_instructions(500 / 30);
 
-        let display_value = c8.get_display_as_ints();
+    // keep the last 10 seconds, snapshotting every frame
+    c8.rewind_configure(10, 1);
+    c8.present();
+
+    // runs the emulator from one loop: the scheduler works out how many 60 Hz frames fit in the time since the last
+    // call, the display is updated through frontend.js and the samples the frames made are sent to the audio worklet
+    function loop(timestamp) {
+        requestAnimationFrame(loop);
+
+        if (active && rewinding) {
+            c8.pause_clock();
+            c8.rewind_step();
+        } else if (active) {
+            try {
+                c8.run(timestamp).free();
+                push_audio(c8);
+            } catch (e) {
+                active = false;
+                alert(`CHIP-8 halted: ${e.message}`);
+            }
+        } else {
+            c8.pause_clock();
+        }
+    }
 
-        let canvas = document.getElementById("chip8-out");
-        let ctx = canvas.getContext("2d");
+    requestAnimationFrame(loop);
 
-        let rectangle_width = canvas.width / 64;
-        let rectangle_height = canvas.height / 32;
+    // the sound can only start after the user interacts with the page
+    document.body.addEventListener("click", () => start_audio(c8));
 
-        for (let y = 0; y < 32; y++) {
-            for (let x = 0; x < 64; x++) {
-            let i = x + y * 64;
-            let pixel = display_value[i];
+    // sound settings
+    let waveform_select = document.getElementById("waveform_select");
+    let tone_input = document.getElementById("tone_input");
 
-            ctx.fillStyle = pixel ? "white" : "black";
-            ctx.fillRect(rectangle_width * x, rectangle_height * y, rectangle_width, rectangle_height);
-            }
-        }
-    }, 1000 / 30);
+    function set_tone() {
+        c8.set_audio_tone(waveform_select.value, Number(tone_input.value));
+    }
+
+    waveform_select.onchange = set_tone;
+    tone_input.onchange = set_tone;
 
     // resetting program button
     document.getElementById("restart_program_btn").addEventListener("click", c8.reset_pc);
 
-    // handle timers and sound
-    let audio = document.getElementById("beep_audio");
+    // save state slots, kept in localStorage as base64
+    let state_slot_select = document.getElementById("state_slot_select");
 
-    setInterval(() => {
-        let sound_active = c8.timer_tick_and_get_sound();
+    document.getElementById("save_state_btn").addEventListener("click", () => {
+        let state = c8.save_state();
+        let binary = "";
 
-        if (sound_active) {
-            audio.play();
-        } else {
-            audio.pause();
+        for (let i = 0; i < state.length; i++) {
+            binary += String.fromCharCode(state[i]);
         }
-    }, 1000 / 60);
+
+        localStorage.setItem(`chip8_state_${state_slot_select.value}`, btoa(binary));
+    });
+
+    document.getElementById("load_state_btn").addEventListener("click", () => {
+        let saved = localStorage.getItem(`chip8_state_${state_slot_select.value}`);
+
+        if (saved === null) {
+            alert("That slot is empty.");
+            return;
+        }
+
+        let binary = atob(saved);
+        let state = new Uint8Array(binary.length);
+
+        for (let i = 0; i < binary.length; i++) {
+            state[i] = binary.charCodeAt(i);
+        }
+
+        try {
+            c8.load_state(state);
+            active = true;
+        } catch (e) {
+            alert(`Could not load state: ${e.message}`);
+        }
+    });
 
     // handle key inputs
     const keys = "x123qweasdzc4rfv";
@@ -61,12 +105,25 @@ init().then(() => {
     }
 
     document.body.addEventListener("keydown", (e) => {
+        start_audio(c8);
+
+        if (e.key == "Backspace") {
+            rewinding = true;
+            e.preventDefault();
+            return;
+        }
+
         keys_pressed[e.key] = 1;
 
         c8.update_keys_status(create_keys_status());
     });
 
     document.body.addEventListener("keyup", (e) => {
+        if (e.key == "Backspace") {
+            rewinding = false;
+            return;
+        }
+
         keys_pressed[e.key] = 0;
 
         c8.update_keys_status(create_keys_status());
@@ -74,6 +131,11 @@ init().then(() => {
 
 });
 
+// handle quirks selection
+let quirks_select = document.getElementById("quirks_select");
+
+quirks_select.onchange = () => c8.set_quirks_preset(quirks_select.value);
+
 // handle program uploading
 let program_file_select = document.getElementById("chip8_file_select");
 
@@ -84,10 +146,9 @@ program_file_select.onchange = () => {
         active = false;
 
         let file_contents = new Uint8Array(e.target.result);
-        let program_bin = c8.program_8_to_16(file_contents);
+        program_bin = c8.program_8_to_16(file_contents);
 
-        c8.reset_inst();
-        c8.load_program(program_bin);
+        power_on();
         
         active = true;
     }
@@ -95,4 +156,65 @@ program_file_select.onchange = () => {
     reader.readAsArrayBuffer(program_file_select.files[0]);
 }
 
+// restarts the loaded program from power on
+function power_on() {
+    c8.reset_inst();
+    c8.set_quirks_preset(quirks_select.value);
+    c8.load_program(program_bin);
+    c8.present();
+}
+
+// handle movie recording and playback. movies start from power on, so the program is restarted first
+document.getElementById("record_movie_btn").addEventListener("click", () => {
+    if (program_bin === null) {
+        alert("Select a program first.");
+        return;
+    }
+
+    power_on();
+    c8.movie_start_recording();
+    active = true;
+});
+
+document.getElementById("stop_movie_btn").addEventListener("click", () => {
+    let movie;
+
+    try {
+        movie = c8.movie_stop_recording();
+    } catch (e) {
+        c8.movie_stop();
+        return;
+    }
+
+    let link = document.createElement("a");
+    link.href = URL.createObjectURL(new Blob([movie], { type: "application/octet-stream" }));
+    link.download = "recording.c8m";
+    link.click();
+    URL.revokeObjectURL(link.href);
+});
+
+let movie_file_select = document.getElementById("movie_file_select");
+
+movie_file_select.onchange = () => {
+    let reader = new FileReader;
+
+    reader.onload = (e) => {
+        if (program_bin === null) {
+            alert("Select the program the movie was recorded with first.");
+            return;
+        }
+
+        power_on();
+
+        try {
+            c8.movie_play(new Uint8Array(e.target.result));
+            active = true;
+        } catch (e) {
+            alert(`Could not play movie: ${e.message}`);
+        }
+    }
+
+    reader.readAsArrayBuffer(movie_file_select.files[0]);
+}
+
 
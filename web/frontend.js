@@ -0,0 +1,62 @@
+// the browser side of the emulator's video and audio output. the display is drawn when the wasm bridge calls
+// present_display (see WebVideo in lib.rs), the buzzer's samples are made by the emulator and played by
+// audio-worklet.js
+
+// colors for each pixel value (XO-CHIP programs can use both bitplanes, giving 4 colors)
+const palette = ["black", "white", "#aaaaaa", "#555555"];
+
+// draws the display on the canvas
+export function present_display(pixels, width, height) {
+    let canvas = document.getElementById("chip8-out");
+    let ctx = canvas.getContext("2d");
+
+    // keep the canvas at the current resolution (64x32, or 128x64 in hi-res mode)
+    if (canvas.width != width || canvas.height != height) {
+        canvas.width = width;
+        canvas.height = height;
+    }
+
+    let rectangle_width = canvas.width / width;
+    let rectangle_height = canvas.height / height;
+
+    for (let y = 0; y < height; y++) {
+        for (let x = 0; x < width; x++) {
+            let i = x + y * width;
+
+            ctx.fillStyle = palette[pixels[i]];
+            ctx.fillRect(rectangle_width * x, rectangle_height * y, rectangle_width, rectangle_height);
+        }
+    }
+}
+
+let audio_started = false;
+let audio_node = null;
+
+// starts playing the emulator's sound, which browsers only allow once the user has interacted with the page
+export async function start_audio(c8) {
+    if (audio_started) {
+        return;
+    }
+    audio_started = true;
+
+    let context = new AudioContext();
+    await context.audioWorklet.addModule(new URL("./audio-worklet.js", import.meta.url));
+
+    c8.set_audio_sample_rate(context.sampleRate);
+
+    audio_node = new AudioWorkletNode(context, "chip8-audio");
+    audio_node.connect(context.destination);
+}
+
+// sends the samples the emulator made since the last call to the audio worklet
+export function push_audio(c8) {
+    let count = c8.get_audio_buffered();
+
+    if (audio_node === null || count == 0) {
+        return;
+    }
+
+    let samples = new Float32Array(count);
+    c8.fill_audio_buffer(samples);
+    audio_node.port.postMessage(samples, [samples.buffer]);
+}